@@ -1,10 +1,21 @@
 use wasm_bindgen::prelude::*;
 use js_sys::Map;
+use js_sys::Array;
+use js_sys::Function;
 use std::rc::Rc;
 use std::cell::RefCell;
 
 use crate::webgl2_frame::*;
 use crate::webgl2_frame::animations::*;
+use crate::webgl2_frame::scene::ObjectTarget;
+use crate::webgl2_frame::picking::*;
+use crate::webgl2_frame::selection::*;
+use crate::webgl2_frame::render_modes::RenderMode;
+use crate::webgl2_frame::lights::*;
+use crate::webgl2_frame::post_process::*;
+use crate::webgl2_frame::capture::CaptureOptions;
+use crate::webgl2_wavefront_object::material::AlphaMode;
+use crate::webgl2_wavefront_object::material::MaterialMap;
 use crate::logger::*;
 
 #[wasm_bindgen]
@@ -29,6 +40,7 @@ impl EngineWebGl2
 			)
 		);
 		initialize_animation(&frame.clone());
+		register_pick_listeners(&frame);
 		rust_info(&"...webgl initialisation complete.");
 
 		return Ok
@@ -45,4 +57,564 @@ impl EngineWebGl2
 		let _ = self.frame.borrow_mut().update_scene(resources);	
 		return Ok(());
 	}
-}
\ No newline at end of file
+
+	/*
+	*
+	*	Set a custom shader uniform. The value is validated against the uniform's declared type in the
+	*	linked program and fed to the GPU on every frame until removed. A sampler2D takes the name of a
+	*	supplied texture, bound on a unit from 15 up, so only MAX_TEXTURE_IMAGE_UNITS - 15 can be set at once
+	*
+	*/
+	pub fn set_uniform(&self, name: &str, value: JsValue) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_uniform(name, &value)?;
+		return Ok(());
+	}
+
+	pub fn remove_uniform(&self, name: &str) -> bool
+	{
+		return self.frame.borrow_mut().remove_uniform(name);
+	}
+
+	/*
+	*
+	*	List the active attributes and uniforms of the linked program, with their GLSL types and locations
+	*
+	*/
+	pub fn get_shader_inputs(&self) -> JsValue
+	{
+		return self.frame.borrow().get_shader_inputs();
+	}
+
+	/*
+	*
+	*	Scene graph node transforms. Nodes are named after the OBJ objects and groups they were built from
+	*
+	*/
+	pub fn set_node_position(&self, name: &str, x: f32, y: f32, z: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_node_transform(name, Some([x, y, z]), None, None)?;
+		return Ok(());
+	}
+
+	pub fn set_node_rotation(&self, name: &str, x_degrees: f32, y_degrees: f32, z_degrees: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_node_transform(name, None, Some([x_degrees, y_degrees, z_degrees]), None)?;
+		return Ok(());
+	}
+
+	pub fn set_node_scale(&self, name: &str, x: f32, y: f32, z: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_node_transform(name, None, None, Some([x, y, z]))?;
+		return Ok(());
+	}
+
+	pub fn get_node_names(&self) -> Array
+	{
+		return self.frame.borrow().get_node_names().into_iter().map(|name| JsValue::from_str(&name)).collect();
+	}
+
+	/*
+	*
+	*	Incremental scene changes. Objects are referred to either by the numeric id returned when they were
+	*	added or by a scene node name, which covers every object on that node or below it. A name several
+	*	nodes share is refused, and those nodes are given by their path instead, as in "car/wheel"
+	*
+	*/
+	pub fn add_object(&self, obj_text: &str) -> Result<Array, JsValue>
+	{
+		let ids = self.frame.borrow_mut().add_object(obj_text)?;
+		return Ok(ids.into_iter().map(JsValue::from).collect());
+	}
+
+	pub fn remove_object(&self, target: JsValue) -> Result<usize, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		return Ok(self.frame.borrow_mut().remove_object(&target)?);
+	}
+
+	pub fn replace_geometry(&self, target: JsValue, obj_text: &str) -> Result<Array, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		let ids = self.frame.borrow_mut().replace_geometry(&target, obj_text)?;
+		return Ok(ids.into_iter().map(JsValue::from).collect());
+	}
+
+	pub fn set_visible(&self, target: JsValue, visible: bool) -> Result<usize, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		return Ok(self.frame.borrow_mut().set_visible(&target, visible)?);
+	}
+
+	pub fn list_objects(&self) -> Array
+	{
+		return self.frame.borrow().list_objects();
+	}
+
+	/*
+	*
+	*	Pick the surface under a point given in client coordinates. Returns null when nothing is hit, otherwise
+	*	{ id, name, node, triangle, point, normal, uv, distance } with the point and normal in world space
+	*
+	*/
+	pub fn pick(&self, x: f32, y: f32) -> JsValue
+	{
+		return match self.frame.borrow().pick(x, y)
+		{
+			Some(result) => result.to_js(),
+			None => JsValue::NULL
+		};
+	}
+
+	/*
+	*
+	*	Callbacks receiving the pick result (or null) when the canvas is clicked, or when the object under
+	*	the mouse changes. Pass undefined to remove
+	*
+	*/
+	pub fn set_on_click(&self, callback: Option<Function>)
+	{
+		self.frame.borrow_mut().set_on_click(callback);
+	}
+
+	pub fn set_on_hover(&self, callback: Option<Function>)
+	{
+		self.frame.borrow_mut().set_on_hover(callback);
+	}
+
+	/*
+	*
+	*	Selection. Targets are object ids or scene node names as above. Selecting replaces the current
+	*	selection unless additive is set, and returns how many objects were selected
+	*
+	*/
+	pub fn select(&self, target: JsValue, additive: bool) -> Result<usize, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		return Ok(self.frame.borrow_mut().select(&target, additive)?);
+	}
+
+	pub fn deselect(&self, target: JsValue) -> Result<usize, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		return Ok(self.frame.borrow_mut().deselect(&target)?);
+	}
+
+	pub fn clear_selection(&self)
+	{
+		self.frame.borrow_mut().clear_selection();
+	}
+
+	pub fn get_selection(&self) -> Array
+	{
+		return self.frame.borrow().get_selection().into_iter().map(JsValue::from).collect();
+	}
+
+	/*
+	*
+	*	Highlighting of the selection: "none", "outline", "tint" or "outline_tint". The outline width is in pixels
+	*
+	*/
+	pub fn set_selection_style(&self, style: &str) -> Result<(), JsValue>
+	{
+		let style = SelectionStyle::from_str(style)?;
+		self.frame.borrow_mut().set_selection_style(style);
+		return Ok(());
+	}
+
+	pub fn set_outline(&self, r: f32, g: f32, b: f32, a: f32, width: f32)
+	{
+		self.frame.borrow_mut().set_outline([r, g, b, a], width);
+	}
+
+	pub fn set_tint(&self, r: f32, g: f32, b: f32, a: f32)
+	{
+		self.frame.borrow_mut().set_tint([r, g, b, a]);
+	}
+
+	/*
+	*
+	*	What happens to unselected objects: "off", "isolate" to ghost them or "hide_others" to hide them
+	*
+	*/
+	pub fn set_isolation(&self, mode: &str) -> Result<(), JsValue>
+	{
+		let mode = IsolationMode::from_str(mode)?;
+		self.frame.borrow_mut().set_isolation(mode);
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Render modes: "shaded", "wireframe", "shaded_wireframe", "points" or "hidden_line". The frame's mode
+	*	applies to every object without one of its own
+	*
+	*/
+	pub fn set_render_mode(&self, mode: &str) -> Result<(), JsValue>
+	{
+		let mode = RenderMode::from_str(mode)?;
+		self.frame.borrow_mut().set_render_mode(mode);
+		return Ok(());
+	}
+
+	pub fn set_object_render_mode(&self, target: JsValue, mode: &str) -> Result<usize, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		let mode = RenderMode::from_str(mode)?;
+		return Ok(self.frame.borrow_mut().set_object_render_mode(&target, Some(mode))?);
+	}
+
+	// Return the targeted objects to the frame's render mode
+	pub fn clear_object_render_mode(&self, target: JsValue) -> Result<usize, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		return Ok(self.frame.borrow_mut().set_object_render_mode(&target, None)?);
+	}
+
+	pub fn set_wireframe_style(&self, r: f32, g: f32, b: f32, a: f32, point_size: f32)
+	{
+		self.frame.borrow_mut().set_wireframe_style([r, g, b, a], point_size);
+	}
+
+	/*
+	*
+	*	How the targeted objects use their alpha: "opaque", "blend" or "mask", which discards fragments with
+	*	alpha under the cutoff. Dissolved materials and textures with transparent pixels blend by default
+	*
+	*/
+	pub fn set_alpha_mode(&self, target: JsValue, mode: &str, cutoff: f32) -> Result<usize, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		let mode = AlphaMode::from_str(mode)?;
+		return Ok(self.frame.borrow_mut().set_alpha_mode(&target, mode, cutoff)?);
+	}
+
+	/*
+	*
+	*	How the targeted objects sample a texture map ("base_color", "metallic", "roughness", "normal",
+	*	"occlusion", "emissive", "specular", "opacity", "bump" or "all"), from {wrap, wrap_s, wrap_t:
+	*	"repeat" | "clamp" | "mirror", filter, min_filter, mag_filter: "nearest" | "linear", mipmaps,
+	*	anisotropy}
+	*
+	*/
+	pub fn set_texture_sampler(&self, target: JsValue, map: &str, options: JsValue) -> Result<usize, JsValue>
+	{
+		let target = ObjectTarget::from_js(&target)?;
+		let map = if map == "all" { None } else { Some(MaterialMap::from_str(map)?) };
+		return Ok(self.frame.borrow_mut().set_texture_sampler(&target, map, &options)?);
+	}
+
+	/*
+	*
+	*	Blended objects are sorted back to front by default. Order independent transparency handles
+	*	intersecting and interleaved surfaces better, and errors on devices without float render targets
+	*
+	*/
+	pub fn set_order_independent_transparency(&self, enabled: bool) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_order_independent_transparency(enabled)?;
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Lights. Light 0 is a directional headlight attached to the camera, which can be moved or removed like
+	*	any other. Up to 8 lights are fed to the shaders through the lighting chunk
+	*
+	*/
+	pub fn add_light(&self, kind: &str) -> Result<u32, JsValue>
+	{
+		let kind = LightKind::from_str(kind)?;
+		return Ok(self.frame.borrow_mut().add_light(kind)?);
+	}
+
+	pub fn remove_light(&self, id: u32) -> bool
+	{
+		return self.frame.borrow_mut().remove_light(id);
+	}
+
+	pub fn list_lights(&self) -> Array
+	{
+		return self.frame.borrow().list_lights();
+	}
+
+	pub fn set_light_color(&self, id: u32, r: f32, g: f32, b: f32, intensity: f32) -> Result<(), JsValue>
+	{
+		let mut frame = self.frame.borrow_mut();
+		let light = frame.get_light_mut(id)?;
+		light.color = [r, g, b];
+		light.intensity = intensity;
+		return Ok(());
+	}
+
+	pub fn set_light_position(&self, id: u32, x: f32, y: f32, z: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().get_light_mut(id)?.position = [x, y, z];
+		return Ok(());
+	}
+
+	pub fn set_light_direction(&self, id: u32, x: f32, y: f32, z: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().get_light_mut(id)?.direction = [x, y, z];
+		return Ok(());
+	}
+
+	pub fn set_light_range(&self, id: u32, range: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().get_light_mut(id)?.range = range.max(0.0);
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Spot light cone half angles in degrees. Light fades out between the inner and outer angle
+	*
+	*/
+	pub fn set_light_cone(&self, id: u32, inner_degrees: f32, outer_degrees: f32) -> Result<(), JsValue>
+	{
+		let mut frame = self.frame.borrow_mut();
+		let light = frame.get_light_mut(id)?;
+		light.inner_angle = inner_degrees;
+		light.outer_angle = outer_degrees;
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Camera attached lights take their position and direction in camera space and follow the view
+	*
+	*/
+	pub fn set_light_camera_attached(&self, id: u32, attached: bool) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().get_light_mut(id)?.camera_attached = attached;
+		return Ok(());
+	}
+
+	pub fn set_ambient_color(&self, r: f32, g: f32, b: f32)
+	{
+		self.frame.borrow_mut().set_ambient_color([r, g, b]);
+	}
+
+	/*
+	*
+	*	Image based lighting from a base64 encoded equirectangular Radiance .hdr image, the same as the
+	*	"environment" resource. The lighting is precomputed on load, which can take a moment for large images
+	*
+	*/
+	pub fn set_environment(&self, hdr_base64: &str) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_environment(hdr_base64)?;
+		return Ok(());
+	}
+
+	pub fn clear_environment(&self)
+	{
+		self.frame.borrow_mut().clear_environment();
+	}
+
+	pub fn set_environment_intensity(&self, intensity: f32)
+	{
+		self.frame.borrow_mut().set_environment_intensity(intensity);
+	}
+
+	/*
+	*
+	*	What is drawn behind the scene. Backgrounds can be changed at any time and are kept across scene updates.
+	*	Colors are RGBA in [0, 1], and transparency lets the page show through the canvas
+	*
+	*/
+	pub fn set_background_transparent(&self)
+	{
+		self.frame.borrow_mut().set_background_transparent();
+	}
+
+	pub fn set_background_color(&self, r: f32, g: f32, b: f32, a: f32)
+	{
+		self.frame.borrow_mut().set_background_color([r, g, b, a]);
+	}
+
+	pub fn set_background_gradient(&self, top_r: f32, top_g: f32, top_b: f32, bottom_r: f32, bottom_g: f32, bottom_b: f32)
+	{
+		self.frame.borrow_mut().set_background_gradient([top_r, top_g, top_b, 1.0], [bottom_r, bottom_g, bottom_b, 1.0]);
+	}
+
+	/*
+	*
+	*	Six base64 encoded square images in the order +x, -x, +y, -y, +z, -z
+	*
+	*/
+	pub fn set_background_skybox(&self, faces: Array) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_background_skybox(&faces)?;
+		return Ok(());
+	}
+
+	pub fn set_background_environment(&self) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_background_environment()?;
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Shadows cast by the first directional light. Shadows are off by default. The bias is in shadow map depth
+	*	units and trades acne on lit surfaces against shadows detaching from their casters
+	*
+	*/
+	pub fn set_shadows(&self, enabled: bool) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_shadows(enabled)?;
+		return Ok(());
+	}
+
+	pub fn set_shadow_bias(&self, bias: f32)
+	{
+		self.frame.borrow_mut().set_shadow_bias(bias);
+	}
+
+	pub fn set_shadow_map_size(&self, size: u32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_shadow_map_size(size)?;
+		return Ok(());
+	}
+
+	/*
+	*
+	*	A plane under the bottom of the scene's bounding box that catches its shadows. By default only the shadows
+	*	are visible, giving the plane an opaque color makes it a floor
+	*
+	*/
+	pub fn set_ground_plane(&self, enabled: bool)
+	{
+		self.frame.borrow_mut().set_ground_plane(enabled);
+	}
+
+	pub fn set_ground_style(&self, r: f32, g: f32, b: f32, a: f32, shadow_opacity: f32)
+	{
+		self.frame.borrow_mut().set_ground_style([r, g, b, a], shadow_opacity);
+	}
+
+	/*
+	*
+	*	Post effects, run over the finished frame in the order they were added: tone_mapping, gamma, bloom,
+	*	vignette and ssao, or a custom fragment shader. Each is given an id to change, reorder or remove it by. Custom
+	*	shaders read the image so far from u_source_map at v_texcoord and can use u_time, u_resolution and
+	*	u_mouse_position along with any uniforms set through set_post_effect_uniform
+	*
+	*/
+	pub fn add_post_effect(&self, kind: &str) -> Result<u32, JsValue>
+	{
+		let kind = PostEffectKind::from_str(kind)?;
+		return Ok(self.frame.borrow_mut().add_post_effect(kind)?);
+	}
+
+	pub fn add_custom_post_effect(&self, frag_shader: &str) -> Result<u32, JsValue>
+	{
+		return Ok(self.frame.borrow_mut().add_custom_post_effect(frag_shader)?);
+	}
+
+	pub fn remove_post_effect(&self, id: u32) -> bool
+	{
+		return self.frame.borrow_mut().remove_post_effect(id);
+	}
+
+	pub fn clear_post_effects(&self)
+	{
+		self.frame.borrow_mut().clear_post_effects();
+	}
+
+	pub fn move_post_effect(&self, id: u32, position: usize) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().move_post_effect(id, position)?;
+		return Ok(());
+	}
+
+	pub fn list_post_effects(&self) -> Array
+	{
+		return self.frame.borrow().list_post_effects();
+	}
+
+	pub fn set_post_effect_enabled(&self, id: u32, enabled: bool) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().get_post_effect_mut(id)?.enabled = enabled;
+		return Ok(());
+	}
+
+	// The operator is linear, reinhard or aces. Exposure scales the frame before it is mapped
+	pub fn set_tone_mapping(&self, id: u32, operator: &str, exposure: f32) -> Result<(), JsValue>
+	{
+		let operator = ToneMapper::from_str(operator)?;
+		self.frame.borrow_mut().set_post_effect_settings(id, PostEffectKind::ToneMapping { operator, exposure })?;
+		return Ok(());
+	}
+
+	pub fn set_gamma(&self, id: u32, gamma: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_post_effect_settings(id, PostEffectKind::Gamma { gamma })?;
+		return Ok(());
+	}
+
+	// Brightness above the threshold glows, the radius widens the blur
+	pub fn set_bloom(&self, id: u32, threshold: f32, intensity: f32, radius: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_post_effect_settings(id, PostEffectKind::Bloom { threshold, intensity, radius })?;
+		return Ok(());
+	}
+
+	// Intensity is how dark the corners get, smoothness from 0 to 1 how far in from them the darkening starts
+	pub fn set_vignette(&self, id: u32, intensity: f32, smoothness: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_post_effect_settings(id, PostEffectKind::Vignette { intensity, smoothness })?;
+		return Ok(());
+	}
+
+	/*
+	*
+	*	The radius is in world units around each surface, intensity darkens the occlusion further and blur is
+	*	in pixels. Works best before tone mapping, so the darkening is mapped with the rest of the lighting
+	*
+	*/
+	pub fn set_ssao(&self, id: u32, radius: f32, intensity: f32, blur: f32) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_post_effect_settings(id, PostEffectKind::AmbientOcclusion { radius, intensity, blur })?;
+		return Ok(());
+	}
+
+	pub fn set_post_effect_uniform(&self, id: u32, name: &str, value: JsValue) -> Result<(), JsValue>
+	{
+		self.frame.borrow_mut().set_post_effect_uniform(id, name, &value)?;
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Render the view offscreen at any size and return it as PNG bytes. Options is an optional object whose
+	*	background is "scene" to keep the current one, "transparent", or an [r, g, b, a] color
+	*
+	*/
+	pub fn capture(&self, width: u32, height: u32, options: JsValue) -> Result<Vec<u8>, JsValue>
+	{
+		let options = CaptureOptions::from_js(&options)?;
+		return Ok(self.frame.borrow_mut().capture(width, height, &options)?);
+	}
+
+	/*
+	*
+	*	A full turn of the camera around the model's centre in the given number of frames, starting from the
+	*	current view. Returns an array of PNGs, or one sprite sheet PNG with the given number of columns, 0 to
+	*	lay the frames out as close to square as they fit. Options are as for capture
+	*
+	*/
+	pub fn capture_turntable(&self, width: u32, height: u32, frames: u32, options: JsValue) -> Result<Array, JsValue>
+	{
+		let options = CaptureOptions::from_js(&options)?;
+		let pngs = self.frame.borrow_mut().capture_turntable(width, height, frames, &options)?;
+		return Ok(pngs.iter().map(|png| js_sys::Uint8Array::from(png.as_slice())).collect());
+	}
+
+	pub fn capture_sprite_sheet(&self, width: u32, height: u32, frames: u32, columns: u32, options: JsValue) -> Result<Vec<u8>, JsValue>
+	{
+		let options = CaptureOptions::from_js(&options)?;
+		return Ok(self.frame.borrow_mut().capture_sprite_sheet(width, height, frames, columns, &options)?);
+	}
+}
@@ -0,0 +1,449 @@
+pub mod prefilter;
+
+use std::f32::consts::PI;
+
+use crate::matrix_helper::cross;
+use crate::matrix_helper::normalize;
+
+/*
+*
+*	An equirectangular environment image of linear RGB radiance. Row 0 is the top of the sky (+y) and the
+*	middle column looks down -z, matching the view the camera starts with
+*
+*/
+#[derive(Clone, Debug)]
+pub struct EnvironmentImage
+{
+	pub width: usize,
+	pub height: usize,
+	pub pixels: Vec<[f32; 3]>
+}
+
+impl EnvironmentImage
+{
+	pub fn new(width: usize, height: usize, pixels: Vec<[f32; 3]>) -> Result<Self, String>
+	{
+		if width == 0 || height == 0 || pixels.len() != width * height
+		{
+			return Err(format!("Environment image of {} x {} needs {} pixels, found {}", width, height, width * height, pixels.len()));
+		}
+
+		return Ok(Self { width, height, pixels });
+	}
+
+	/*
+	*
+	*	Decode a Radiance .hdr (RGBE) image
+	*
+	*/
+	pub fn decode_hdr(bytes: &[u8]) -> Result<Self, String>
+	{
+		let image = image::load_from_memory_with_format(bytes, image::ImageFormat::Hdr)
+			.map_err(|e| "Failed to decode HDR image: ".to_owned() + &e.to_string())?
+			.into_rgb32f();
+
+		let (width, height) = (image.width() as usize, image.height() as usize);
+		let pixels: Vec<[f32; 3]> = image.pixels().map(|pixel| pixel.0).collect();
+
+		return Self::new(width, height, pixels);
+	}
+
+	fn get_pixel(&self, x: usize, y: usize) -> [f32; 3]
+	{
+		return self.pixels[y * self.width + x];
+	}
+
+	/*
+	*
+	*	Bilinearly sample the radiance arriving from a direction, wrapping around horizontally
+	*
+	*/
+	pub fn sample(&self, direction: &[f32; 3]) -> [f32; 3]
+	{
+		let [u, v] = direction_to_equirect(direction);
+
+		let x = u * self.width as f32 - 0.5;
+		let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+		let x_0 = x.floor();
+		let y_0 = y.floor();
+		let x_weight = x - x_0;
+		let y_weight = y - y_0;
+
+		let column = |offset: f32| (x_0 + offset).rem_euclid(self.width as f32) as usize % self.width;
+		let row = |offset: f32| ((y_0 + offset) as usize).min(self.height - 1);
+
+		let top = lerp(&self.get_pixel(column(0.0), row(0.0)), &self.get_pixel(column(1.0), row(0.0)), x_weight);
+		let bottom = lerp(&self.get_pixel(column(0.0), row(1.0)), &self.get_pixel(column(1.0), row(1.0)), x_weight);
+
+		return lerp(&top, &bottom, y_weight);
+	}
+
+	/*
+	*
+	*	Halve the image with a 2x2 box filter. Odd edges fold into the last pixel
+	*
+	*/
+	pub fn downsample(&self) -> Self
+	{
+		let width = (self.width / 2).max(1);
+		let height = (self.height / 2).max(1);
+		let mut pixels: Vec<[f32; 3]> = Vec::with_capacity(width * height);
+
+		for y in 0..height
+		{
+			for x in 0..width
+			{
+				let mut sum = [0.0, 0.0, 0.0];
+				for (source_x, source_y) in [(x * 2, y * 2), (x * 2 + 1, y * 2), (x * 2, y * 2 + 1), (x * 2 + 1, y * 2 + 1)]
+				{
+					let pixel = self.get_pixel(source_x.min(self.width - 1), source_y.min(self.height - 1));
+					sum = add(&sum, &pixel);
+				}
+				pixels.push(scale(&sum, 0.25));
+			}
+		}
+
+		return Self { width, height, pixels };
+	}
+
+	/*
+	*
+	*	The image and successively halved copies of it, down to the first no wider than min_width
+	*
+	*/
+	pub fn mip_chain(&self, min_width: usize) -> Vec<EnvironmentImage>
+	{
+		let mut chain = vec![self.clone()];
+		while chain[chain.len() - 1].width > min_width.max(1) && chain[chain.len() - 1].height > 1
+		{
+			let next = chain[chain.len() - 1].downsample();
+			chain.push(next);
+		}
+
+		return chain;
+	}
+
+	// The average solid angle a pixel covers
+	pub fn texel_solid_angle(&self) -> f32
+	{
+		return 4.0 * PI / (self.width * self.height) as f32;
+	}
+}
+
+/*
+*
+*	A cubemap face by face in GL order (+x, -x, +y, -y, +z, -z), each size x size pixels with row 0 first
+*
+*/
+#[derive(Clone, Debug)]
+pub struct CubeMap
+{
+	pub size: usize,
+	pub faces: Vec<Vec<[f32; 3]>>
+}
+
+impl CubeMap
+{
+	/*
+	*
+	*	Fill a cubemap by evaluating a function of the direction through the centre of each pixel
+	*
+	*/
+	pub fn from_fn<F>(size: usize, f: F) -> Self
+		where F: Fn(&[f32; 3]) -> [f32; 3]
+	{
+		let faces = (0..6)
+			.map(|face| {
+				let mut pixels: Vec<[f32; 3]> = Vec::with_capacity(size * size);
+				for y in 0..size
+				{
+					for x in 0..size
+					{
+						let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+						let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+						pixels.push(f(&cube_face_direction(face, s, t)));
+					}
+				}
+				pixels
+			})
+			.collect();
+
+		return Self { size, faces };
+	}
+}
+
+/*
+*
+*	The direction through the point (s, t) of a cubemap face, both in [-1, 1], following the GL face layout
+*
+*/
+pub fn cube_face_direction(face: usize, s: f32, t: f32) -> [f32; 3]
+{
+	let direction = match face
+	{
+		0 => [1.0, -t, -s],
+		1 => [-1.0, -t, s],
+		2 => [s, 1.0, t],
+		3 => [s, -1.0, -t],
+		4 => [s, -t, 1.0],
+		_ => [-s, -t, -1.0]
+	};
+
+	return normalize(&direction);
+}
+
+/*
+*
+*	Texture coordinates of a direction in an equirectangular image. Must match the background shader
+*
+*/
+pub fn direction_to_equirect(direction: &[f32; 3]) -> [f32; 2]
+{
+	let direction = normalize(direction);
+	let u = 0.5 + direction[0].atan2(-direction[2]) / (2.0 * PI);
+	let v = direction[1].clamp(-1.0, 1.0).acos() / PI;
+
+	return [u, v];
+}
+
+pub fn equirect_to_direction(u: f32, v: f32) -> [f32; 3]
+{
+	let phi = (u - 0.5) * 2.0 * PI;
+	let theta = v * PI;
+
+	return [theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos()];
+}
+
+/*
+*
+*	The i-th of n points of the Hammersley sequence, evenly covering the unit square
+*
+*/
+pub fn hammersley(i: u32, n: u32) -> [f32; 2]
+{
+	let radical_inverse = i.reverse_bits() as f32 * (1.0 / 4_294_967_296.0);
+	return [i as f32 / n as f32, radical_inverse];
+}
+
+/*
+*
+*	A half vector drawn from the GGX distribution around the normal for the given roughness
+*
+*/
+pub fn importance_sample_ggx(xi: &[f32; 2], normal: &[f32; 3], roughness: f32) -> [f32; 3]
+{
+	let alpha = roughness * roughness;
+	let phi = 2.0 * PI * xi[0];
+	let cos_theta = ((1.0 - xi[1]) / (1.0 + (alpha * alpha - 1.0) * xi[1])).sqrt();
+	let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+	let (tangent, bitangent) = tangent_basis(normal);
+	let half = [
+		tangent[0] * phi.cos() * sin_theta + bitangent[0] * phi.sin() * sin_theta + normal[0] * cos_theta,
+		tangent[1] * phi.cos() * sin_theta + bitangent[1] * phi.sin() * sin_theta + normal[1] * cos_theta,
+		tangent[2] * phi.cos() * sin_theta + bitangent[2] * phi.sin() * sin_theta + normal[2] * cos_theta
+	];
+
+	return normalize(&half);
+}
+
+pub fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32
+{
+	let alpha = roughness * roughness;
+	let alpha_squared = alpha * alpha;
+	let denominator = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+
+	return alpha_squared / (PI * denominator * denominator);
+}
+
+fn tangent_basis(normal: &[f32; 3]) -> ([f32; 3], [f32; 3])
+{
+	let up = if normal[2].abs() < 0.999 { [0.0, 0.0, 1.0] } else { [1.0, 0.0, 0.0] };
+	let tangent = normalize(&cross(&up, normal));
+	let bitangent = cross(normal, &tangent);
+
+	return (tangent, bitangent);
+}
+
+/*
+*
+*	Convert to the bits of an IEEE half float for HALF_FLOAT textures. Values too large for a half are
+*	clamped to the largest finite one rather than becoming infinite
+*
+*/
+pub fn f32_to_f16(value: f32) -> u16
+{
+	let bits = value.to_bits();
+	let sign = ((bits >> 16) & 0x8000) as u16;
+
+	if value.is_nan()
+	{
+		return 0x7e00;
+	}
+
+	let magnitude = value.abs();
+	if magnitude >= 65504.0
+	{
+		return sign | 0x7bff;
+	}
+	// Below the smallest half subnormal
+	if magnitude < 5.96e-8
+	{
+		return sign;
+	}
+	// Subnormal halves are multiples of 2^-24
+	if magnitude < 6.103_515_6e-5
+	{
+		return sign | (magnitude * 16_777_216.0).round() as u16;
+	}
+
+	let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+	let mantissa = bits & 0x7f_ffff;
+	// Round to nearest, letting a carry out of the mantissa bump the exponent
+	let half = ((exponent as u32) << 10) + (mantissa >> 13) + ((mantissa >> 12) & 1);
+
+	return sign | (half.min(0x7bff) as u16);
+}
+
+fn add(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3]
+{
+	return [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+}
+
+fn scale(a: &[f32; 3], factor: f32) -> [f32; 3]
+{
+	return [a[0] * factor, a[1] * factor, a[2] * factor];
+}
+
+fn lerp(a: &[f32; 3], b: &[f32; 3], weight: f32) -> [f32; 3]
+{
+	return
+	[
+		a[0] + (b[0] - a[0]) * weight,
+		a[1] + (b[1] - a[1]) * weight,
+		a[2] + (b[2] - a[2]) * weight
+	];
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::matrix_helper::dot;
+
+	fn assert_close(a: f32, b: f32, tolerance: f32)
+	{
+		assert!((a - b).abs() <= tolerance, "{} is not within {} of {}", a, tolerance, b);
+	}
+
+	#[test]
+	fn equirect_round_trips_directions()
+	{
+		for direction in [[0.3, 0.5, -0.8], [-0.9, -0.1, 0.2], [0.0, 0.2, 1.0], [0.6, -0.7, -0.1]]
+		{
+			let direction = normalize(&direction);
+			let [u, v] = direction_to_equirect(&direction);
+			let round_trip = equirect_to_direction(u, v);
+			assert_close(dot(&direction, &round_trip), 1.0, 1e-5);
+		}
+	}
+
+	#[test]
+	fn equirect_centre_looks_down_negative_z()
+	{
+		let [u, v] = direction_to_equirect(&[0.0, 0.0, -1.0]);
+		assert_close(u, 0.5, 1e-6);
+		assert_close(v, 0.5, 1e-6);
+		assert_close(direction_to_equirect(&[0.0, 1.0, 0.0])[1], 0.0, 1e-6);
+	}
+
+	#[test]
+	fn cube_faces_are_centred_on_their_axes()
+	{
+		let axes = [[1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, -1.0]];
+		for (face, axis) in axes.iter().enumerate()
+		{
+			assert_close(dot(&cube_face_direction(face, 0.0, 0.0), axis), 1.0, 1e-6);
+		}
+
+		// Rows run down the side faces
+		assert!(cube_face_direction(4, 0.0, 0.9)[1] < 0.0);
+	}
+
+	#[test]
+	fn sampling_a_constant_image_returns_the_constant()
+	{
+		let image = EnvironmentImage::new(8, 4, vec![[0.5, 1.0, 2.0]; 32]).unwrap();
+		for direction in [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [-0.2, -0.9, 0.1]]
+		{
+			let sample = image.sample(&direction);
+			assert_close(sample[0], 0.5, 1e-6);
+			assert_close(sample[2], 2.0, 1e-6);
+		}
+	}
+
+	#[test]
+	fn mip_chain_keeps_the_average()
+	{
+		let pixels: Vec<[f32; 3]> = (0..128).map(|n| [n as f32, 0.0, 1.0]).collect();
+		let image = EnvironmentImage::new(16, 8, pixels).unwrap();
+		let chain = image.mip_chain(2);
+
+		assert_eq!(chain.len(), 4);
+		assert_eq!((chain[3].width, chain[3].height), (2, 1));
+		let average = chain[3].pixels.iter().map(|pixel| pixel[0]).sum::<f32>() / 2.0;
+		assert_close(average, 63.5, 1e-4);
+	}
+
+	#[test]
+	fn decodes_radiance_hdr()
+	{
+		let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+		bytes.extend_from_slice(&[128, 64, 32, 129, 128, 128, 128, 128]);
+
+		let image = EnvironmentImage::decode_hdr(&bytes).unwrap();
+		assert_eq!((image.width, image.height), (2, 1));
+		assert_close(image.pixels[0][0], 1.0, 1e-6);
+		assert_close(image.pixels[0][1], 0.5, 1e-6);
+		assert_close(image.pixels[0][2], 0.25, 1e-6);
+		assert_close(image.pixels[1][0], 0.5, 1e-6);
+	}
+
+	#[test]
+	fn rejects_images_that_are_not_hdr()
+	{
+		assert!(EnvironmentImage::decode_hdr(b"not an image").is_err());
+	}
+
+	#[test]
+	fn hammersley_points_cover_the_square()
+	{
+		assert_eq!(hammersley(0, 4), [0.0, 0.0]);
+		assert_eq!(hammersley(1, 4), [0.25, 0.5]);
+		assert_eq!(hammersley(2, 4), [0.5, 0.25]);
+	}
+
+	#[test]
+	fn ggx_samples_stay_around_the_normal()
+	{
+		let normal = normalize(&[0.2, 0.9, -0.3]);
+		for i in 0..32
+		{
+			let half = importance_sample_ggx(&hammersley(i, 32), &normal, 0.1);
+			assert!(dot(&half, &normal) > 0.9);
+		}
+	}
+
+	#[test]
+	fn converts_to_half_floats()
+	{
+		assert_eq!(f32_to_f16(0.0), 0x0000);
+		assert_eq!(f32_to_f16(1.0), 0x3c00);
+		assert_eq!(f32_to_f16(-2.0), 0xc000);
+		assert_eq!(f32_to_f16(0.5), 0x3800);
+		assert_eq!(f32_to_f16(65504.0), 0x7bff);
+		assert_eq!(f32_to_f16(1.0e6), 0x7bff);
+		assert_eq!(f32_to_f16(2.0f32.powi(-24)), 0x0001);
+		assert_eq!(f32_to_f16(1.0 + 1.0 / 1024.0), 0x3c01);
+	}
+}
@@ -0,0 +1,308 @@
+use std::f32::consts::PI;
+
+use crate::matrix_helper::dot;
+use crate::matrix_helper::normalize;
+use crate::matrix_helper::sub;
+
+use super::*;
+
+// Irradiance varies slowly, so it is integrated over a small copy of the environment
+const IRRADIANCE_SOURCE_WIDTH: usize = 64;
+
+// GGX samples per pixel of the prefiltered levels and the BRDF lookup table
+const SPECULAR_SAMPLES: u32 = 64;
+const BRDF_SAMPLES: u32 = 128;
+
+/*
+*
+*	Diffuse irradiance around each direction, already divided by pi so a surface's diffuse light is simply
+*	albedo * irradiance. A uniform environment of radiance L gives L in every direction
+*
+*/
+pub fn irradiance_cubemap(image: &EnvironmentImage, size: usize) -> CubeMap
+{
+	let source = image.mip_chain(IRRADIANCE_SOURCE_WIDTH).pop().unwrap_or_else(|| image.clone());
+
+	// Each pixel's direction, weighted by the solid angle it covers which shrinks towards the poles
+	let texel_area = (2.0 * PI / source.width as f32) * (PI / source.height as f32);
+	let mut texels: Vec<([f32; 3], [f32; 3])> = Vec::with_capacity(source.pixels.len());
+	for y in 0..source.height
+	{
+		let v = (y as f32 + 0.5) / source.height as f32;
+		let solid_angle = texel_area * (v * PI).sin();
+		for x in 0..source.width
+		{
+			let u = (x as f32 + 0.5) / source.width as f32;
+			texels.push((equirect_to_direction(u, v), scale(&source.get_pixel(x, y), solid_angle)));
+		}
+	}
+
+	return CubeMap::from_fn(size, |normal| {
+		let mut sum = [0.0, 0.0, 0.0];
+		for (direction, radiance) in &texels
+		{
+			let cos_theta = dot(normal, direction);
+			if cos_theta > 0.0
+			{
+				sum = add(&sum, &scale(radiance, cos_theta));
+			}
+		}
+		scale(&sum, 1.0 / PI)
+	});
+}
+
+/*
+*
+*	The environment blurred by the GGX lobe, one cubemap per mip level with roughness rising linearly from
+*	0 at the full size level to 1 at the 1x1 level. Uses the split sum approximation with the view along the normal
+*
+*/
+pub fn prefiltered_cubemaps(image: &EnvironmentImage, size: usize) -> Vec<CubeMap>
+{
+	// Sampling far more finely than the output only aliases, so start from a copy a few times its size
+	let sources = image.mip_chain(1);
+	let first_source = sources.iter().position(|source| source.width <= size * 4).unwrap_or(sources.len() - 1);
+	let sources = &sources[first_source..];
+
+	let levels = mip_level_count(size);
+	let mut cubemaps: Vec<CubeMap> = Vec::with_capacity(levels);
+
+	for level in 0..levels
+	{
+		let level_size = (size >> level).max(1);
+		let roughness = if levels > 1 { level as f32 / (levels - 1) as f32 } else { 0.0 };
+
+		let cubemap = if level == 0
+		{
+			CubeMap::from_fn(level_size, |direction| sample_lod(sources, direction, 0.0))
+		}
+		else
+		{
+			CubeMap::from_fn(level_size, |normal| prefilter_direction(sources, normal, roughness))
+		};
+		cubemaps.push(cubemap);
+	}
+
+	return cubemaps;
+}
+
+pub fn mip_level_count(size: usize) -> usize
+{
+	return (usize::BITS - size.max(1).leading_zeros()) as usize;
+}
+
+fn prefilter_direction(sources: &[EnvironmentImage], normal: &[f32; 3], roughness: f32) -> [f32; 3]
+{
+	let mut sum = [0.0, 0.0, 0.0];
+	let mut weight = 0.0;
+
+	for i in 0..SPECULAR_SAMPLES
+	{
+		let half = importance_sample_ggx(&hammersley(i, SPECULAR_SAMPLES), normal, roughness);
+		let n_dot_h = dot(normal, &half);
+		let light = sub(&scale(&half, 2.0 * n_dot_h), normal);
+		let n_dot_l = dot(normal, &light);
+
+		if n_dot_l > 0.0
+		{
+			// Read from a blurrier copy where samples are sparse, so bright pixels don't show up as speckles
+			let pdf = distribution_ggx(n_dot_h, roughness) / 4.0;
+			let sample_solid_angle = 1.0 / (SPECULAR_SAMPLES as f32 * pdf + 0.0001);
+			let lod = 0.5 * (sample_solid_angle / sources[0].texel_solid_angle()).log2() + 1.0;
+
+			sum = add(&sum, &scale(&sample_lod(sources, &light, lod), n_dot_l));
+			weight += n_dot_l;
+		}
+	}
+
+	if weight <= 0.0
+	{
+		return sample_lod(sources, normal, 0.0);
+	}
+
+	return scale(&sum, 1.0 / weight);
+}
+
+// Trilinear sampling across the mip chain
+fn sample_lod(sources: &[EnvironmentImage], direction: &[f32; 3], lod: f32) -> [f32; 3]
+{
+	let lod = lod.clamp(0.0, (sources.len() - 1) as f32);
+	let lower = lod.floor() as usize;
+	let upper = (lower + 1).min(sources.len() - 1);
+
+	let sample = sources[lower].sample(direction);
+	if upper == lower
+	{
+		return sample;
+	}
+
+	return lerp(&sample, &sources[upper].sample(direction), lod - lower as f32);
+}
+
+/*
+*
+*	The split sum BRDF lookup table, size x size with n.v along the rows and roughness down the columns.
+*	Each entry is the scale and bias applied to f0 for the specular environment term
+*
+*/
+pub fn brdf_lut(size: usize) -> Vec<[f32; 2]>
+{
+	let mut table: Vec<[f32; 2]> = Vec::with_capacity(size * size);
+
+	for y in 0..size
+	{
+		let roughness = (y as f32 + 0.5) / size as f32;
+		for x in 0..size
+		{
+			let n_dot_v = (x as f32 + 0.5) / size as f32;
+			table.push(integrate_brdf(n_dot_v, roughness));
+		}
+	}
+
+	return table;
+}
+
+fn integrate_brdf(n_dot_v: f32, roughness: f32) -> [f32; 2]
+{
+	let normal = [0.0, 0.0, 1.0];
+	let view = [(1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v];
+	let mut scale_term = 0.0;
+	let mut bias_term = 0.0;
+
+	for i in 0..BRDF_SAMPLES
+	{
+		let half = importance_sample_ggx(&hammersley(i, BRDF_SAMPLES), &normal, roughness);
+		let v_dot_h = dot(&view, &half);
+		let light = normalize(&sub(&scale(&half, 2.0 * v_dot_h), &view));
+
+		let n_dot_l = light[2].max(0.0);
+		let n_dot_h = half[2].max(0.0);
+		let v_dot_h = v_dot_h.max(0.0);
+
+		if n_dot_l > 0.0
+		{
+			let geometry = geometry_smith_ibl(n_dot_v, n_dot_l, roughness);
+			let visibility = geometry * v_dot_h / (n_dot_h * n_dot_v).max(0.0001);
+			let fresnel = (1.0 - v_dot_h).powi(5);
+
+			scale_term += (1.0 - fresnel) * visibility;
+			bias_term += fresnel * visibility;
+		}
+	}
+
+	return [scale_term / BRDF_SAMPLES as f32, bias_term / BRDF_SAMPLES as f32];
+}
+
+// Smith's geometry term with the k remapping used for image based lighting
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32
+{
+	let k = roughness * roughness / 2.0;
+	let view_term = n_dot_v / (n_dot_v * (1.0 - k) + k);
+	let light_term = n_dot_l / (n_dot_l * (1.0 - k) + k);
+
+	return view_term * light_term;
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn uniform_environment(radiance: [f32; 3]) -> EnvironmentImage
+	{
+		return EnvironmentImage::new(32, 16, vec![radiance; 32 * 16]).unwrap();
+	}
+
+	// Bright sky above, dark ground below
+	fn sky_environment() -> EnvironmentImage
+	{
+		let pixels: Vec<[f32; 3]> = (0..32 * 16).map(|n| if n / 32 < 8 { [1.0, 1.0, 1.0] } else { [0.0, 0.0, 0.0] }).collect();
+		return EnvironmentImage::new(32, 16, pixels).unwrap();
+	}
+
+	#[test]
+	fn uniform_environment_gives_uniform_irradiance()
+	{
+		let irradiance = irradiance_cubemap(&uniform_environment([2.0, 1.0, 0.5]), 4);
+		for face in &irradiance.faces
+		{
+			for pixel in face
+			{
+				assert!((pixel[0] - 2.0).abs() < 0.04, "irradiance {:?}", pixel);
+				assert!((pixel[2] - 0.5).abs() < 0.01, "irradiance {:?}", pixel);
+			}
+		}
+	}
+
+	#[test]
+	fn irradiance_follows_the_sky()
+	{
+		// A uniformly lit upper hemisphere gives (1 + n.y) / 2 for every normal
+		let irradiance = irradiance_cubemap(&sky_environment(), 4);
+		for (face, pixels) in irradiance.faces.iter().enumerate()
+		{
+			for (n, pixel) in pixels.iter().enumerate()
+			{
+				let s = 2.0 * ((n % 4) as f32 + 0.5) / 4.0 - 1.0;
+				let t = 2.0 * ((n / 4) as f32 + 0.5) / 4.0 - 1.0;
+				let expected = (1.0 + cube_face_direction(face, s, t)[1]) / 2.0;
+				assert!((pixel[0] - expected).abs() < 0.05, "face {} pixel {} is {:?}, expected {}", face, n, pixel, expected);
+			}
+		}
+	}
+
+	#[test]
+	fn prefiltered_levels_halve_down_to_one_pixel()
+	{
+		let cubemaps = prefiltered_cubemaps(&uniform_environment([1.0, 1.0, 1.0]), 8);
+		let sizes: Vec<usize> = cubemaps.iter().map(|cubemap| cubemap.size).collect();
+		assert_eq!(sizes, vec![8, 4, 2, 1]);
+
+		for cubemap in &cubemaps
+		{
+			for pixel in cubemap.faces.iter().flatten()
+			{
+				assert!((pixel[1] - 1.0).abs() < 1e-3, "prefiltered {:?}", pixel);
+			}
+		}
+	}
+
+	#[test]
+	fn rougher_levels_blur_the_horizon()
+	{
+		let cubemaps = prefiltered_cubemaps(&sky_environment(), 8);
+		// Just above the horizon on the +z face
+		let sharp = cubemaps[0].faces[4][3 * 8 + 4][0];
+		let rough = cubemaps[cubemaps.len() - 1].faces[4][0][0];
+
+		assert!(sharp > 0.99);
+		assert!(rough > 0.2 && rough < 0.8);
+	}
+
+	#[test]
+	fn counts_mip_levels()
+	{
+		assert_eq!(mip_level_count(1), 1);
+		assert_eq!(mip_level_count(64), 7);
+		assert_eq!(mip_level_count(100), 7);
+	}
+
+	#[test]
+	fn brdf_lut_is_energy_conserving()
+	{
+		let table = brdf_lut(8);
+		assert_eq!(table.len(), 64);
+		for entry in &table
+		{
+			assert!(entry[0] >= 0.0 && entry[1] >= 0.0);
+			assert!(entry[0] + entry[1] <= 1.001, "brdf {:?}", entry);
+		}
+
+		// Smooth surfaces seen head on reflect all of f0
+		let smooth_head_on = table[7];
+		assert!((smooth_head_on[0] + smooth_head_on[1] - 1.0).abs() < 0.05, "brdf {:?}", smooth_head_on);
+		// Rough surfaces at grazing angles lose energy
+		let rough_grazing = table[7 * 8];
+		assert!(rough_grazing[0] + rough_grazing[1] < smooth_head_on[0] + smooth_head_on[1]);
+	}
+}
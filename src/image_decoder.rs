@@ -0,0 +1,99 @@
+use std::fmt;
+use std::path::Path;
+
+use image::ImageFormat;
+use image::RgbaImage;
+
+/*
+*
+*	Why an encoded texture could not be turned into pixels. Formats the crate was built without are reported
+*	separately from corrupt files, naming the cargo feature that would enable them
+*
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageDecodeError
+{
+	UnknownFormat,
+	FormatNotEnabled { format: &'static str, feature: &'static str },
+	UnsupportedFormat(&'static str),
+	Decode { format: &'static str, message: String }
+}
+
+impl fmt::Display for ImageDecodeError
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		return match self
+		{
+			ImageDecodeError::UnknownFormat => write!(f, "image format could not be recognised"),
+			ImageDecodeError::FormatNotEnabled { format, feature } => write!(f, "{} images are not supported by this build, enable the \"{}\" feature", format, feature),
+			ImageDecodeError::UnsupportedFormat(format) => write!(f, "{} images are not supported", format),
+			ImageDecodeError::Decode { format, message } => write!(f, "failed to decode {} image: {}", format, message)
+		};
+	}
+}
+
+impl std::error::Error for ImageDecodeError {}
+
+/*
+*
+*	Decode an encoded image into RGBA8 pixels. The format is recognised from the leading bytes, falling back
+*	to the extension of the file name for formats without a signature such as TGA. Animated GIFs give their
+*	first frame
+*
+*/
+pub fn decode_rgba8(bytes: &[u8], file_name: Option<&str>) -> Result<RgbaImage, ImageDecodeError>
+{
+	let format = image::guess_format(bytes)
+		.ok()
+		.or_else(|| file_name.and_then(|name| ImageFormat::from_path(Path::new(name)).ok()))
+		.ok_or(ImageDecodeError::UnknownFormat)?;
+
+	let name = get_format_name(format);
+	if !format.reading_enabled()
+	{
+		return Err(match get_format_feature(format)
+		{
+			Some(feature) => ImageDecodeError::FormatNotEnabled { format: name, feature },
+			None => ImageDecodeError::UnsupportedFormat(name)
+		});
+	}
+
+	let image = image::load_from_memory_with_format(bytes, format)
+		.map_err(|e| ImageDecodeError::Decode { format: name, message: e.to_string() })?;
+
+	return Ok(image.into_rgba8());
+}
+
+fn get_format_name(format: ImageFormat) -> &'static str
+{
+	return match format
+	{
+		ImageFormat::Png => "PNG",
+		ImageFormat::Jpeg => "JPEG",
+		ImageFormat::WebP => "WebP",
+		ImageFormat::Tga => "TGA",
+		ImageFormat::Bmp => "BMP",
+		ImageFormat::Gif => "GIF",
+		ImageFormat::Hdr => "HDR",
+		_ => format.extensions_str().first().copied().unwrap_or("unknown")
+	};
+}
+
+/*
+*
+*	The cargo feature of this crate that turns on decoding for a format, if it offers one
+*
+*/
+fn get_format_feature(format: ImageFormat) -> Option<&'static str>
+{
+	return match format
+	{
+		ImageFormat::Jpeg => Some("jpeg"),
+		ImageFormat::WebP => Some("webp"),
+		ImageFormat::Tga => Some("tga"),
+		ImageFormat::Bmp => Some("bmp"),
+		ImageFormat::Gif => Some("gif"),
+		_ => None
+	};
+}
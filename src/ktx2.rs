@@ -0,0 +1,293 @@
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::io::Read;
+
+const IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+// The Vulkan formats a KTX2 file can hold that WebGL2 can take without transcoding
+pub const VK_FORMAT_UNDEFINED: u32 = 0;
+pub const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+pub const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+pub const VK_FORMAT_BC3_UNORM_BLOCK: u32 = 137;
+pub const VK_FORMAT_BC3_SRGB_BLOCK: u32 = 138;
+pub const VK_FORMAT_BC7_UNORM_BLOCK: u32 = 145;
+pub const VK_FORMAT_BC7_SRGB_BLOCK: u32 = 146;
+pub const VK_FORMAT_ETC2_R8G8B8A8_UNORM_BLOCK: u32 = 151;
+pub const VK_FORMAT_ETC2_R8G8B8A8_SRGB_BLOCK: u32 = 152;
+pub const VK_FORMAT_ASTC_4X4_UNORM_BLOCK: u32 = 157;
+pub const VK_FORMAT_ASTC_4X4_SRGB_BLOCK: u32 = 158;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Supercompression
+{
+	None,
+	BasisLz,
+	Zstandard,
+	Zlib
+}
+
+/*
+*
+*	A parsed KTX2 container. Levels borrow their bytes from the file, still supercompressed, and run from
+*	the full size image down. Only single 2D images are accepted, not arrays, cubemaps or volumes
+*
+*/
+#[derive(Debug)]
+pub struct Ktx2<'a>
+{
+	pub vk_format: u32,
+	pub width: u32,
+	pub height: u32,
+	pub supercompression: Supercompression,
+	// A level count of 0 in the header asks for the mip chain to be generated from the one level stored
+	pub generate_mipmaps: bool,
+	pub levels: Vec<&'a [u8]>,
+	// The size of each level once supercompression is undone, 0 for BasisLZ where it isn't known
+	uncompressed_lengths: Vec<usize>
+}
+
+impl<'a> Ktx2<'a>
+{
+	pub fn is_ktx2(bytes: &[u8]) -> bool
+	{
+		return bytes.starts_with(&IDENTIFIER);
+	}
+
+	pub fn parse(bytes: &'a [u8]) -> Result<Self, String>
+	{
+		if !Ktx2::is_ktx2(bytes) || bytes.len() < HEADER_SIZE
+		{
+			return Err(String::from("not a KTX2 file"));
+		}
+
+		let vk_format = read_u32(bytes, 12)?;
+		let width = read_u32(bytes, 20)?;
+		let height = read_u32(bytes, 24)?;
+		let depth = read_u32(bytes, 28)?;
+		let layer_count = read_u32(bytes, 32)?;
+		let face_count = read_u32(bytes, 36)?;
+		let level_count = read_u32(bytes, 40)? as usize;
+		let supercompression = match read_u32(bytes, 44)?
+		{
+			0 => Supercompression::None,
+			1 => Supercompression::BasisLz,
+			2 => Supercompression::Zstandard,
+			3 => Supercompression::Zlib,
+			scheme => return Err(format!("unknown KTX2 supercompression scheme {}", scheme))
+		};
+
+		if width == 0 || height == 0 || depth > 1 || layer_count > 1 || face_count != 1
+		{
+			return Err(format!("only 2D KTX2 textures are supported, found {} x {} x {} with {} layers and {} faces", width, height, depth, layer_count, face_count));
+		}
+
+		// The index always has at least the one level, even when the rest are to be generated
+		let generate_mipmaps = level_count == 0;
+		let level_count = level_count.max(1);
+		let mut levels: Vec<&'a [u8]> = Vec::with_capacity(level_count);
+		let mut uncompressed_lengths: Vec<usize> = Vec::with_capacity(level_count);
+		for level in 0..level_count
+		{
+			let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+			let offset = read_u64(bytes, entry)? as usize;
+			let length = read_u64(bytes, entry + 8)? as usize;
+			let data = offset.checked_add(length)
+				.and_then(|end| bytes.get(offset..end))
+				.ok_or_else(|| format!("KTX2 level {} runs past the end of the file", level))?;
+			levels.push(data);
+			uncompressed_lengths.push(read_u64(bytes, entry + 16)? as usize);
+		}
+
+		return Ok(Self { vk_format, width, height, supercompression, generate_mipmaps, levels, uncompressed_lengths });
+	}
+
+	/*
+	*
+	*	Whether the levels are Basis Universal data, ETC1S or UASTC, that has to be transcoded before upload.
+	*	Any supercompression of Basis data is undone by the transcoder along with it
+	*
+	*/
+	pub fn needs_transcoding(&self) -> bool
+	{
+		return self.vk_format == VK_FORMAT_UNDEFINED;
+	}
+
+	/*
+	*
+	*	The levels of a file in a GPU format with Zstandard or zlib supercompression undone. BasisLZ only
+	*	applies to Basis Universal data, which goes to the transcoder whole instead
+	*
+	*/
+	pub fn decompress_levels(&self) -> Result<Vec<Cow<'a, [u8]>>, String>
+	{
+		let mut levels: Vec<Cow<'a, [u8]>> = Vec::with_capacity(self.levels.len());
+		for (level, data) in self.levels.iter().enumerate()
+		{
+			let decompressed = match self.supercompression
+			{
+				Supercompression::None => Cow::Borrowed(*data),
+				Supercompression::Zstandard =>
+				{
+					let mut decompressed: Vec<u8> = Vec::with_capacity(self.uncompressed_lengths[level]);
+					ruzstd::decoding::StreamingDecoder::new(*data)
+						.map_err(|e| e.to_string())
+						.and_then(|mut decoder| decoder.read_to_end(&mut decompressed).map_err(|e| e.to_string()))
+						.map_err(|e| format!("failed to decompress Zstandard KTX2 level {}: {}", level, e))?;
+					Cow::Owned(decompressed)
+				},
+				Supercompression::Zlib => Cow::Owned
+				(
+					miniz_oxide::inflate::decompress_to_vec_zlib(data)
+						.map_err(|e| format!("failed to decompress zlib KTX2 level {}: {}", level, e))?
+				),
+				Supercompression::BasisLz => return Err(String::from("BasisLZ supercompression is only valid for Basis Universal data"))
+			};
+
+			if self.supercompression != Supercompression::None && decompressed.len() != self.uncompressed_lengths[level]
+			{
+				return Err(format!("KTX2 level {} decompressed to {} bytes rather than {}", level, decompressed.len(), self.uncompressed_lengths[level]));
+			}
+			levels.push(decompressed);
+		}
+
+		return Ok(levels);
+	}
+
+	pub fn get_level_size(&self, level: usize) -> (u32, u32)
+	{
+		return ((self.width >> level).max(1), (self.height >> level).max(1));
+	}
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String>
+{
+	return bytes.get(offset..offset + 4)
+		.and_then(|slice| slice.try_into().ok())
+		.map(u32::from_le_bytes)
+		.ok_or_else(|| String::from("KTX2 header is truncated"));
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, String>
+{
+	return bytes.get(offset..offset + 8)
+		.and_then(|slice| slice.try_into().ok())
+		.map(u64::from_le_bytes)
+		.ok_or_else(|| String::from("KTX2 level index is truncated"));
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// A KTX2 file with the given header fields and levels laid out one after another past the level index
+	fn build(vk_format: u32, width: u32, height: u32, level_count: u32, supercompression: u32, levels: &[(&[u8], usize)]) -> Vec<u8>
+	{
+		let mut bytes: Vec<u8> = IDENTIFIER.to_vec();
+		for value in [vk_format, 1, width, height, 0, 0, 1, level_count, supercompression, 0, 0, 0, 0]
+		{
+			bytes.extend_from_slice(&value.to_le_bytes());
+		}
+		bytes.extend_from_slice(&[0; 16]);
+
+		let mut offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+		for (data, uncompressed_length) in levels
+		{
+			for value in [offset, data.len(), *uncompressed_length]
+			{
+				bytes.extend_from_slice(&(value as u64).to_le_bytes());
+			}
+			offset += data.len();
+		}
+		for (data, _) in levels
+		{
+			bytes.extend_from_slice(data);
+		}
+
+		return bytes;
+	}
+
+	#[test]
+	fn parses_a_valid_file()
+	{
+		let (level_0, level_1) = ([1u8; 64], [2u8; 16]);
+		let bytes = build(VK_FORMAT_R8G8B8A8_UNORM, 4, 4, 2, 0, &[(&level_0, 64), (&level_1, 16)]);
+
+		let ktx2 = Ktx2::parse(&bytes).unwrap();
+		assert_eq!(ktx2.vk_format, VK_FORMAT_R8G8B8A8_UNORM);
+		assert_eq!((ktx2.width, ktx2.height), (4, 4));
+		assert_eq!(ktx2.supercompression, Supercompression::None);
+		assert!(!ktx2.generate_mipmaps);
+		assert!(!ktx2.needs_transcoding());
+		assert_eq!(ktx2.levels, vec![&level_0[..], &level_1[..]]);
+		assert_eq!(ktx2.get_level_size(1), (2, 2));
+		assert_eq!(ktx2.get_level_size(5), (1, 1));
+	}
+
+	#[test]
+	fn rejects_a_bad_identifier()
+	{
+		let mut bytes = build(VK_FORMAT_R8G8B8A8_UNORM, 1, 1, 1, 0, &[(&[0; 4], 4)]);
+		bytes[5] = b'1';
+
+		assert!(!Ktx2::is_ktx2(&bytes));
+		assert!(Ktx2::parse(&bytes).is_err());
+		assert!(Ktx2::parse(&IDENTIFIER).is_err());
+	}
+
+	#[test]
+	fn rejects_truncated_levels()
+	{
+		let bytes = build(VK_FORMAT_R8G8B8A8_UNORM, 2, 2, 2, 0, &[(&[0; 16], 16), (&[0; 4], 4)]);
+
+		// The last level's data cut short
+		let error = Ktx2::parse(&bytes[..bytes.len() - 1]).unwrap_err();
+		assert!(error.contains("level 1 runs past"), "{}", error);
+
+		// The level index cut short
+		let error = Ktx2::parse(&bytes[..HEADER_SIZE + 8]).unwrap_err();
+		assert!(error.contains("level index is truncated"), "{}", error);
+	}
+
+	#[test]
+	fn level_count_0_asks_for_generated_mipmaps()
+	{
+		let bytes = build(VK_FORMAT_R8G8B8A8_UNORM, 4, 2, 0, 0, &[(&[7; 32], 32)]);
+
+		let ktx2 = Ktx2::parse(&bytes).unwrap();
+		assert!(ktx2.generate_mipmaps);
+		assert_eq!(ktx2.levels.len(), 1);
+		assert_eq!(ktx2.levels[0].len(), 32);
+	}
+
+	#[test]
+	fn only_basis_data_needs_transcoding()
+	{
+		let level = [3u8; 16];
+		let compressed = ruzstd::encoding::compress_to_vec(&level[..], ruzstd::encoding::CompressionLevel::Fastest);
+		let bytes = build(VK_FORMAT_BC7_UNORM_BLOCK, 4, 4, 1, 2, &[(&compressed, 16)]);
+
+		let ktx2 = Ktx2::parse(&bytes).unwrap();
+		assert_eq!(ktx2.supercompression, Supercompression::Zstandard);
+		assert!(!ktx2.needs_transcoding());
+		assert_eq!(ktx2.decompress_levels().unwrap()[0].as_ref(), &level[..]);
+
+		let bytes = build(VK_FORMAT_UNDEFINED, 4, 4, 1, 1, &[(&[0; 8], 0)]);
+		assert!(Ktx2::parse(&bytes).unwrap().needs_transcoding());
+	}
+
+	#[test]
+	fn decompresses_zlib_levels()
+	{
+		let level = [9u8; 64];
+		let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&level, 6);
+		let bytes = build(VK_FORMAT_R8G8B8A8_UNORM, 4, 4, 1, 3, &[(&compressed, 64)]);
+		assert_eq!(Ktx2::parse(&bytes).unwrap().decompress_levels().unwrap()[0].as_ref(), &level[..]);
+
+		// A wrong uncompressed length in the index is caught
+		let bytes = build(VK_FORMAT_R8G8B8A8_UNORM, 4, 4, 1, 3, &[(&compressed, 60)]);
+		assert!(Ktx2::parse(&bytes).unwrap().decompress_levels().is_err());
+	}
+}
@@ -5,6 +5,11 @@ mod engine_webgl2;
 mod webgl2_frame;
 mod webgl2_wavefront_object;
 mod matrix_helper;
+mod scene_graph;
+mod raycast;
+mod ibl;
+mod image_decoder;
+mod ktx2;
 
 use crate::controller::*;
 use crate::utils::*;
@@ -1,5 +1,40 @@
 use webgl_matrix::*;
 
+pub fn sub(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3]
+{
+    return [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn dot(a: &[f32; 3], b: &[f32; 3]) -> f32
+{
+    return a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3]
+{
+    return [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/*
+*
+*   Scale a vector to unit length, leaving a zero length vector as it is
+*
+*/
+pub fn normalize(a: &[f32; 3]) -> [f32; 3]
+{
+    let length = dot(a, a).sqrt();
+    if length < f32::EPSILON
+    {
+        return *a
+    }
+
+    return [a[0] / length, a[1] / length, a[2] / length]
+}
+
 pub fn scaling_matrix(factor: f32) -> Mat4
 {
     let scale = [
@@ -9,4 +44,122 @@ pub fn scaling_matrix(factor: f32) -> Mat4
         0.0, 0.0, 0.0, 1.0,
     ];
     return scale
-}
\ No newline at end of file
+}
+
+pub fn scaling_matrix_xyz(factors: &[f32; 3]) -> Mat4
+{
+    let scale = [
+        factors[0], 0.0, 0.0, 0.0,
+        0.0, factors[1], 0.0, 0.0,
+        0.0, 0.0, factors[2], 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    ];
+    return scale
+}
+
+/*
+*
+*   Multiply two column major matrices as a * b, so b is applied first. Note webgl_matrix's own `mul`
+*   treats the arrays as row major, so `a.mul(b)` gives b * a in GL terms
+*
+*/
+pub fn m4_multiply(a: &Mat4, b: &Mat4) -> Mat4
+{
+    let mut out = *b;
+    out.mul(a);
+    return out
+}
+
+/*
+*
+*   Build a translation * rotation * scale matrix. Rotation is in degrees, applied about x then y then z
+*
+*/
+pub fn trs_matrix(translation: &[f32; 3], rotation: &[f32; 3], scale: &[f32; 3]) -> Mat4
+{
+    let mut out = Mat4::identity();
+    out.translate(translation);
+    out.rotate(rotation[2].to_radians(), &[0.0, 0.0, 1.0]);
+    out.rotate(rotation[1].to_radians(), &[0.0, 1.0, 0.0]);
+    out.rotate(rotation[0].to_radians(), &[1.0, 0.0, 0.0]);
+    return m4_multiply(&out, &scaling_matrix_xyz(scale))
+}
+
+/*
+*
+*   Transform a point by a column major matrix, including the perspective divide
+*
+*/
+pub fn m4_transform_point(matrix: &Mat4, point: &[f32; 3]) -> [f32; 3]
+{
+    let out = matrix.mul_vector_left(&[point[0], point[1], point[2], 1.0]);
+    let w = if out[3].abs() > f32::EPSILON { out[3] } else { 1.0 };
+    return [out[0] / w, out[1] / w, out[2] / w]
+}
+
+/*
+*
+*   Transform a direction by a column major matrix, ignoring translation
+*
+*/
+pub fn m4_transform_vector(matrix: &Mat4, vector: &[f32; 3]) -> [f32; 3]
+{
+    let out = matrix.mul_vector_left(&[vector[0], vector[1], vector[2], 0.0]);
+    return [out[0], out[1], out[2]]
+}
+
+pub fn m4_inverse(matrix: &Mat4) -> Option<Mat4>
+{
+    let mut out = *matrix;
+    return out.inverse().map(|inverse| *inverse)
+}
+
+/*
+*
+*   The 3x3 inverse transpose of a model matrix, for moving normals into world space
+*
+*/
+pub fn normal_matrix(model_matrix: &Mat4) -> [f32; 9]
+{
+    let mut inverse = m4_inverse(model_matrix).unwrap_or(Mat4::identity());
+    inverse.transpose();
+    return [
+        inverse[0], inverse[1], inverse[2],
+        inverse[4], inverse[5], inverse[6],
+        inverse[8], inverse[9], inverse[10],
+    ]
+}
+
+/*
+*
+*   A view matrix looking from eye towards target, the equivalent of gluLookAt
+*
+*/
+pub fn look_at_matrix(eye: &[f32; 3], target: &[f32; 3], up: &[f32; 3]) -> Mat4
+{
+    let forward = normalize(&[target[0] - eye[0], target[1] - eye[1], target[2] - eye[2]]);
+    let side = normalize(&cross(&forward, up));
+    let up = cross(&side, &forward);
+
+    return [
+        side[0], up[0], -forward[0], 0.0,
+        side[1], up[1], -forward[1], 0.0,
+        side[2], up[2], -forward[2], 0.0,
+        -dot(&side, eye), -dot(&up, eye), dot(&forward, eye), 1.0,
+    ]
+}
+
+/*
+*
+*   An orthographic projection of the given view volume, the equivalent of glOrtho
+*
+*/
+pub fn orthographic_matrix(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Mat4
+{
+    return [
+        2.0 / (right - left), 0.0, 0.0, 0.0,
+        0.0, 2.0 / (top - bottom), 0.0, 0.0,
+        0.0, 0.0, -2.0 / (far - near), 0.0,
+        -(right + left) / (right - left), -(top + bottom) / (top - bottom), -(far + near) / (far - near), 1.0,
+    ]
+}
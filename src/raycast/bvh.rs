@@ -0,0 +1,330 @@
+use super::*;
+
+// Triangles per leaf before a node is split
+const LEAF_SIZE: usize = 4;
+
+/*
+*
+*	An axis aligned bounding box
+*
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb
+{
+	pub min: [f32; 3],
+	pub max: [f32; 3]
+}
+
+impl Aabb
+{
+	pub fn empty() -> Self
+	{
+		Self
+		{
+			min: [f32::INFINITY; 3],
+			max: [f32::NEG_INFINITY; 3]
+		}
+	}
+
+	pub fn grow(&mut self, point: &[f32; 3])
+	{
+		for (axis, value) in point.iter().enumerate()
+		{
+			self.min[axis] = self.min[axis].min(*value);
+			self.max[axis] = self.max[axis].max(*value);
+		}
+	}
+
+	/*
+	*
+	*	Slab test, returning the distance along the ray at which it enters the box
+	*
+	*/
+	pub fn intersect(&self, ray: &Ray, max_t: f32) -> Option<f32>
+	{
+		let mut t_min: f32 = 0.0;
+		let mut t_max: f32 = max_t;
+
+		for axis in 0..3
+		{
+			let inverse_direction = 1.0 / ray.direction[axis];
+			let mut t_0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+			let mut t_1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+			if inverse_direction < 0.0
+			{
+				std::mem::swap(&mut t_0, &mut t_1);
+			}
+
+			t_min = if t_0 > t_min { t_0 } else { t_min };
+			t_max = if t_1 < t_max { t_1 } else { t_max };
+			if t_max < t_min
+			{
+				return None;
+			}
+		}
+
+		return Some(t_min);
+	}
+}
+
+/*
+*
+*	A flattened BVH node. Leaves hold a range of `triangles`, interior nodes hold the index of their
+*	second child, the first child always following its parent directly
+*
+*/
+#[derive(Clone, Copy, Debug)]
+struct BvhNode
+{
+	bounds: Aabb,
+	second_child: usize,
+	first_triangle: usize,
+	triangle_count: usize
+}
+
+/*
+*
+*	Bounding volume hierarchy over a triangle mesh, built by splitting on the median centroid of the longest axis
+*
+*/
+pub struct Bvh
+{
+	nodes: Vec<BvhNode>,
+	triangles: Vec<usize>
+}
+
+impl Bvh
+{
+	pub fn build(positions: &[f32], indices: &[u32]) -> Self
+	{
+		let triangle_count = indices.len() / 3;
+		let centroids: Vec<[f32; 3]> = (0..triangle_count)
+			.map(|triangle| {
+				let [a, b, c] = get_triangle(positions, indices, triangle);
+				[(a[0] + b[0] + c[0]) / 3.0, (a[1] + b[1] + c[1]) / 3.0, (a[2] + b[2] + c[2]) / 3.0]
+			})
+			.collect();
+
+		let mut bvh = Self
+		{
+			nodes: Vec::new(),
+			triangles: (0..triangle_count).collect()
+		};
+		if triangle_count > 0
+		{
+			bvh.build_node(positions, indices, &centroids, 0, triangle_count);
+		}
+
+		return bvh;
+	}
+
+	fn build_node(&mut self, positions: &[f32], indices: &[u32], centroids: &[[f32; 3]], first: usize, count: usize) -> usize
+	{
+		let mut bounds = Aabb::empty();
+		let mut centroid_bounds = Aabb::empty();
+		for triangle in &self.triangles[first..first + count]
+		{
+			for vertex in get_triangle(positions, indices, *triangle).iter()
+			{
+				bounds.grow(vertex);
+			}
+			centroid_bounds.grow(&centroids[*triangle]);
+		}
+
+		let node = self.nodes.len();
+		self.nodes.push(BvhNode { bounds, second_child: 0, first_triangle: first, triangle_count: count });
+
+		if count <= LEAF_SIZE
+		{
+			return node;
+		}
+
+		let extent = sub(&centroid_bounds.max, &centroid_bounds.min);
+		let axis = if extent[0] >= extent[1] && extent[0] >= extent[2] { 0 } else if extent[1] >= extent[2] { 1 } else { 2 };
+
+		// Every centroid in the same place can't be split any further
+		if extent[axis] <= 0.0
+		{
+			return node;
+		}
+
+		self.triangles[first..first + count].sort_by(|a, b| {
+			centroids[*a][axis].partial_cmp(&centroids[*b][axis]).unwrap_or(std::cmp::Ordering::Equal)
+		});
+
+		let half = count / 2;
+		self.build_node(positions, indices, centroids, first, half);
+		let second_child = self.build_node(positions, indices, centroids, first + half, count - half);
+
+		self.nodes[node].second_child = second_child;
+		self.nodes[node].triangle_count = 0;
+
+		return node;
+	}
+
+	pub fn intersect(&self, positions: &[f32], indices: &[u32], ray: &Ray) -> Option<RayHit>
+	{
+		let mut closest: Option<RayHit> = None;
+		let mut closest_t = f32::INFINITY;
+		let mut stack: Vec<usize> = vec![0];
+
+		while let Some(n) = stack.pop()
+		{
+			let node = match self.nodes.get(n)
+			{
+				Some(node) => node,
+				None => continue
+			};
+
+			if node.bounds.intersect(ray, closest_t).is_none()
+			{
+				continue;
+			}
+
+			if node.triangle_count > 0
+			{
+				let triangles = self.triangles[node.first_triangle..node.first_triangle + node.triangle_count].iter().copied();
+				if let Some(hit) = intersect_triangles(positions, indices, triangles, ray, closest_t)
+				{
+					closest_t = hit.t;
+					closest = Some(hit);
+				}
+			}
+			else
+			{
+				stack.push(node.second_child);
+				stack.push(n + 1);
+			}
+		}
+
+		return closest;
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	/*
+	*
+	*	Two bumpy grids of quads, one above the other, so rays can cross both layers and the BVH has to pick the nearer
+	*
+	*/
+	fn layered_grid(size: usize) -> (Vec<f32>, Vec<u32>)
+	{
+		let mut positions: Vec<f32> = Vec::new();
+		let mut indices: Vec<u32> = Vec::new();
+
+		for layer in 0..2
+		{
+			let first = (positions.len() / 3) as u32;
+			for y in 0..=size
+			{
+				for x in 0..=size
+				{
+					let height = layer as f32 * 2.0 + ((x * 7 + y * 13) % 5) as f32 * 0.1;
+					positions.extend_from_slice(&[x as f32, y as f32, height]);
+				}
+			}
+
+			let row = (size + 1) as u32;
+			for y in 0..size as u32
+			{
+				for x in 0..size as u32
+				{
+					let corner = first + y * row + x;
+					indices.extend_from_slice(&[corner, corner + 1, corner + row + 1]);
+					indices.extend_from_slice(&[corner, corner + row + 1, corner + row]);
+				}
+			}
+		}
+
+		return (positions, indices);
+	}
+
+	fn brute_force(positions: &[f32], indices: &[u32], ray: &Ray) -> Option<RayHit>
+	{
+		return intersect_mesh(positions, indices, None, ray);
+	}
+
+	fn assert_same_hit(bvh_hit: Option<RayHit>, brute_hit: Option<RayHit>, ray: &Ray)
+	{
+		match (bvh_hit, brute_hit)
+		{
+			(Some(bvh_hit), Some(brute_hit)) => assert!((bvh_hit.t - brute_hit.t).abs() < 1e-5, "{:?} hit at {} rather than {}", ray, bvh_hit.t, brute_hit.t),
+			(None, None) => {},
+			(bvh_hit, brute_hit) => panic!("{:?}: BVH gave {:?}, brute force gave {:?}", ray, bvh_hit, brute_hit)
+		}
+	}
+
+	#[test]
+	fn bvh_matches_brute_force()
+	{
+		let (positions, indices) = layered_grid(16);
+		assert!(indices.len() / 3 >= 256);
+		let bvh = Bvh::build(&positions, &indices);
+
+		for i in 0..400
+		{
+			let x = (i % 20) as f32 * 0.9 - 0.5;
+			let y = (i / 20) as f32 * 0.9 - 0.5;
+			let directions = [[0.0, 0.0, -1.0], [0.0, 0.0, 1.0], [0.3, -0.2, -1.0], [-1.0, 0.5, -0.4]];
+			for direction in directions.iter()
+			{
+				let ray = Ray { origin: [x, y, if direction[2] > 0.0 { -5.0 } else { 5.0 }], direction: *direction };
+				assert_same_hit(bvh.intersect(&positions, &indices, &ray), brute_force(&positions, &indices, &ray), &ray);
+			}
+		}
+	}
+
+	#[test]
+	fn bvh_finds_the_nearer_layer_from_either_side()
+	{
+		let (positions, indices) = layered_grid(16);
+		let bvh = Bvh::build(&positions, &indices);
+
+		let from_above = bvh.intersect(&positions, &indices, &Ray { origin: [4.3, 7.6, 10.0], direction: [0.0, 0.0, -1.0] }).unwrap();
+		assert!(from_above.t < 8.5, "hit the lower layer at {}", from_above.t);
+
+		let from_below = bvh.intersect(&positions, &indices, &Ray { origin: [4.3, 7.6, -10.0], direction: [0.0, 0.0, 1.0] }).unwrap();
+		assert!(from_below.t < 10.5, "hit the upper layer at {}", from_below.t);
+	}
+
+	#[test]
+	fn axis_parallel_rays_through_the_bounds()
+	{
+		let (positions, indices) = layered_grid(16);
+		let bvh = Bvh::build(&positions, &indices);
+
+		// Directions with zero components give infinite slab distances, and these skim along the layers
+		let rays =
+		[
+			Ray { origin: [-1.0, 3.5, 0.2], direction: [1.0, 0.0, 0.0] },
+			Ray { origin: [3.5, -1.0, 2.1], direction: [0.0, 1.0, 0.0] },
+			Ray { origin: [-1.0, 0.0, 0.0], direction: [1.0, 0.0, 0.0] },
+			Ray { origin: [20.0, 20.0, 1.0], direction: [-1.0, -1.0, 0.0] }
+		];
+		for ray in rays.iter()
+		{
+			assert_same_hit(bvh.intersect(&positions, &indices, ray), brute_force(&positions, &indices, ray), ray);
+		}
+	}
+
+	#[test]
+	fn rays_missing_the_bounds_find_nothing()
+	{
+		let (positions, indices) = layered_grid(16);
+		let bvh = Bvh::build(&positions, &indices);
+
+		assert!(bvh.intersect(&positions, &indices, &Ray { origin: [-5.0, -5.0, 5.0], direction: [0.0, 0.0, -1.0] }).is_none());
+		assert!(bvh.intersect(&positions, &indices, &Ray { origin: [8.0, 8.0, 5.0], direction: [0.0, 0.0, 1.0] }).is_none());
+	}
+
+	#[test]
+	fn empty_mesh_has_no_hits()
+	{
+		let bvh = Bvh::build(&[], &[]);
+		assert!(bvh.intersect(&[], &[], &Ray { origin: [0.0; 3], direction: [0.0, 0.0, 1.0] }).is_none());
+	}
+}
@@ -0,0 +1,236 @@
+pub mod bvh;
+
+use bvh::Bvh;
+
+use crate::matrix_helper::cross;
+use crate::matrix_helper::dot;
+use crate::matrix_helper::sub;
+
+/*
+*
+*	A ray as origin + t * direction. The direction is not normalised so that t stays comparable after the ray
+*	is moved into an object's local space by an affine transform
+*
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Ray
+{
+	pub origin: [f32; 3],
+	pub direction: [f32; 3]
+}
+
+/*
+*
+*	The closest triangle a ray hits. `u` and `v` are the barycentric weights of the triangle's second and third vertex
+*
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit
+{
+	pub triangle: usize,
+	pub t: f32,
+	pub u: f32,
+	pub v: f32
+}
+
+impl Ray
+{
+	pub fn at(&self, t: f32) -> [f32; 3]
+	{
+		return
+		[
+			self.origin[0] + self.direction[0] * t,
+			self.origin[1] + self.direction[1] * t,
+			self.origin[2] + self.direction[2] * t
+		];
+	}
+}
+
+/*
+*
+*	Cast a ray against an indexed triangle mesh, using the BVH when one has been built
+*
+*/
+pub fn intersect_mesh(positions: &[f32], indices: &[u32], bvh: Option<&Bvh>, ray: &Ray) -> Option<RayHit>
+{
+	match bvh
+	{
+		Some(bvh) => bvh.intersect(positions, indices, ray),
+		None => intersect_triangles(positions, indices, 0..(indices.len() / 3), ray, f32::INFINITY)
+	}
+}
+
+pub(crate) fn intersect_triangles<I>(positions: &[f32], indices: &[u32], triangles: I, ray: &Ray, max_t: f32) -> Option<RayHit>
+	where I: IntoIterator<Item = usize>
+{
+	let mut closest: Option<RayHit> = None;
+	let mut closest_t = max_t;
+
+	for triangle in triangles
+	{
+		let [a, b, c] = get_triangle(positions, indices, triangle);
+		if let Some((t, u, v)) = intersect_triangle(ray, &a, &b, &c)
+		{
+			if t < closest_t
+			{
+				closest_t = t;
+				closest = Some(RayHit { triangle, t, u, v });
+			}
+		}
+	}
+
+	return closest;
+}
+
+pub fn get_triangle(positions: &[f32], indices: &[u32], triangle: usize) -> [[f32; 3]; 3]
+{
+	let vertex = |n: usize| {
+		let index = indices[triangle * 3 + n] as usize * 3;
+		[positions[index], positions[index + 1], positions[index + 2]]
+	};
+
+	return [vertex(0), vertex(1), vertex(2)];
+}
+
+/*
+*
+*	Möller-Trumbore ray/triangle intersection. Both faces are hit as the viewer doesn't cull back faces
+*
+*/
+pub fn intersect_triangle(ray: &Ray, a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> Option<(f32, f32, f32)>
+{
+	let edge_1 = sub(b, a);
+	let edge_2 = sub(c, a);
+	let p = cross(&ray.direction, &edge_2);
+	let det = dot(&edge_1, &p);
+
+	if det.abs() < 1e-12
+	{
+		return None;
+	}
+
+	let inverse_det = 1.0 / det;
+	let s = sub(&ray.origin, a);
+	let u = dot(&s, &p) * inverse_det;
+	if !(0.0..=1.0).contains(&u)
+	{
+		return None;
+	}
+
+	let q = cross(&s, &edge_1);
+	let v = dot(&ray.direction, &q) * inverse_det;
+	if v < 0.0 || u + v > 1.0
+	{
+		return None;
+	}
+
+	let t = dot(&edge_2, &q) * inverse_det;
+	if t <= 0.0
+	{
+		return None;
+	}
+
+	return Some((t, u, v));
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+	fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray
+	{
+		return Ray { origin, direction };
+	}
+
+	fn cast(ray: &Ray) -> Option<(f32, f32, f32)>
+	{
+		return intersect_triangle(ray, &TRIANGLE[0], &TRIANGLE[1], &TRIANGLE[2]);
+	}
+
+	#[test]
+	fn hit_gives_distance_and_barycentrics()
+	{
+		let (t, u, v) = cast(&ray([0.25, 0.5, 2.0], [0.0, 0.0, -1.0])).unwrap();
+		assert!((t - 2.0).abs() < 1e-6);
+		assert!((u - 0.25).abs() < 1e-6);
+		assert!((v - 0.5).abs() < 1e-6);
+	}
+
+	#[test]
+	fn distance_is_in_units_of_the_unnormalised_direction()
+	{
+		let (t, _, _) = cast(&ray([0.25, 0.25, 2.0], [0.0, 0.0, -4.0])).unwrap();
+		assert!((t - 0.5).abs() < 1e-6);
+	}
+
+	#[test]
+	fn back_faces_are_hit()
+	{
+		assert!(cast(&ray([0.25, 0.25, -1.0], [0.0, 0.0, 1.0])).is_some());
+	}
+
+	#[test]
+	fn misses_outside_the_triangle()
+	{
+		assert!(cast(&ray([0.75, 0.75, 1.0], [0.0, 0.0, -1.0])).is_none());
+		assert!(cast(&ray([-0.1, 0.5, 1.0], [0.0, 0.0, -1.0])).is_none());
+	}
+
+	#[test]
+	fn misses_behind_the_origin()
+	{
+		assert!(cast(&ray([0.25, 0.25, 1.0], [0.0, 0.0, 1.0])).is_none());
+	}
+
+	#[test]
+	fn parallel_rays_miss()
+	{
+		// In the triangle's plane, and just above it
+		assert!(cast(&ray([-1.0, 0.25, 0.0], [1.0, 0.0, 0.0])).is_none());
+		assert!(cast(&ray([-1.0, 0.25, 0.1], [1.0, 0.0, 0.0])).is_none());
+	}
+
+	#[test]
+	fn grazing_rays_hit_edges_and_corners()
+	{
+		let (_, u, v) = cast(&ray([0.5, 0.5, 1.0], [0.0, 0.0, -1.0])).unwrap();
+		assert!((u + v - 1.0).abs() < 1e-6);
+		assert!(cast(&ray([0.0, 0.0, 1.0], [0.0, 0.0, -1.0])).is_some());
+
+		// Nearly edge on, yet still through the face
+		let (t, _, _) = cast(&ray([-1.0, 0.25, 0.0025], [1.0, 0.0, -0.002])).unwrap();
+		assert!((t - 1.25).abs() < 1e-3);
+	}
+
+	#[test]
+	fn mesh_hit_is_the_nearest_whatever_the_triangle_order()
+	{
+		// Three copies of the triangle at z = -1, 2 and 1, with the nearest to the ray in the middle
+		let positions: Vec<f32> = [-1.0, 2.0, 1.0].iter()
+			.flat_map(|z| TRIANGLE.iter().flat_map(move |vertex| vec![vertex[0], vertex[1], *z]))
+			.collect();
+		let indices: Vec<u32> = (0..9).collect();
+
+		let hit = intersect_mesh(&positions, &indices, None, &ray([0.25, 0.25, 3.0], [0.0, 0.0, -1.0])).unwrap();
+		assert_eq!(hit.triangle, 1);
+		assert!((hit.t - 1.0).abs() < 1e-6);
+
+		let hit = intersect_mesh(&positions, &indices, None, &ray([0.25, 0.25, -3.0], [0.0, 0.0, 1.0])).unwrap();
+		assert_eq!(hit.triangle, 0);
+		assert!((hit.t - 2.0).abs() < 1e-6);
+	}
+
+	#[test]
+	fn closer_limit_excludes_further_hits()
+	{
+		let positions: Vec<f32> = TRIANGLE.iter().flatten().copied().collect();
+		let indices = [0, 1, 2];
+		let cast_ray = ray([0.25, 0.25, 2.0], [0.0, 0.0, -1.0]);
+
+		assert!(intersect_triangles(&positions, &indices, 0..1, &cast_ray, 1.5).is_none());
+		assert!(intersect_triangles(&positions, &indices, 0..1, &cast_ray, 2.5).is_some());
+	}
+}
@@ -0,0 +1,283 @@
+use crate::matrix_helper::*;
+
+use webgl_matrix::*;
+
+pub const ROOT_NODE: usize = 0;
+
+/*
+*
+*	A node in the scene graph. Transforms are local to the parent node
+*
+*/
+#[derive(Clone, Debug)]
+pub struct SceneNode
+{
+	pub name: String,
+	pub translation: [f32; 3],
+	pub rotation: [f32; 3],
+	pub scale: [f32; 3],
+	pub parent: Option<usize>,
+	pub children: Vec<usize>
+}
+
+impl SceneNode
+{
+	pub fn new(name: &str, parent: Option<usize>) -> Self
+	{
+		Self
+		{
+			name: name.to_owned(),
+			translation: [0.0, 0.0, 0.0],
+			rotation: [0.0, 0.0, 0.0],
+			scale: [1.0, 1.0, 1.0],
+			parent,
+			children: Vec::new()
+		}
+	}
+
+	pub fn local_matrix(&self) -> Mat4
+	{
+		return trs_matrix(&self.translation, &self.rotation, &self.scale);
+	}
+}
+
+/*
+*
+*	Node ids are indices into `nodes` and stay valid for the life of the graph. Removed nodes leave a
+*	`None` slot behind rather than shifting the ids of the nodes after them
+*
+*/
+pub struct SceneGraph
+{
+	nodes: Vec<Option<SceneNode>>
+}
+
+impl SceneGraph
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			nodes: vec![Some(SceneNode::new("root", None))]
+		}
+	}
+
+	pub fn add_node(&mut self, name: &str, parent: usize) -> usize
+	{
+		let id = self.nodes.len();
+		self.nodes.push(Some(SceneNode::new(name, Some(parent))));
+
+		if let Some(parent_node) = self.get_node_mut(parent)
+		{
+			parent_node.children.push(id);
+		}
+
+		return id;
+	}
+
+	/*
+	*
+	*	Remove a node and all of its descendants, returning the ids of every node removed
+	*
+	*/
+	pub fn remove_node(&mut self, id: usize) -> Vec<usize>
+	{
+		if id == ROOT_NODE
+		{
+			return Vec::new();
+		}
+
+		let removed = self.get_subtree(id);
+
+		if let Some(parent_node) = self.get_node(id).and_then(|node| node.parent).and_then(|parent| self.get_node_mut(parent))
+		{
+			parent_node.children.retain(|child| *child != id);
+		}
+
+		for node in &removed
+		{
+			self.nodes[*node] = None;
+		}
+
+		return removed;
+	}
+
+	/*
+	*
+	*	Get the ids of a node and all of its descendants
+	*
+	*/
+	pub fn get_subtree(&self, id: usize) -> Vec<usize>
+	{
+		let mut subtree: Vec<usize> = Vec::new();
+
+		if let Some(node) = self.get_node(id)
+		{
+			subtree.push(id);
+			for child in &node.children
+			{
+				subtree.extend(self.get_subtree(*child));
+			}
+		}
+
+		return subtree;
+	}
+
+	pub fn get_node(&self, id: usize) -> Option<&SceneNode>
+	{
+		return self.nodes.get(id).and_then(|node| node.as_ref());
+	}
+
+	pub fn get_node_mut(&mut self, id: usize) -> Option<&mut SceneNode>
+	{
+		return self.nodes.get_mut(id).and_then(|node| node.as_mut());
+	}
+
+	/*
+	*
+	*	Find a node by name, or by the path of names leading to it from below the root, as in "car/wheel".
+	*	A name more than one node shares is an error rather than a guess, its path telling them apart
+	*
+	*/
+	pub fn find_node(&self, name: &str) -> Result<usize, String>
+	{
+		let matches: Vec<usize> = (0..self.nodes.len()).filter(|id| self.get_node(*id).map(|node| node.name == name).unwrap_or(false)).collect();
+
+		return match matches.as_slice()
+		{
+			[id] => Ok(*id),
+			[] if name.contains('/') => self.find_path(name),
+			[] => Err(format!("No scene node named {}", name)),
+			_ => Err(format!("{} scene nodes are named {}, give its path from the root instead, as in parent/{}", matches.len(), name, name))
+		};
+	}
+
+	fn find_path(&self, path: &str) -> Result<usize, String>
+	{
+		let mut id = ROOT_NODE;
+		for name in path.trim_start_matches('/').split('/')
+		{
+			let children: Vec<usize> = self.get_node(id)
+				.map(|node| node.children.iter().copied().filter(|child| self.get_node(*child).map(|child| child.name == name).unwrap_or(false)).collect())
+				.unwrap_or_default();
+
+			id = match children.as_slice()
+			{
+				[child] => *child,
+				[] => return Err(format!("No scene node at {}, it has no {}", path, name)),
+				_ => return Err(format!("The scene node path {} is ambiguous, more than one {} is there", path, name))
+			};
+		}
+
+		return Ok(id);
+	}
+
+	pub fn get_node_names(&self) -> Vec<String>
+	{
+		return self.nodes.iter().skip(1).flatten().map(|node| node.name.clone()).collect();
+	}
+
+	/*
+	*
+	*	Compute the world matrix of every node, indexed by node id. Parents are always created before their
+	*	children so a single pass in id order is enough
+	*
+	*/
+	pub fn get_world_matrices(&self) -> Vec<Mat4>
+	{
+		let mut world_matrices: Vec<Mat4> = vec![Mat4::identity(); self.nodes.len()];
+
+		for id in 0..self.nodes.len()
+		{
+			if let Some(node) = &self.nodes[id]
+			{
+				world_matrices[id] = match node.parent
+				{
+					Some(parent) => m4_multiply(&world_matrices[parent], &node.local_matrix()),
+					None => node.local_matrix()
+				};
+			}
+		}
+
+		return world_matrices;
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// root -> car -> wheel, and root -> truck -> wheel and cab
+	fn vehicles() -> (SceneGraph, [usize; 5])
+	{
+		let mut graph = SceneGraph::new();
+		let car = graph.add_node("car", ROOT_NODE);
+		let car_wheel = graph.add_node("wheel", car);
+		let truck = graph.add_node("truck", ROOT_NODE);
+		let truck_wheel = graph.add_node("wheel", truck);
+		let cab = graph.add_node("cab", truck);
+
+		return (graph, [car, car_wheel, truck, truck_wheel, cab]);
+	}
+
+	#[test]
+	fn unique_names_are_found()
+	{
+		let (graph, [car, _, _, _, cab]) = vehicles();
+
+		assert_eq!(graph.find_node("car"), Ok(car));
+		assert_eq!(graph.find_node("cab"), Ok(cab));
+		assert_eq!(graph.find_node("root"), Ok(ROOT_NODE));
+		assert!(graph.find_node("boat").is_err());
+	}
+
+	#[test]
+	fn shared_names_are_an_error()
+	{
+		let (graph, _) = vehicles();
+
+		let error = graph.find_node("wheel").unwrap_err();
+		assert!(error.contains("2 scene nodes are named wheel"), "{}", error);
+	}
+
+	#[test]
+	fn paths_tell_shared_names_apart()
+	{
+		let (graph, [_, car_wheel, _, truck_wheel, cab]) = vehicles();
+
+		assert_eq!(graph.find_node("car/wheel"), Ok(car_wheel));
+		assert_eq!(graph.find_node("/truck/wheel"), Ok(truck_wheel));
+		assert_eq!(graph.find_node("truck/cab"), Ok(cab));
+		assert!(graph.find_node("car/cab").is_err());
+		assert!(graph.find_node("wheel/car").is_err());
+	}
+
+	#[test]
+	fn ambiguous_paths_are_an_error()
+	{
+		let (mut graph, _) = vehicles();
+		graph.add_node("car", ROOT_NODE);
+
+		assert!(graph.find_node("car/wheel").is_err());
+	}
+
+	#[test]
+	fn names_with_slashes_match_whole_before_paths()
+	{
+		let (mut graph, _) = vehicles();
+		let node = graph.add_node("car/wheel", ROOT_NODE);
+
+		assert_eq!(graph.find_node("car/wheel"), Ok(node));
+	}
+
+	#[test]
+	fn removed_nodes_are_not_found()
+	{
+		let (mut graph, [car, _, _, truck_wheel, _]) = vehicles();
+		assert_eq!(graph.remove_node(car).len(), 2);
+
+		assert!(graph.find_node("car").is_err());
+		assert_eq!(graph.find_node("wheel"), Ok(truck_wheel));
+	}
+}
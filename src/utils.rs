@@ -2,7 +2,7 @@ use crate::controller::*;
 
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
-use web_sys::{window, EventTarget, MouseEvent };
+use web_sys::{window, EventTarget, MouseEvent, WebGl2RenderingContext, WebGlProgram };
 use js_sys::Array;
 use js_sys::Map;
 use std::collections::HashMap;
@@ -93,6 +93,10 @@ pub fn get_window_resolution() -> [f32; 2]
 pub fn get_js_sys_map_to_hashmap(outer_map: &Map, inner_map_key: &str) -> Option<HashMap<String, String>>
 {
     let inner_map_jsvalue = outer_map.get(&JsValue::from_str(inner_map_key));
+    if !inner_map_jsvalue.is_instance_of::<Map>()
+    {
+        return None;
+    }
     let inner_map = Map::from(inner_map_jsvalue);
     let result: HashMap<String, String> = Array::from(&inner_map.entries())
         .iter()
@@ -112,4 +116,23 @@ pub fn get_js_sys_map_to_hashmap(outer_map: &Map, inner_map_key: &str) -> Option
     {
         return Some(result);
     }
+}
+
+/*
+*
+*	Look up an attribute location, returning None instead of -1 when the program doesn't use the attribute
+*
+*/
+pub fn get_attribute_location(context: &WebGl2RenderingContext, program: &WebGlProgram, name: &str) -> Option<u32>
+{
+    let location = context.get_attrib_location(program, name);
+
+    if location < 0
+    {
+        return None;
+    }
+    else
+    {
+        return Some(location as u32);
+    }
 }
\ No newline at end of file
@@ -66,13 +66,6 @@ pub fn initialize_animation(frame_wrap: &Rc<RefCell<WebGl2Frame>>)
 			//Update the camera position
 			frame.camera_matrix = update_camera_position(&frame.camera_matrix, &controller_values);
 
-			//Mutable reference to the Webgl Frame
-			let tmp = frame.program.as_mut().unwrap().clone();
-
-			//Pass worldspace transfomration to the GPU
-			let position_index = frame.context.get_uniform_location(&tmp, "u_camera_matrix");
-			frame.context.uniform_matrix4fv_with_f32_array(position_index.as_ref(), false, &frame.camera_matrix);
-			
 			//Pass mouse position to the GPU
 			let mouse_position = controller_values.mouse_position;
 			rust_super_super_verbose
@@ -83,14 +76,12 @@ pub fn initialize_animation(frame_wrap: &Rc<RefCell<WebGl2Frame>>)
 					" to shader"
 				)
 			);
-			let mouse_position_index = frame.context.get_uniform_location(&tmp, "u_mouse_position");
-			frame.context.uniform2fv_with_f32_array(mouse_position_index.as_ref(), &mouse_position);
+			frame.mouse_position = mouse_position;
 
 			//Pass time to the GPU
 			time = time + ((now - base) / 1000.0) as f32;
 			rust_super_super_verbose(&("Passing u_time ".to_owned() + time.to_string().as_str() + " to shader."));
-			let time_index = frame.context.get_uniform_location(&tmp, "u_time");
-			frame.context.uniform1f(time_index.as_ref(), time);
+			frame.time = time;
 
 			frame.draw();
 
@@ -0,0 +1,179 @@
+use js_sys::Map;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlProgram;
+use web_sys::WebGlTexture;
+
+use crate::logger::*;
+
+use super::WebGl2Frame;
+use super::builtin_shaders::FULLSCREEN_VERTEX_SHADER;
+
+// Edge directed blur over luma, after Timothy Lottes' FXAA
+const FXAA_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+#define REDUCE_MIN (1.0 / 128.0)
+#define REDUCE_MUL (1.0 / 8.0)
+#define SPAN_MAX 8.0
+
+uniform sampler2D u_scene_map;
+
+out vec4 out_color;
+
+float luma(vec3 color)
+{
+	return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main()
+{
+	vec2 texel = 1.0 / vec2(textureSize(u_scene_map, 0));
+	vec2 uv = gl_FragCoord.xy * texel;
+
+	float luma_nw = luma(texture(u_scene_map, uv + vec2(-1.0, 1.0) * texel).rgb);
+	float luma_ne = luma(texture(u_scene_map, uv + vec2(1.0, 1.0) * texel).rgb);
+	float luma_sw = luma(texture(u_scene_map, uv + vec2(-1.0, -1.0) * texel).rgb);
+	float luma_se = luma(texture(u_scene_map, uv + vec2(1.0, -1.0) * texel).rgb);
+	vec4 center = texture(u_scene_map, uv);
+	float luma_m = luma(center.rgb);
+
+	float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+	float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+	// Blur along the edge, across the steepest luma gradient
+	vec2 direction = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+	float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+	float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+	direction = clamp(direction * scale, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel;
+
+	vec4 near = 0.5 * (texture(u_scene_map, uv + direction * (1.0 / 3.0 - 0.5)) + texture(u_scene_map, uv + direction * (2.0 / 3.0 - 0.5)));
+	vec4 far = near * 0.5 + 0.25 * (texture(u_scene_map, uv - direction * 0.5) + texture(u_scene_map, uv + direction * 0.5));
+
+	// The wider blur is dropped where it reached past the local contrast, having crossed another edge
+	float luma_far = luma(far.rgb);
+	out_color = (luma_far < luma_min || luma_far > luma_max) ? near : far;
+}
+"#;
+
+/*
+*
+*	Anti-aliasing, chosen when the engine is created through an "antialias" map in the resources:
+*	"context" (bool) asks the browser to antialias the canvas, "samples" (number) renders through a
+*	multisampled framebuffer instead, and "fxaa" (bool) smooths the finished frame in a post process.
+*	Samples and FXAA can be combined, either of them turns the browser's own antialiasing off
+*
+*/
+pub struct Antialiasing
+{
+	context_antialias: bool,
+	samples: i32,
+	fxaa: bool,
+	fxaa_program: Option<WebGlProgram>
+}
+
+impl Antialiasing
+{
+	pub fn from_resources(resources: &Map) -> Self
+	{
+		let mut antialiasing = Self
+		{
+			context_antialias: true,
+			samples: 0,
+			fxaa: false,
+			fxaa_program: None
+		};
+
+		let settings = resources.get(&JsValue::from_str("antialias"));
+		if settings.is_instance_of::<Map>()
+		{
+			let settings = Map::from(settings);
+			antialiasing.context_antialias = settings.get(&JsValue::from_str("context")).as_bool().unwrap_or(true);
+			antialiasing.samples = settings.get(&JsValue::from_str("samples")).as_f64().unwrap_or(0.0).max(0.0) as i32;
+			antialiasing.fxaa = settings.get(&JsValue::from_str("fxaa")).as_bool().unwrap_or(false);
+		}
+
+		rust_verbose
+		(
+			&format!
+			(
+				"Anti-aliasing: context {}, {} samples, FXAA {}",
+				antialiasing.uses_context_antialias(), antialiasing.samples, antialiasing.fxaa
+			)
+		);
+
+		return antialiasing;
+	}
+
+	// A multisampled canvas can't be blitted to, so the browser's antialiasing is only used on its own
+	pub fn uses_context_antialias(&self) -> bool
+	{
+		return self.context_antialias && !self.renders_offscreen();
+	}
+
+	pub fn renders_offscreen(&self) -> bool
+	{
+		return self.samples > 0 || self.fxaa;
+	}
+
+	pub fn samples(&self) -> i32
+	{
+		return self.samples;
+	}
+
+	pub fn uses_fxaa(&self) -> bool
+	{
+		return self.fxaa;
+	}
+
+	pub fn limit_samples(&mut self, max_samples: i32)
+	{
+		if self.samples > max_samples
+		{
+			rust_warn(&format!("{} samples requested but this device supports at most {}", self.samples, max_samples));
+			self.samples = max_samples;
+		}
+	}
+
+	pub fn disable(&mut self)
+	{
+		self.samples = 0;
+		self.fxaa = false;
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Run FXAA over a finished frame into whichever framebuffer is bound, the canvas at the end of the frame
+	*
+	*/
+	pub(in super) fn draw_fxaa(&mut self, source: Option<&WebGlTexture>)
+	{
+		if self.antialiasing.fxaa_program.is_none()
+		{
+			rust_verbose("Building FXAA shader...");
+			match self.build_builtin_program(FULLSCREEN_VERTEX_SHADER, FXAA_FRAGMENT_SHADER)
+			{
+				Ok(program) => self.antialiasing.fxaa_program = Some(program),
+				Err(e) =>
+				{
+					rust_error(&format!("Turning off FXAA: {}", e));
+					self.antialiasing.fxaa = false;
+					return;
+				}
+			}
+			rust_verbose("...FXAA shader build complete.");
+		}
+
+		self.current_program = self.antialiasing.fxaa_program.clone();
+		self.context.use_program(self.current_program.as_ref());
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+		self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, source);
+		let scene_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_scene_map");
+		self.context.uniform1i(scene_index.as_ref(), 0);
+
+		self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+	}
+}
@@ -0,0 +1,309 @@
+use base64::Engine;
+use js_sys::Array;
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlTexture;
+
+use crate::image_decoder::decode_rgba8;
+use crate::logger::*;
+use crate::matrix_helper::*;
+
+use super::WebGl2Frame;
+use super::builtin_shaders::FULLSCREEN_VERTEX_SHADER;
+
+// After the environment map units
+const SKYBOX_UNIT: u32 = 10;
+
+// Mode values the background shader switches on
+const MODE_GRADIENT: i32 = 0;
+const MODE_SKYBOX: i32 = 1;
+const MODE_ENVIRONMENT: i32 = 2;
+
+// Draws every non flat background from the full screen triangle at the far plane
+const BACKGROUND_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+#define PI 3.14159265359
+#define MODE_GRADIENT 0
+#define MODE_SKYBOX 1
+#define MODE_ENVIRONMENT 2
+
+in vec2 v_ndc;
+in vec2 v_texcoord;
+
+uniform int u_background_mode;
+uniform vec4 u_screen_rect;
+uniform vec4 u_gradient_top;
+uniform vec4 u_gradient_bottom;
+uniform mat4 u_inverse_view_projection;
+uniform samplerCube u_skybox_map;
+uniform sampler2D u_background_map;
+uniform float u_environment_intensity;
+
+out vec4 out_color;
+
+void main()
+{
+	if (u_background_mode == MODE_GRADIENT)
+	{
+		// The height up the whole image, which is only part of it when rendered in tiles
+		out_color = mix(u_gradient_bottom, u_gradient_top, u_screen_rect.y + v_texcoord.y * u_screen_rect.w);
+		return;
+	}
+
+	vec4 near = u_inverse_view_projection * vec4(v_ndc, -1.0, 1.0);
+	vec4 far = u_inverse_view_projection * vec4(v_ndc, 1.0, 1.0);
+	vec3 direction = normalize(far.xyz / far.w - near.xyz / near.w);
+
+	if (u_background_mode == MODE_SKYBOX)
+	{
+		out_color = vec4(texture(u_skybox_map, direction).rgb, 1.0);
+		return;
+	}
+
+	vec2 uv = vec2(0.5 + atan(direction.x, -direction.z) / (2.0 * PI), acos(clamp(direction.y, -1.0, 1.0)) / PI);
+	// Explicit level, as the derivatives jump across the seam where atan wraps
+	out_color = vec4(textureLod(u_background_map, uv, 0.0).rgb * u_environment_intensity, 1.0);
+}
+"#;
+
+/*
+*
+*	What is drawn behind the scene. Solid colors and transparency are simply the clear color, the others
+*	are drawn by the background shader before any objects
+*
+*/
+pub enum Background
+{
+	Transparent,
+	Solid([f32; 4]),
+	Gradient { top: [f32; 4], bottom: [f32; 4] },
+	// Six faces in GL order: +x, -x, +y, -y, +z, -z
+	Skybox(WebGlTexture),
+	// The loaded HDR environment map
+	Environment
+}
+
+impl WebGl2Frame
+{
+	pub fn set_background_transparent(&mut self)
+	{
+		self.replace_background(Background::Transparent);
+	}
+
+	pub fn set_background_color(&mut self, color: [f32; 4])
+	{
+		self.replace_background(Background::Solid(color));
+	}
+
+	pub fn set_background_gradient(&mut self, top: [f32; 4], bottom: [f32; 4])
+	{
+		self.replace_background(Background::Gradient { top, bottom });
+	}
+
+	/*
+	*
+	*	Show the loaded environment map as the background. It stays the background across environment changes,
+	*	and while no environment is loaded the canvas is left transparent
+	*
+	*/
+	pub fn set_background_environment(&mut self) -> Result<(), String>
+	{
+		if !self.environment.has_maps()
+		{
+			return Err(String::from("No environment map is loaded to use as the background"));
+		}
+
+		self.replace_background(Background::Environment);
+
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Use six base64 encoded images as a skybox, given in the order +x, -x, +y, -y, +z, -z.
+	*	The faces must all be the same square size
+	*
+	*/
+	pub fn set_background_skybox(&mut self, faces: &Array) -> Result<(), String>
+	{
+		if faces.length() != 6
+		{
+			return Err(format!("A skybox needs 6 faces (+x, -x, +y, -y, +z, -z), found {}", faces.length()));
+		}
+
+		let mut images: Vec<(u32, Vec<u8>)> = Vec::with_capacity(6);
+		for (n, face) in faces.iter().enumerate()
+		{
+			let face_base64 = face.as_string().ok_or_else(|| format!("Skybox face {} is not a base64 string", n))?;
+			let (size, pixels) = decode_skybox_face(&face_base64).map_err(|e| format!("Skybox face {}: {}", n, e))?;
+			if let Some((first_size, _)) = images.first()
+			{
+				if size != *first_size
+				{
+					return Err(format!("Skybox face {} is {} pixels wide but face 0 is {}", n, size, first_size));
+				}
+			}
+			images.push((size, pixels));
+		}
+
+		let target = WebGl2RenderingContext::TEXTURE_CUBE_MAP;
+		let texture = self.context.create_texture().ok_or("failed to create skybox texture")?;
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + SKYBOX_UNIT);
+		self.context.bind_texture(target, Some(&texture));
+
+		for (face, (size, pixels)) in images.iter().enumerate()
+		{
+			let uploaded = self.context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array
+			(
+				WebGl2RenderingContext::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+				0,
+				WebGl2RenderingContext::RGBA8 as i32,
+				*size as i32,
+				*size as i32,
+				0,
+				WebGl2RenderingContext::RGBA,
+				WebGl2RenderingContext::UNSIGNED_BYTE,
+				Some(pixels)
+			);
+			if uploaded.is_err()
+			{
+				self.context.delete_texture(Some(&texture));
+				return Err(String::from("failed to send skybox data to the GPU"));
+			}
+		}
+
+		self.context.generate_mipmap(target);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR_MIPMAP_LINEAR as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+
+		rust_verbose(&format!("Loaded {} x {} skybox", images[0].0, images[0].0));
+		self.replace_background(Background::Skybox(texture));
+
+		return Ok(());
+	}
+
+	fn replace_background(&mut self, background: Background)
+	{
+		if let Background::Skybox(texture) = std::mem::replace(&mut self.background, background)
+		{
+			self.context.delete_texture(Some(&texture));
+		}
+	}
+
+	/*
+	*
+	*	The color the canvas is cleared to. Drawn backgrounds are opaque so the clear color doesn't matter under them
+	*
+	*/
+	pub(in super) fn get_clear_color(&self) -> [f32; 4]
+	{
+		match self.background
+		{
+			Background::Solid(color) => color,
+			_ => [0.0, 0.0, 0.0, 0.0]
+		}
+	}
+
+	/*
+	*
+	*	Draw the gradient, skybox or environment behind everything, leaving the depth buffer clear for the scene
+	*
+	*/
+	pub(in super) fn draw_background(&mut self)
+	{
+		let mode = match self.background
+		{
+			Background::Gradient { .. } => MODE_GRADIENT,
+			Background::Skybox(_) => MODE_SKYBOX,
+			Background::Environment if self.environment.has_maps() => MODE_ENVIRONMENT,
+			_ => return
+		};
+
+		if self.background_program.is_none()
+		{
+			rust_verbose("Building background shader...");
+			match self.build_builtin_program(FULLSCREEN_VERTEX_SHADER, BACKGROUND_FRAGMENT_SHADER)
+			{
+				Ok(program) => self.background_program = Some(program),
+				Err(e) =>
+				{
+					rust_error(&format!("Skipping background, falling back to transparent: {}", e));
+					self.replace_background(Background::Transparent);
+					return;
+				}
+			}
+			rust_verbose("...background shader build complete.");
+		}
+
+		if self.current_program != self.background_program
+		{
+			self.current_program = self.background_program.clone();
+			self.context.use_program(self.current_program.as_ref());
+			self.apply_frame_uniforms();
+		}
+		let program = self.current_program.as_ref().unwrap();
+
+		let mode_index = self.context.get_uniform_location(program, "u_background_mode");
+		self.context.uniform1i(mode_index.as_ref(), mode);
+
+		// Samplers of different types may never share a unit, so the skybox sampler always gets its own
+		let skybox_index = self.context.get_uniform_location(program, "u_skybox_map");
+		self.context.uniform1i(skybox_index.as_ref(), SKYBOX_UNIT as i32);
+
+		match &self.background
+		{
+			Background::Gradient { top, bottom } =>
+			{
+				let top_index = self.context.get_uniform_location(program, "u_gradient_top");
+				self.context.uniform4fv_with_f32_array(top_index.as_ref(), top);
+				let bottom_index = self.context.get_uniform_location(program, "u_gradient_bottom");
+				self.context.uniform4fv_with_f32_array(bottom_index.as_ref(), bottom);
+				let screen_rect_index = self.context.get_uniform_location(program, "u_screen_rect");
+				self.context.uniform4fv_with_f32_array(screen_rect_index.as_ref(), &self.get_screen_rect());
+			},
+			Background::Skybox(texture) =>
+			{
+				self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + SKYBOX_UNIT);
+				self.context.bind_texture(WebGl2RenderingContext::TEXTURE_CUBE_MAP, Some(texture));
+				self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+			},
+			_ => ()
+		}
+
+		let view_projection = m4_multiply(&self.projection_matrix, &self.camera_matrix);
+		let inverse_view_projection = m4_inverse(&view_projection).unwrap_or(view_projection);
+		let inverse_index = self.context.get_uniform_location(program, "u_inverse_view_projection");
+		self.context.uniform_matrix4fv_with_f32_array(inverse_index.as_ref(), false, &inverse_view_projection);
+
+		self.context.depth_mask(false);
+		self.context.disable(WebGl2RenderingContext::DEPTH_TEST);
+		self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+		self.context.enable(WebGl2RenderingContext::DEPTH_TEST);
+		self.context.depth_mask(true);
+	}
+}
+
+/*
+*
+*	Decode a base64 encoded skybox face into its width and RGBA pixels
+*
+*/
+fn decode_skybox_face(face_base64: &str) -> Result<(u32, Vec<u8>), String>
+{
+	let bytes = base64::engine::general_purpose::STANDARD
+		.decode(face_base64.trim())
+		.map_err(|e| "failed to decode base64: ".to_owned() + &e.to_string())?;
+
+	let image = decode_rgba8(&bytes, None).map_err(|e| e.to_string())?;
+
+	let (width, height) = image.dimensions();
+	if width != height
+	{
+		return Err(format!("faces must be square, found {} x {}", width, height));
+	}
+
+	return Ok((width, image.into_raw()));
+}
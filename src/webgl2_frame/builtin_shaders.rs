@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+
+/*
+*
+*	Shader chunks the engine provides to every program. User supplied chunks of the same name take precedence
+*
+*/
+pub fn builtin_chunks() -> HashMap<String, String>
+{
+	let mut chunks: HashMap<String, String> = HashMap::new();
+	chunks.insert(String::from("lighting"), String::from(LIGHTING_CHUNK));
+	chunks.insert(String::from("pbr"), String::from(PBR_CHUNK));
+
+	return chunks;
+}
+
+/*
+*
+*	Light uniforms fed by the engine and a Blinn-Phong shading function over them. Positions are in world space.
+*	With an environment map loaded its irradiance replaces the flat ambient color, and with shadows on the
+*	shadowed light is attenuated by a percentage closer filtered lookup into the shadow map
+*
+*/
+pub const LIGHTING_CHUNK: &str = r#"#define MAX_LIGHTS 8
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+uniform int u_light_count;
+uniform int u_light_type[MAX_LIGHTS];
+uniform vec3 u_light_color[MAX_LIGHTS];
+uniform vec3 u_light_position[MAX_LIGHTS];
+uniform vec3 u_light_direction[MAX_LIGHTS];
+uniform float u_light_range[MAX_LIGHTS];
+uniform vec2 u_light_cone[MAX_LIGHTS];
+uniform vec3 u_ambient_color;
+uniform vec3 u_camera_position;
+
+#ifdef HAS_ENVIRONMENT
+uniform samplerCube u_irradiance_map;
+uniform samplerCube u_prefiltered_map;
+uniform sampler2D u_brdf_lut;
+uniform float u_prefiltered_levels;
+uniform float u_environment_intensity;
+#endif
+
+#ifdef HAS_SHADOWS
+uniform highp sampler2DShadow u_shadow_map;
+uniform mat4 u_shadow_matrix;
+uniform int u_shadow_light;
+uniform float u_shadow_bias;
+uniform float u_shadow_normal_offset;
+#endif
+
+// Light arriving at a surface from all around it
+vec3 get_ambient(vec3 normal)
+{
+#ifdef HAS_ENVIRONMENT
+	return texture(u_irradiance_map, normal).rgb * u_environment_intensity;
+#else
+	return u_ambient_color;
+#endif
+}
+
+// The direction from a surface point towards light i, and how much of the light reaches the point
+vec3 get_light_direction(int i, vec3 position, out float attenuation)
+{
+	attenuation = 1.0;
+	if (u_light_type[i] == LIGHT_DIRECTIONAL)
+	{
+		return -u_light_direction[i];
+	}
+
+	vec3 to_light = u_light_position[i] - position;
+	float light_distance = length(to_light);
+	vec3 light_direction = to_light / max(light_distance, 0.00001);
+
+	if (u_light_range[i] > 0.0)
+	{
+		float falloff = clamp(1.0 - pow(light_distance / u_light_range[i], 4.0), 0.0, 1.0);
+		attenuation = falloff * falloff;
+	}
+
+	if (u_light_type[i] == LIGHT_SPOT)
+	{
+		attenuation *= smoothstep(u_light_cone[i].y, u_light_cone[i].x, dot(-light_direction, u_light_direction[i]));
+	}
+
+	return light_direction;
+}
+
+// How much of light i is unblocked at a surface point, averaged over a 3x3 block of shadow map texels
+float get_light_shadow(int i, vec3 position, vec3 normal)
+{
+#ifdef HAS_SHADOWS
+	if (i != u_shadow_light)
+	{
+		return 1.0;
+	}
+
+	// Pushing the lookup out along the normal keeps surfaces from shadowing themselves
+	vec4 shadow_position = u_shadow_matrix * vec4(position + normal * u_shadow_normal_offset, 1.0);
+	vec3 coords = shadow_position.xyz / shadow_position.w;
+	if (any(lessThan(coords, vec3(0.0))) || any(greaterThan(coords, vec3(1.0))))
+	{
+		return 1.0;
+	}
+
+	vec2 texel = 1.0 / vec2(textureSize(u_shadow_map, 0));
+	float lit = 0.0;
+	for (int x = -1; x <= 1; x++)
+	{
+		for (int y = -1; y <= 1; y++)
+		{
+			lit += texture(u_shadow_map, vec3(coords.xy + vec2(x, y) * texel, coords.z - u_shadow_bias));
+		}
+	}
+
+	return lit / 9.0;
+#else
+	return 1.0;
+#endif
+}
+
+vec3 blinn_phong(vec3 position, vec3 normal, vec3 diffuse_color, vec3 specular_color, float shininess)
+{
+	vec3 view_direction = normalize(u_camera_position - position);
+	vec3 color = get_ambient(normal) * diffuse_color;
+
+	for (int i = 0; i < MAX_LIGHTS; i++)
+	{
+		if (i >= u_light_count)
+		{
+			break;
+		}
+
+		float attenuation;
+		vec3 light_direction = get_light_direction(i, position, attenuation);
+		attenuation *= get_light_shadow(i, position, normal);
+
+		float diffuse = max(dot(normal, light_direction), 0.0);
+		float specular = 0.0;
+		if (diffuse > 0.0)
+		{
+			vec3 half_direction = normalize(light_direction + view_direction);
+			specular = pow(max(dot(normal, half_direction), 0.0), shininess);
+		}
+
+		color += u_light_color[i] * attenuation * (diffuse * diffuse_color + specular * specular_color);
+	}
+
+	return color;
+}
+"#;
+
+/*
+*
+*	Metallic-roughness shading with a GGX specular lobe, over the lights of the lighting chunk which must be
+*	included first. Light colors are treated as already divided by pi so both models light a white surface alike.
+*	Environment maps add split sum image based lighting in place of the ambient term
+*
+*/
+pub const PBR_CHUNK: &str = r#"#define PI 3.14159265359
+
+float distribution_ggx(float n_dot_h, float roughness)
+{
+	float alpha = roughness * roughness;
+	float alpha_squared = alpha * alpha;
+	float denominator = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+	return alpha_squared / (PI * denominator * denominator);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness)
+{
+	float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+	float view_term = n_dot_v / (n_dot_v * (1.0 - k) + k);
+	float light_term = n_dot_l / (n_dot_l * (1.0 - k) + k);
+	return view_term * light_term;
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0)
+{
+	return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Light from every direction at once, so rough surfaces see less of the grazing angle fresnel rise
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness)
+{
+	return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+vec3 metallic_roughness(vec3 position, vec3 normal, vec3 base_color, float metallic, float roughness, float occlusion)
+{
+	vec3 view_direction = normalize(u_camera_position - position);
+	float n_dot_v = max(dot(normal, view_direction), 0.0001);
+	roughness = clamp(roughness, 0.04, 1.0);
+
+	vec3 f0 = mix(vec3(0.04), base_color, metallic);
+	vec3 diffuse_color = base_color * (1.0 - metallic);
+
+#ifdef HAS_ENVIRONMENT
+	vec3 reflected = reflect(-view_direction, normal);
+	vec3 prefiltered = textureLod(u_prefiltered_map, reflected, roughness * (u_prefiltered_levels - 1.0)).rgb * u_environment_intensity;
+	vec2 brdf = texture(u_brdf_lut, vec2(n_dot_v, roughness)).rg;
+	vec3 ambient_fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+	vec3 color = ((1.0 - ambient_fresnel) * diffuse_color * get_ambient(normal) + prefiltered * (f0 * brdf.x + brdf.y)) * occlusion;
+#else
+	vec3 color = get_ambient(normal) * base_color * occlusion;
+#endif
+
+	for (int i = 0; i < MAX_LIGHTS; i++)
+	{
+		if (i >= u_light_count)
+		{
+			break;
+		}
+
+		float attenuation;
+		vec3 light_direction = get_light_direction(i, position, attenuation);
+		attenuation *= get_light_shadow(i, position, normal);
+		float n_dot_l = dot(normal, light_direction);
+		if (n_dot_l <= 0.0)
+		{
+			continue;
+		}
+
+		vec3 half_direction = normalize(light_direction + view_direction);
+		float n_dot_h = max(dot(normal, half_direction), 0.0);
+		float v_dot_h = max(dot(view_direction, half_direction), 0.0);
+
+		vec3 fresnel = fresnel_schlick(v_dot_h, f0);
+		vec3 specular = distribution_ggx(n_dot_h, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness) * fresnel / (4.0 * n_dot_v * n_dot_l);
+		vec3 diffuse = (1.0 - fresnel) * diffuse_color;
+
+		color += u_light_color[i] * attenuation * n_dot_l * (diffuse + PI * specular);
+	}
+
+	return color;
+}
+"#;
+
+/*
+*
+*	The lit shader used when the resources don't supply their own
+*
+*/
+pub const LIT_VERTEX_SHADER: &str = r#"#version 300 es
+in vec4 a_position;
+#ifdef HAS_NORMALS
+in vec3 a_normal;
+#endif
+#ifdef HAS_TANGENTS
+in vec4 a_tangent;
+out vec4 v_tangent;
+#endif
+#ifdef HAS_TEXCOORD
+in vec2 a_texcoord;
+out vec2 v_texcoord;
+#endif
+#ifdef HAS_VERTEX_COLOR
+in vec4 a_color;
+out vec4 v_color;
+#endif
+
+uniform mat4 u_projection_matrix;
+uniform mat4 u_camera_matrix;
+uniform mat4 u_model_matrix;
+uniform mat3 u_normal_matrix;
+
+out vec3 v_position;
+out vec3 v_normal;
+
+void main()
+{
+	vec4 world_position = u_model_matrix * a_position;
+	v_position = world_position.xyz / world_position.w;
+#ifdef HAS_NORMALS
+	v_normal = u_normal_matrix * a_normal;
+#else
+	v_normal = vec3(0.0);
+#endif
+#ifdef HAS_TANGENTS
+	// Tangents lie in the surface, so move with the model matrix rather than the normal matrix
+	v_tangent = vec4(mat3(u_model_matrix) * a_tangent.xyz, a_tangent.w);
+#endif
+#ifdef HAS_TEXCOORD
+	v_texcoord = a_texcoord;
+#endif
+#ifdef HAS_VERTEX_COLOR
+	v_color = a_color;
+#endif
+
+	gl_Position = u_projection_matrix * u_camera_matrix * world_position;
+}
+"#;
+
+pub const LIT_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+#include "lighting"
+#include "pbr"
+
+in vec3 v_position;
+in vec3 v_normal;
+#ifdef HAS_TANGENTS
+in vec4 v_tangent;
+#endif
+#ifdef HAS_TEXCOORD
+in vec2 v_texcoord;
+#endif
+#ifdef HAS_VERTEX_COLOR
+in vec4 v_color;
+#endif
+
+uniform vec4 u_base_color;
+uniform float u_metallic;
+uniform float u_roughness;
+uniform vec3 u_emissive;
+uniform float u_normal_scale;
+uniform float u_bump_scale;
+uniform float u_occlusion_strength;
+uniform vec3 u_specular_color;
+uniform float u_shininess;
+uniform float u_alpha_cutoff;
+
+#ifdef HAS_BASE_COLOR_MAP
+uniform sampler2D u_base_color_map;
+#endif
+#ifdef HAS_METALLIC_MAP
+uniform sampler2D u_metallic_map;
+#endif
+#ifdef HAS_ROUGHNESS_MAP
+uniform sampler2D u_roughness_map;
+#endif
+#ifdef HAS_NORMAL_MAP
+uniform sampler2D u_normal_map;
+#endif
+#ifdef HAS_OCCLUSION_MAP
+uniform sampler2D u_occlusion_map;
+#endif
+#ifdef HAS_EMISSIVE_MAP
+uniform sampler2D u_emissive_map;
+#endif
+#ifdef HAS_SPECULAR_MAP
+uniform sampler2D u_specular_map;
+#endif
+#ifdef HAS_OPACITY_MAP
+uniform sampler2D u_opacity_map;
+#endif
+#ifdef HAS_BUMP_MAP
+uniform sampler2D u_bump_map;
+#endif
+
+#ifdef WEIGHTED_OIT
+// Weighted blended order independent transparency: premultiplied color and revealage, and the weight sum
+layout(location = 0) out vec4 out_color;
+layout(location = 1) out float out_weight;
+#else
+out vec4 out_color;
+#endif
+
+#if defined(HAS_NORMAL_MAP) || defined(HAS_BUMP_MAP)
+// The tangent frame, with x along texture u and y up texture v. Built from the mesh's tangents when it has
+// them, otherwise from screen space derivatives, where the bitangent is flipped as texture v runs down the
+// image once uploaded
+mat3 get_tangent_frame(vec3 normal, vec3 position, vec2 texcoord)
+{
+#ifdef HAS_TANGENTS
+	vec3 tangent = normalize(v_tangent.xyz - normal * dot(normal, v_tangent.xyz));
+	vec3 bitangent = cross(normal, tangent) * v_tangent.w;
+	return mat3(tangent, bitangent, normal);
+#else
+	vec3 position_dx = dFdx(position);
+	vec3 position_dy = dFdy(position);
+	vec2 texcoord_dx = dFdx(texcoord);
+	vec2 texcoord_dy = dFdy(texcoord);
+
+	vec3 dy_perpendicular = cross(position_dy, normal);
+	vec3 dx_perpendicular = cross(normal, position_dx);
+	vec3 tangent = dy_perpendicular * texcoord_dx.x + dx_perpendicular * texcoord_dy.x;
+	vec3 bitangent = -(dy_perpendicular * texcoord_dx.y + dx_perpendicular * texcoord_dy.y);
+	float scale = inversesqrt(max(max(dot(tangent, tangent), dot(bitangent, bitangent)), 0.0000001));
+	return mat3(tangent * scale, bitangent * scale, normal);
+#endif
+}
+#endif
+
+#ifdef HAS_NORMAL_MAP
+vec3 perturb_normal(mat3 tangent_frame, vec2 texcoord)
+{
+	vec3 mapped = texture(u_normal_map, texcoord).xyz * 2.0 - 1.0;
+	mapped.xy *= u_normal_scale;
+	return normalize(tangent_frame * mapped);
+}
+#endif
+
+#ifdef HAS_BUMP_MAP
+// Tilt the normal against the slope of the height map from one texel to the next along u and up v
+vec3 bump_normal(mat3 tangent_frame, vec2 texcoord)
+{
+	vec2 texel = 1.0 / vec2(textureSize(u_bump_map, 0));
+	float height = texture(u_bump_map, texcoord).r;
+	float height_u = texture(u_bump_map, texcoord + vec2(texel.x, 0.0)).r;
+	float height_v = texture(u_bump_map, texcoord - vec2(0.0, texel.y)).r;
+	vec3 mapped = vec3((height - height_u) * u_bump_scale, (height - height_v) * u_bump_scale, 1.0);
+	return normalize(tangent_frame * mapped);
+}
+#endif
+
+void main()
+{
+	vec4 base_color = u_base_color;
+#ifdef HAS_VERTEX_COLOR
+	base_color *= v_color;
+#endif
+#ifdef HAS_BASE_COLOR_MAP
+	base_color *= texture(u_base_color_map, v_texcoord);
+#endif
+#ifdef HAS_OPACITY_MAP
+	base_color.a *= texture(u_opacity_map, v_texcoord).r;
+#endif
+#ifdef ALPHA_MASK
+	if (base_color.a < u_alpha_cutoff)
+	{
+		discard;
+	}
+#endif
+#ifndef ALPHA_BLEND
+	base_color.a = 1.0;
+#endif
+
+#ifdef HAS_NORMALS
+	vec3 normal = normalize(v_normal);
+	// Both faces are drawn, so light the back of a face as if it were the front
+	if (!gl_FrontFacing)
+	{
+		normal = -normal;
+	}
+#else
+	// Without normals fall back to flat shading from the screen space derivatives of the position,
+	// which always face the camera
+	vec3 normal = normalize(cross(dFdx(v_position), dFdy(v_position)));
+#endif
+#if defined(HAS_NORMAL_MAP) || defined(HAS_BUMP_MAP)
+	mat3 tangent_frame = get_tangent_frame(normal, v_position, v_texcoord);
+#endif
+#ifdef HAS_NORMAL_MAP
+	normal = perturb_normal(tangent_frame, v_texcoord);
+#elif defined(HAS_BUMP_MAP)
+	normal = bump_normal(tangent_frame, v_texcoord);
+#endif
+
+	vec3 emissive = u_emissive;
+#ifdef HAS_EMISSIVE_MAP
+	emissive *= texture(u_emissive_map, v_texcoord).rgb;
+#endif
+
+#ifdef MATERIAL_PBR
+	float metallic = u_metallic;
+	float roughness = u_roughness;
+#if defined(METALLIC_ROUGHNESS_PACKED) && defined(HAS_METALLIC_MAP)
+	vec4 metallic_roughness_sample = texture(u_metallic_map, v_texcoord);
+	roughness *= metallic_roughness_sample.g;
+	metallic *= metallic_roughness_sample.b;
+#else
+#ifdef HAS_METALLIC_MAP
+	metallic *= texture(u_metallic_map, v_texcoord).r;
+#endif
+#ifdef HAS_ROUGHNESS_MAP
+	roughness *= texture(u_roughness_map, v_texcoord).r;
+#endif
+#endif
+
+	float occlusion = 1.0;
+#ifdef HAS_OCCLUSION_MAP
+	occlusion = mix(1.0, texture(u_occlusion_map, v_texcoord).r, u_occlusion_strength);
+#endif
+
+	vec3 color = metallic_roughness(v_position, normal, base_color.rgb, metallic, roughness, occlusion);
+#else
+	vec3 specular_color = u_specular_color;
+#ifdef HAS_SPECULAR_MAP
+	specular_color *= texture(u_specular_map, v_texcoord).rgb;
+#endif
+	vec3 color = blinn_phong(v_position, normal, base_color.rgb, specular_color, u_shininess);
+#endif
+
+	color += emissive;
+#ifdef WEIGHTED_OIT
+	// Nearer and more opaque surfaces weigh more (McGuire and Bavoil 2013)
+	float alpha = base_color.a;
+	float weight = clamp(pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 0.01, 3000.0);
+	out_color = vec4(color * alpha * weight, alpha);
+	out_weight = alpha * weight;
+#else
+	out_color = vec4(color, base_color.a);
+#endif
+}
+"#;
+
+/*
+*
+*	A single triangle covering the screen at the far plane, drawn with drawArrays(TRIANGLES, 0, 3) and no
+*	attributes. v_ndc runs from -1 to 1 across the screen and v_texcoord from 0 to 1
+*
+*/
+pub const FULLSCREEN_VERTEX_SHADER: &str = r#"#version 300 es
+out vec2 v_ndc;
+out vec2 v_texcoord;
+
+void main()
+{
+	v_ndc = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2)) * 2.0 - 1.0;
+	v_texcoord = v_ndc * 0.5 + 0.5;
+	gl_Position = vec4(v_ndc, 1.0, 1.0);
+}
+"#;
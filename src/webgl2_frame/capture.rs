@@ -0,0 +1,640 @@
+use wasm_bindgen::prelude::*;
+use web_sys::WebGl2RenderingContext;
+use js_sys::Array;
+use js_sys::Reflect;
+use std::io::Cursor;
+use webgl_matrix::*;
+
+use crate::logger::*;
+use crate::matrix_helper::*;
+
+use super::WebGl2Frame;
+use super::background::Background;
+use super::render_target::RenderTarget;
+
+// Pixels rendered around each tile and thrown away, so blurs and FXAA don't show the seams between tiles
+const TILE_GUTTER: i32 = 64;
+
+// A wasm32 module can address at most 4 GiB and browsers often grant half that. The image buffer, the
+// tile being read back and the PNG being encoded are all held at once, so this caps the image at 256 MiB
+const MAX_CAPTURE_PIXELS: u64 = 8192 * 8192;
+
+/*
+*
+*	What goes behind the scene in a capture. Scene keeps the frame's own background
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptureBackground
+{
+	Scene,
+	Transparent,
+	Color([f32; 4])
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureOptions
+{
+	pub background: CaptureBackground
+}
+
+impl CaptureOptions
+{
+	/*
+	*
+	*	Read an options object of the form { background: "scene" | "transparent" | [r, g, b, a] }. Every key
+	*	is optional and undefined gives the defaults
+	*
+	*/
+	pub fn from_js(options: &JsValue) -> Result<Self, String>
+	{
+		let mut capture_options = Self { background: CaptureBackground::Scene };
+		if options.is_undefined() || options.is_null()
+		{
+			return Ok(capture_options);
+		}
+
+		let background = Reflect::get(options, &JsValue::from_str("background")).unwrap_or(JsValue::UNDEFINED);
+		if let Some(name) = background.as_string()
+		{
+			capture_options.background = match name.as_str()
+			{
+				"scene" => CaptureBackground::Scene,
+				"transparent" => CaptureBackground::Transparent,
+				_ => return Err(format!("Unknown capture background {}, expected scene, transparent or a color", name))
+			};
+		}
+		else if background.is_object()
+		{
+			let values: Vec<f32> = Array::from(&background).iter().filter_map(|value| value.as_f64()).map(|value| value as f32).collect();
+			capture_options.background = match values.as_slice()
+			{
+				[r, g, b] => CaptureBackground::Color([*r, *g, *b, 1.0]),
+				[r, g, b, a] => CaptureBackground::Color([*r, *g, *b, *a]),
+				_ => return Err(String::from("A capture background color needs 3 or 4 numbers"))
+			};
+		}
+
+		return Ok(capture_options);
+	}
+}
+
+/*
+*
+*	The part of a capture one tile keeps, from the bottom left as GL counts
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CaptureTile
+{
+	x: i32,
+	y: i32,
+	width: i32,
+	height: i32
+}
+
+/*
+*
+*	Where the frame is drawn while capturing instead of the canvas, and which part of the whole image it
+*	holds as the offset and size of a rectangle from 0 to 1, for effects that depend on the screen position
+*
+*/
+pub struct Capture
+{
+	target: Option<RenderTarget>,
+	screen_rect: [f32; 4]
+}
+
+impl Capture
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			target: None,
+			screen_rect: [0.0, 0.0, 1.0, 1.0]
+		}
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Render the current view offscreen at the given size and return it encoded as a PNG. Sizes beyond what
+	*	the device can render in one go are drawn as tiles with off center projections and stitched together.
+	*	The view is framed as it is on the canvas, so a different aspect ratio stretches it
+	*
+	*/
+	pub fn capture(&mut self, width: u32, height: u32, options: &CaptureOptions) -> Result<Vec<u8>, String>
+	{
+		rust_verbose(&format!("Capturing the view at {} x {}...", width, height));
+		let pixels = self.capture_pixels(width, height, options)?;
+		let png = encode_png(width, height, pixels)?;
+		rust_verbose(&format!("...capture complete, {} bytes of PNG.", png.len()));
+
+		return Ok(png);
+	}
+
+	/*
+	*
+	*	Capture the view from a number of steps around a full turn of the camera about the model's centre,
+	*	starting from the current view, as one PNG per frame
+	*
+	*/
+	pub fn capture_turntable(&mut self, width: u32, height: u32, frames: u32, options: &CaptureOptions) -> Result<Vec<Vec<u8>>, String>
+	{
+		let mut pngs: Vec<Vec<u8>> = Vec::new();
+		self.draw_turntable(width, height, frames, options, |_, pixels| {
+			pngs.push(encode_png(width, height, pixels)?);
+			Ok(())
+		})?;
+
+		return Ok(pngs);
+	}
+
+	/*
+	*
+	*	Capture a turntable as a single PNG of the frames laid out left to right then top to bottom, with the
+	*	given number of columns or as close to square as they fit with 0
+	*
+	*/
+	pub fn capture_sprite_sheet(&mut self, width: u32, height: u32, frames: u32, columns: u32, options: &CaptureOptions) -> Result<Vec<u8>, String>
+	{
+		let (columns, rows) = get_sprite_sheet_grid(frames, columns);
+		let sheet_width = width as u64 * columns as u64;
+		let sheet_height = height as u64 * rows as u64;
+		if sheet_width * sheet_height > MAX_CAPTURE_PIXELS
+		{
+			return Err(format!("A {} x {} sprite sheet is larger than the {} pixels allowed", sheet_width, sheet_height, MAX_CAPTURE_PIXELS));
+		}
+
+		let mut sheet: Vec<u8> = vec![0; sheet_width as usize * sheet_height as usize * 4];
+		self.draw_turntable(width, height, frames, options, |frame, pixels| {
+			copy_sprite(&mut sheet, columns, width, height, frame, &pixels);
+			Ok(())
+		})?;
+
+		return encode_png(sheet_width as u32, sheet_height as u32, sheet);
+	}
+
+	// Orbit the camera in equal steps, handing each frame's pixels over as it is captured
+	fn draw_turntable<F>(&mut self, width: u32, height: u32, frames: u32, options: &CaptureOptions, mut on_frame: F) -> Result<(), String>
+		where F: FnMut(u32, Vec<u8>) -> Result<(), String>
+	{
+		if frames == 0
+		{
+			return Err(String::from("A turntable needs at least 1 frame"));
+		}
+		rust_verbose(&format!("Capturing a {} frame turntable at {} x {}...", frames, width, height));
+
+		// Turning the scene one way about the vertical through its centre moves the camera round it the other
+		let world_matrices = self.scene_graph.get_world_matrices();
+		let centre = match self.get_world_bounds(&world_matrices)
+		{
+			Some(bounds) => [(bounds.min[0] + bounds.max[0]) * 0.5, (bounds.min[1] + bounds.max[1]) * 0.5, (bounds.min[2] + bounds.max[2]) * 0.5],
+			None => [0.0, 0.0, 0.0]
+		};
+		let to_origin = trs_matrix(&[-centre[0], -centre[1], -centre[2]], &[0.0, 0.0, 0.0], &[1.0, 1.0, 1.0]);
+
+		let camera_matrix = self.camera_matrix;
+		let mut result = Ok(());
+		for frame in 0..frames
+		{
+			let angle = -360.0 * frame as f32 / frames as f32;
+			let orbit = m4_multiply(&trs_matrix(&centre, &[0.0, angle, 0.0], &[1.0, 1.0, 1.0]), &to_origin);
+			self.camera_matrix = m4_multiply(&camera_matrix, &orbit);
+
+			result = self.capture_pixels(width, height, options).and_then(|pixels| on_frame(frame, pixels));
+			if result.is_err()
+			{
+				break;
+			}
+		}
+		self.camera_matrix = camera_matrix;
+		rust_verbose("...turntable capture complete.");
+
+		return result;
+	}
+
+	// Render the view to a top down RGBA buffer of the given size, leaving the frame as it was for the canvas
+	fn capture_pixels(&mut self, width: u32, height: u32, options: &CaptureOptions) -> Result<Vec<u8>, String>
+	{
+		if width == 0 || height == 0
+		{
+			return Err(String::from("A capture needs a width and height of at least 1"));
+		}
+		if width as u64 * height as u64 > MAX_CAPTURE_PIXELS
+		{
+			return Err(format!("A {} x {} capture is larger than the {} pixels allowed", width, height, MAX_CAPTURE_PIXELS));
+		}
+
+		let projection_matrix = self.projection_matrix;
+		let resolution = self.resolution;
+		let background = match options.background
+		{
+			CaptureBackground::Scene => None,
+			CaptureBackground::Transparent => Some(std::mem::replace(&mut self.background, Background::Transparent)),
+			CaptureBackground::Color(color) => Some(std::mem::replace(&mut self.background, Background::Solid(color)))
+		};
+		self.resolution = [width as f32, height as f32];
+
+		let pixels = self.draw_capture_tiles(width as i32, height as i32);
+
+		// Put everything back for the canvas, whether or not the capture worked
+		self.projection_matrix = projection_matrix;
+		self.resolution = resolution;
+		if let Some(background) = background
+		{
+			self.background = background;
+		}
+		self.capture.screen_rect = [0.0, 0.0, 1.0, 1.0];
+		RenderTarget::release(&self.context, &mut self.capture.target);
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+		return pixels;
+	}
+
+	// Draw every tile and read it back into one top down RGBA buffer
+	fn draw_capture_tiles(&mut self, width: i32, height: i32) -> Result<Vec<u8>, String>
+	{
+		let max_size = self.get_max_render_size();
+		let (tiles, gutter) = get_capture_tiles(width, height, max_size);
+		if tiles.len() > 1
+		{
+			rust_verbose(&format!("Capture is larger than the {} pixel render limit, drawing {} tiles", max_size, tiles.len()));
+		}
+
+		let projection_matrix = self.projection_matrix;
+		let mut pixels: Vec<u8> = vec![0; width as usize * height as usize * 4];
+
+		for tile in tiles
+		{
+			// Each tile renders its gutter too, which is cut away when reading it back
+			let render_x = tile.x - gutter;
+			let render_y = tile.y - gutter;
+			let render_width = tile.width + 2 * gutter;
+			let render_height = tile.height + 2 * gutter;
+
+			RenderTarget::prepare(&self.context, &mut self.capture.target, render_width, render_height, WebGl2RenderingContext::RGBA8, 0, true)?;
+			self.capture.screen_rect =
+			[
+				render_x as f32 / width as f32,
+				render_y as f32 / height as f32,
+				render_width as f32 / width as f32,
+				render_height as f32 / height as f32
+			];
+			self.projection_matrix = m4_multiply(&get_tile_matrix(&self.capture.screen_rect), &projection_matrix);
+
+			self.draw();
+
+			let mut tile_pixels: Vec<u8> = vec![0; tile.width as usize * tile.height as usize * 4];
+			self.bind_output_framebuffer();
+			self.context
+				.read_pixels_with_opt_u8_array
+				(
+					gutter, gutter, tile.width, tile.height,
+					WebGl2RenderingContext::RGBA, WebGl2RenderingContext::UNSIGNED_BYTE, Some(&mut tile_pixels)
+				)
+				.map_err(|_| "Failed to read the captured pixels back")?;
+
+			copy_tile(&mut pixels, width, height, &tile, &tile_pixels);
+		}
+
+		unpremultiply(&mut pixels);
+
+		return Ok(pixels);
+	}
+
+	// The largest square the device can render into and read back in one pass
+	fn get_max_render_size(&self) -> i32
+	{
+		let get_limit = |parameter: u32| self.context
+			.get_parameter(parameter)
+			.ok()
+			.and_then(|value| value.as_f64())
+			.unwrap_or(4096.0) as i32;
+
+		return get_limit(WebGl2RenderingContext::MAX_RENDERBUFFER_SIZE).min(get_limit(WebGl2RenderingContext::MAX_TEXTURE_SIZE));
+	}
+
+	// The size of whatever the frame is drawn to, the capture target or the canvas
+	pub(in super) fn get_output_size(&self) -> (i32, i32)
+	{
+		return match self.capture.target.as_ref()
+		{
+			Some(target) => (target.width, target.height),
+			None => (self.context.drawing_buffer_width(), self.context.drawing_buffer_height())
+		};
+	}
+
+	pub(in super) fn get_output_target(&self) -> Option<&RenderTarget>
+	{
+		return self.capture.target.as_ref();
+	}
+
+	pub(in super) fn bind_output_framebuffer(&self)
+	{
+		let framebuffer = self.capture.target.as_ref().map(|target| &target.framebuffer);
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer);
+	}
+
+	pub(in super) fn get_screen_rect(&self) -> [f32; 4]
+	{
+		return self.capture.screen_rect;
+	}
+}
+
+/*
+*
+*	Split a capture into tiles the device can render, row by row from the bottom left. A capture within the
+*	limit is one tile, anything larger is cut into squares that fit with a gutter either side, the last in
+*	each row and column keeping whatever is left over
+*
+*/
+fn get_capture_tiles(width: i32, height: i32, max_size: i32) -> (Vec<CaptureTile>, i32)
+{
+	let (tile_size, gutter) = if width <= max_size && height <= max_size
+	{
+		(width.max(height), 0)
+	}
+	else
+	{
+		((max_size - 2 * TILE_GUTTER).max(1), TILE_GUTTER)
+	};
+
+	let mut tiles: Vec<CaptureTile> = Vec::new();
+	for y in (0..height).step_by(tile_size as usize)
+	{
+		for x in (0..width).step_by(tile_size as usize)
+		{
+			tiles.push(CaptureTile { x, y, width: tile_size.min(width - x), height: tile_size.min(height - y) });
+		}
+	}
+
+	return (tiles, gutter);
+}
+
+// Copy a tile read back from GL, its rows bottom up, into its place in a top down image
+fn copy_tile(pixels: &mut [u8], width: i32, height: i32, tile: &CaptureTile, tile_pixels: &[u8])
+{
+	let row_bytes = tile.width as usize * 4;
+	for tile_row in 0..tile.height as usize
+	{
+		let image_row = (height - 1 - tile.y) as usize - tile_row;
+		let start = (image_row * width as usize + tile.x as usize) * 4;
+		pixels[start..start + row_bytes].copy_from_slice(&tile_pixels[tile_row * row_bytes..(tile_row + 1) * row_bytes]);
+	}
+}
+
+/*
+*
+*	The columns and rows of a sprite sheet of the given number of frames. 0 columns lays them out as close
+*	to square as they fit, and there are never more columns than frames
+*
+*/
+fn get_sprite_sheet_grid(frames: u32, columns: u32) -> (u32, u32)
+{
+	let columns = if columns == 0 { (frames as f64).sqrt().ceil() as u32 } else { columns.min(frames) }.max(1);
+
+	return (columns, frames.div_ceil(columns));
+}
+
+// Copy a top down frame into its place on a sprite sheet, filling rows left to right from the top
+fn copy_sprite(sheet: &mut [u8], columns: u32, width: u32, height: u32, frame: u32, pixels: &[u8])
+{
+	let sheet_width = (columns * width) as usize;
+	let x = (frame % columns * width) as usize;
+	let y = (frame / columns * height) as usize;
+	let row_bytes = width as usize * 4;
+	for row in 0..height as usize
+	{
+		let start = ((y + row) * sheet_width + x) * 4;
+		sheet[start..start + row_bytes].copy_from_slice(&pixels[row * row_bytes..(row + 1) * row_bytes]);
+	}
+}
+
+fn encode_png(width: u32, height: u32, pixels: Vec<u8>) -> Result<Vec<u8>, String>
+{
+	let image = image::RgbaImage::from_raw(width, height, pixels).ok_or("captured pixels don't match the image size")?;
+	let mut png: Vec<u8> = Vec::new();
+	image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).map_err(|e| format!("Failed to encode the capture as PNG: {}", e))?;
+
+	return Ok(png);
+}
+
+/*
+*
+*	Map the part of the screen a tile covers, given as a rectangle from 0 to 1, onto the whole of clip space
+*
+*/
+fn get_tile_matrix(screen_rect: &[f32; 4]) -> Mat4
+{
+	let [x, y, width, height] = *screen_rect;
+	let scale = [1.0 / width, 1.0 / height];
+	let offset = [(1.0 - 2.0 * x - width) / width, (1.0 - 2.0 * y - height) / height];
+
+	return
+	[
+		scale[0], 0.0, 0.0, 0.0,
+		0.0, scale[1], 0.0, 0.0,
+		0.0, 0.0, 1.0, 0.0,
+		offset[0], offset[1], 0.0, 1.0
+	];
+}
+
+/*
+*
+*	The frame holds colors already multiplied by their alpha, where PNG expects them apart. Only partly
+*	covered pixels change, at the edges of objects over a transparent background
+*
+*/
+fn unpremultiply(pixels: &mut [u8])
+{
+	for pixel in pixels.chunks_exact_mut(4)
+	{
+		let alpha = pixel[3] as u32;
+		if alpha > 0 && alpha < 255
+		{
+			for channel in &mut pixel[..3]
+			{
+				*channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// Which tile covers each pixel, failing if any is covered twice
+	fn get_coverage(tiles: &[CaptureTile], width: i32, height: i32) -> Vec<usize>
+	{
+		let mut coverage = vec![usize::MAX; (width * height) as usize];
+		for (index, tile) in tiles.iter().enumerate()
+		{
+			for y in tile.y..tile.y + tile.height
+			{
+				for x in tile.x..tile.x + tile.width
+				{
+					let pixel = (y * width + x) as usize;
+					assert_eq!(coverage[pixel], usize::MAX, "pixel {}, {} is in two tiles", x, y);
+					coverage[pixel] = index;
+				}
+			}
+		}
+
+		return coverage;
+	}
+
+	#[test]
+	fn captures_within_the_limit_are_one_tile()
+	{
+		let (tiles, gutter) = get_capture_tiles(1920, 1080, 4096);
+
+		assert_eq!(tiles, vec![CaptureTile { x: 0, y: 0, width: 1920, height: 1080 }]);
+		assert_eq!(gutter, 0);
+	}
+
+	#[test]
+	fn tiles_leave_room_for_the_gutter()
+	{
+		let max_size = 4096;
+		let (tiles, gutter) = get_capture_tiles(10000, 3000, max_size);
+
+		assert_eq!(gutter, TILE_GUTTER);
+		assert!(tiles.iter().all(|tile| tile.width + 2 * gutter <= max_size && tile.height + 2 * gutter <= max_size));
+	}
+
+	#[test]
+	fn non_square_captures_tile_with_partial_last_tiles()
+	{
+		// Tiles are 3968 square, leaving 2064 and 1080 over at the right and top
+		let (tiles, _) = get_capture_tiles(10000, 5048, 4096);
+
+		assert_eq!(tiles.len(), 6);
+		assert_eq!(tiles[0], CaptureTile { x: 0, y: 0, width: 3968, height: 3968 });
+		assert_eq!(tiles[2], CaptureTile { x: 7936, y: 0, width: 2064, height: 3968 });
+		assert_eq!(tiles[3], CaptureTile { x: 0, y: 3968, width: 3968, height: 1080 });
+		assert_eq!(tiles[5], CaptureTile { x: 7936, y: 3968, width: 2064, height: 1080 });
+	}
+
+	#[test]
+	fn tiles_cover_every_pixel_once()
+	{
+		// Leaves 3 pixel tiles once the gutters are taken off
+		let max_size = 2 * TILE_GUTTER + 3;
+		for (width, height) in [(140, 133), (132, 132), (1, 200), (301, 2), (150, 150)].iter()
+		{
+			let (tiles, _) = get_capture_tiles(*width, *height, max_size);
+			assert!(tiles.len() > 1);
+			let coverage = get_coverage(&tiles, *width, *height);
+			assert!(coverage.iter().all(|tile| *tile != usize::MAX), "{} x {} has uncovered pixels", width, height);
+		}
+	}
+
+	#[test]
+	fn tiles_are_copied_top_down()
+	{
+		// 4 pixel tiles, with a partial last row and column, each pixel's red holding its top down row and green its column
+		let (width, height) = (142, 133);
+		let max_size = 2 * TILE_GUTTER + 4;
+		let (tiles, _) = get_capture_tiles(width, height, max_size);
+		assert_eq!(tiles.len(), 36 * 34);
+
+		let mut pixels = vec![0; (width * height * 4) as usize];
+		for tile in &tiles
+		{
+			let mut tile_pixels: Vec<u8> = Vec::new();
+			for tile_row in 0..tile.height
+			{
+				for tile_column in 0..tile.width
+				{
+					let image_row = height - 1 - (tile.y + tile_row);
+					tile_pixels.extend_from_slice(&[image_row as u8, (tile.x + tile_column) as u8, 0, 255]);
+				}
+			}
+			copy_tile(&mut pixels, width, height, tile, &tile_pixels);
+		}
+
+		for (index, pixel) in pixels.chunks_exact(4).enumerate()
+		{
+			assert_eq!(pixel, &[(index / width as usize) as u8, (index % width as usize) as u8, 0, 255], "pixel {}", index);
+		}
+	}
+
+	#[test]
+	fn sprite_sheets_default_to_near_square()
+	{
+		assert_eq!(get_sprite_sheet_grid(36, 0), (6, 6));
+		assert_eq!(get_sprite_sheet_grid(10, 0), (4, 3));
+		assert_eq!(get_sprite_sheet_grid(2, 0), (2, 1));
+		assert_eq!(get_sprite_sheet_grid(1, 0), (1, 1));
+	}
+
+	#[test]
+	fn sprite_sheet_rows_hold_any_frames_left_over()
+	{
+		assert_eq!(get_sprite_sheet_grid(36, 12), (12, 3));
+		assert_eq!(get_sprite_sheet_grid(10, 4), (4, 3));
+		assert_eq!(get_sprite_sheet_grid(7, 1), (1, 7));
+	}
+
+	#[test]
+	fn sprite_sheet_columns_are_limited_to_the_frames()
+	{
+		assert_eq!(get_sprite_sheet_grid(5, 8), (5, 1));
+	}
+
+	#[test]
+	fn sprites_are_placed_left_to_right_then_top_down()
+	{
+		// Five 3 x 2 frames over 2 columns, each pixel's red holding its frame and green its place in the frame
+		let (width, height, frames) = (3, 2, 5);
+		let (columns, rows) = get_sprite_sheet_grid(frames, 2);
+		let sheet_width = (columns * width) as usize;
+		let mut sheet = vec![0; sheet_width * (rows * height) as usize * 4];
+
+		for frame in 0..frames
+		{
+			let pixels: Vec<u8> = (0..width * height).flat_map(|pixel| vec![frame as u8 + 1, pixel as u8, 0, 255]).collect();
+			copy_sprite(&mut sheet, columns, width, height, frame, &pixels);
+		}
+
+		for (index, pixel) in sheet.chunks_exact(4).enumerate()
+		{
+			let (x, y) = ((index % sheet_width) as u32, (index / sheet_width) as u32);
+			let frame = y / height * columns + x / width;
+			let expected = match frame < frames
+			{
+				true => [frame as u8 + 1, ((y % height) * width + x % width) as u8, 0, 255],
+				false => [0, 0, 0, 0]
+			};
+			assert_eq!(pixel, &expected, "sheet pixel {}, {}", x, y);
+		}
+	}
+
+	#[test]
+	fn tile_matrix_maps_the_tile_onto_clip_space()
+	{
+		let screen_rect = [0.25, 0.5, 0.5, 0.25];
+		let matrix = get_tile_matrix(&screen_rect);
+
+		// The tile's corners in whole image clip space, from -1 to 1
+		let bottom_left = [screen_rect[0] * 2.0 - 1.0, screen_rect[1] * 2.0 - 1.0, 0.5];
+		let top_right = [(screen_rect[0] + screen_rect[2]) * 2.0 - 1.0, (screen_rect[1] + screen_rect[3]) * 2.0 - 1.0, 0.5];
+
+		let corner = m4_transform_point(&matrix, &bottom_left);
+		assert!((corner[0] + 1.0).abs() < 1e-6 && (corner[1] + 1.0).abs() < 1e-6 && (corner[2] - 0.5).abs() < 1e-6, "{:?}", corner);
+		let corner = m4_transform_point(&matrix, &top_right);
+		assert!((corner[0] - 1.0).abs() < 1e-6 && (corner[1] - 1.0).abs() < 1e-6, "{:?}", corner);
+	}
+
+	#[test]
+	fn whole_screen_tile_matrix_is_the_identity()
+	{
+		assert_eq!(get_tile_matrix(&[0.0, 0.0, 1.0, 1.0]), Mat4::identity());
+	}
+}
@@ -0,0 +1,312 @@
+use base64::Engine;
+use js_sys::Map;
+use js_sys::Uint16Array;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlTexture;
+
+use crate::ibl::*;
+use crate::ibl::prefilter::*;
+use crate::logger::*;
+
+use super::WebGl2Frame;
+
+// Texture units after the material maps
+const IRRADIANCE_UNIT: u32 = 6;
+const PREFILTERED_UNIT: u32 = 7;
+const BRDF_LUT_UNIT: u32 = 8;
+const BACKGROUND_UNIT: u32 = 9;
+
+const IRRADIANCE_SIZE: usize = 16;
+const PREFILTERED_SIZE: usize = 64;
+const BRDF_LUT_SIZE: usize = 32;
+// Larger environments are halved until they fit, keeping the background texture to a sensible size
+const BACKGROUND_MAX_WIDTH: usize = 2048;
+
+/*
+*
+*	The GPU side of an environment map: diffuse irradiance and prefiltered specular cubemaps, the BRDF
+*	lookup table they are combined with, and the source image for drawing as a background
+*
+*/
+pub struct EnvironmentMaps
+{
+	irradiance: WebGlTexture,
+	prefiltered: WebGlTexture,
+	prefiltered_levels: usize,
+	brdf_lut: WebGlTexture,
+	background: WebGlTexture
+}
+
+pub struct EnvironmentLighting
+{
+	maps: Option<EnvironmentMaps>,
+	intensity: f32
+}
+
+impl EnvironmentLighting
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			maps: None,
+			intensity: 1.0
+		}
+	}
+
+	pub fn has_maps(&self) -> bool
+	{
+		return self.maps.is_some();
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Load the environment map from the resources if one is given. The current environment is kept otherwise,
+	*	so a scene update doesn't have to resend it
+	*
+	*/
+	pub(in super) fn load_environment(&mut self, resources: &Map) -> Result<(), String>
+	{
+		if let Some(hdr_base64) = resources.get(&JsValue::from_str("environment")).as_string()
+		{
+			self.set_environment(&hdr_base64)?;
+		}
+
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Replace the environment with a base64 encoded equirectangular Radiance .hdr image
+	*
+	*/
+	pub fn set_environment(&mut self, hdr_base64: &str) -> Result<(), String>
+	{
+		rust_info("Loading environment map...");
+		let bytes = base64::engine::general_purpose::STANDARD
+			.decode(hdr_base64.trim())
+			.map_err(|e| "Failed to decode base64 environment map: ".to_owned() + &e.to_string())?;
+		let image = EnvironmentImage::decode_hdr(&bytes)?;
+		rust_verbose(&format!("Environment map is {} x {}", image.width, image.height));
+
+		rust_verbose("Precomputing image based lighting...");
+		let irradiance = irradiance_cubemap(&image, IRRADIANCE_SIZE);
+		let prefiltered = prefiltered_cubemaps(&image, PREFILTERED_SIZE);
+		let brdf_lut = brdf_lut(BRDF_LUT_SIZE);
+		rust_verbose("...image based lighting precomputation complete.");
+
+		let background_image = image.mip_chain(BACKGROUND_MAX_WIDTH).pop().unwrap_or(image);
+
+		// Keep hold of each texture as it is created so a later failed upload doesn't leak the earlier ones
+		let mut uploaded: Vec<WebGlTexture> = Vec::new();
+		let mut track = |texture: Result<WebGlTexture, String>| -> Result<WebGlTexture, String>
+		{
+			let texture = texture?;
+			uploaded.push(texture.clone());
+			return Ok(texture);
+		};
+		let maps = (|| -> Result<EnvironmentMaps, String>
+		{
+			return Ok(EnvironmentMaps
+			{
+				irradiance: track(self.upload_cubemaps(&[irradiance]))?,
+				prefiltered_levels: prefiltered.len(),
+				prefiltered: track(self.upload_cubemaps(&prefiltered))?,
+				brdf_lut: track(self.upload_brdf_lut(&brdf_lut))?,
+				background: track(self.upload_background(&background_image))?
+			});
+		})();
+		let maps = match maps
+		{
+			Ok(maps) => maps,
+			Err(e) =>
+			{
+				for texture in &uploaded
+				{
+					self.context.delete_texture(Some(texture));
+				}
+				return Err(e);
+			}
+		};
+
+		self.clear_environment();
+		self.environment.maps = Some(maps);
+		rust_info("...environment map load complete.");
+
+		// Objects now need the HAS_ENVIRONMENT permutations
+		return self.build_program_permutations();
+	}
+
+	pub fn clear_environment(&mut self)
+	{
+		if let Some(maps) = self.environment.maps.take()
+		{
+			self.context.delete_texture(Some(&maps.irradiance));
+			self.context.delete_texture(Some(&maps.prefiltered));
+			self.context.delete_texture(Some(&maps.brdf_lut));
+			self.context.delete_texture(Some(&maps.background));
+		}
+	}
+
+	pub fn set_environment_intensity(&mut self, intensity: f32)
+	{
+		self.environment.intensity = intensity.max(0.0);
+	}
+
+	/*
+	*
+	*	Bind the environment textures to their units and feed the bound program the matching uniforms
+	*
+	*/
+	pub(in super) fn apply_environment_uniforms(&self)
+	{
+		let (program, maps) = match (self.current_program.as_ref(), self.environment.maps.as_ref())
+		{
+			(Some(program), Some(maps)) => (program, maps),
+			_ => return
+		};
+
+		let textures =
+		[
+			(IRRADIANCE_UNIT, WebGl2RenderingContext::TEXTURE_CUBE_MAP, &maps.irradiance, "u_irradiance_map"),
+			(PREFILTERED_UNIT, WebGl2RenderingContext::TEXTURE_CUBE_MAP, &maps.prefiltered, "u_prefiltered_map"),
+			(BRDF_LUT_UNIT, WebGl2RenderingContext::TEXTURE_2D, &maps.brdf_lut, "u_brdf_lut"),
+			(BACKGROUND_UNIT, WebGl2RenderingContext::TEXTURE_2D, &maps.background, "u_background_map")
+		];
+		for (unit, target, texture, sampler) in textures.iter()
+		{
+			self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + unit);
+			self.context.bind_texture(*target, Some(texture));
+
+			let sampler_index = self.context.get_uniform_location(program, sampler);
+			self.context.uniform1i(sampler_index.as_ref(), *unit as i32);
+		}
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+
+		let levels_index = self.context.get_uniform_location(program, "u_prefiltered_levels");
+		self.context.uniform1f(levels_index.as_ref(), maps.prefiltered_levels as f32);
+
+		let intensity_index = self.context.get_uniform_location(program, "u_environment_intensity");
+		self.context.uniform1f(intensity_index.as_ref(), self.environment.intensity);
+	}
+
+	/*
+	*
+	*	Upload a chain of cubemaps as the mip levels of one half float cubemap texture
+	*
+	*/
+	fn upload_cubemaps(&self, levels: &[CubeMap]) -> Result<WebGlTexture, String>
+	{
+		let target = WebGl2RenderingContext::TEXTURE_CUBE_MAP;
+		let texture = self.context.create_texture().ok_or("failed to create environment texture")?;
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + IRRADIANCE_UNIT);
+		self.context.bind_texture(target, Some(&texture));
+
+		for (level, cubemap) in levels.iter().enumerate()
+		{
+			for (face, pixels) in cubemap.faces.iter().enumerate()
+			{
+				let face_target = WebGl2RenderingContext::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32;
+				if let Err(e) = self.upload_rgb_half_float(face_target, level as i32, cubemap.size, cubemap.size, pixels)
+				{
+					self.context.delete_texture(Some(&texture));
+					return Err(e);
+				}
+			}
+		}
+
+		let min_filter = if levels.len() > 1 { WebGl2RenderingContext::LINEAR_MIPMAP_LINEAR } else { WebGl2RenderingContext::LINEAR };
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, min_filter as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAX_LEVEL, levels.len() as i32 - 1);
+
+		return Ok(texture);
+	}
+
+	fn upload_brdf_lut(&self, table: &[[f32; 2]]) -> Result<WebGlTexture, String>
+	{
+		let target = WebGl2RenderingContext::TEXTURE_2D;
+		let texture = self.context.create_texture().ok_or("failed to create BRDF lookup texture")?;
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + BRDF_LUT_UNIT);
+		self.context.bind_texture(target, Some(&texture));
+
+		let half_floats: Vec<u16> = table.iter().flatten().map(|value| f32_to_f16(*value)).collect();
+		let uploaded = self.context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view
+		(
+			target,
+			0,
+			WebGl2RenderingContext::RG16F as i32,
+			BRDF_LUT_SIZE as i32,
+			BRDF_LUT_SIZE as i32,
+			0,
+			WebGl2RenderingContext::RG,
+			WebGl2RenderingContext::HALF_FLOAT,
+			Some(&Uint16Array::from(&half_floats[..]))
+		);
+		if uploaded.is_err()
+		{
+			self.context.delete_texture(Some(&texture));
+			return Err(String::from("failed to send the BRDF lookup table to the GPU"));
+		}
+
+		self.set_linear_filtering(target, WebGl2RenderingContext::CLAMP_TO_EDGE);
+
+		return Ok(texture);
+	}
+
+	fn upload_background(&self, image: &EnvironmentImage) -> Result<WebGlTexture, String>
+	{
+		let target = WebGl2RenderingContext::TEXTURE_2D;
+		let texture = self.context.create_texture().ok_or("failed to create environment background texture")?;
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + BACKGROUND_UNIT);
+		self.context.bind_texture(target, Some(&texture));
+
+		if let Err(e) = self.upload_rgb_half_float(target, 0, image.width, image.height, &image.pixels)
+		{
+			self.context.delete_texture(Some(&texture));
+			return Err(e);
+		}
+		// Wrap around the horizon, clamp at the poles
+		self.set_linear_filtering(target, WebGl2RenderingContext::REPEAT);
+
+		return Ok(texture);
+	}
+
+	fn set_linear_filtering(&self, target: u32, wrap_s: u32)
+	{
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_S, wrap_s as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+	}
+
+	// RGBA rather than RGB keeps every row four byte aligned, whatever the width
+	fn upload_rgb_half_float(&self, target: u32, level: i32, width: usize, height: usize, pixels: &[[f32; 3]]) -> Result<(), String>
+	{
+		let mut half_floats: Vec<u16> = Vec::with_capacity(pixels.len() * 4);
+		for pixel in pixels
+		{
+			half_floats.extend(pixel.iter().map(|channel| f32_to_f16(*channel)));
+			half_floats.push(f32_to_f16(1.0));
+		}
+
+		return self.context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view
+		(
+			target,
+			level,
+			WebGl2RenderingContext::RGBA16F as i32,
+			width as i32,
+			height as i32,
+			0,
+			WebGl2RenderingContext::RGBA,
+			WebGl2RenderingContext::HALF_FLOAT,
+			Some(&Uint16Array::from(&half_floats[..]))
+		).map_err(|_| String::from("failed to send environment map data to the GPU"));
+	}
+}
@@ -0,0 +1,253 @@
+use wasm_bindgen::prelude::*;
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlActiveInfo;
+use web_sys::WebGlProgram;
+use js_sys::Array;
+use js_sys::Object;
+use js_sys::Reflect;
+
+use crate::logger::*;
+
+use super::WebGl2Frame;
+
+/*
+*
+*	An active attribute or uniform as reported by the linked program
+*
+*/
+#[derive(Clone, Debug)]
+pub struct ShaderInput
+{
+	pub name: String,
+	pub gl_type: u32,
+	pub size: i32,
+	pub location: Option<i32>
+}
+
+/*
+*
+*	An input the engine feeds to every program, with the GL types it is able to feed it as
+*
+*/
+struct ExpectedInput
+{
+	name: &'static str,
+	gl_types: &'static [u32],
+	required: bool
+}
+
+const EXPECTED_ATTRIBUTES: [ExpectedInput; 5] =
+[
+	ExpectedInput { name: "a_position", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3, WebGl2RenderingContext::FLOAT_VEC4], required: true },
+	ExpectedInput { name: "a_texcoord", gl_types: &[WebGl2RenderingContext::FLOAT_VEC2], required: false },
+	ExpectedInput { name: "a_color", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3, WebGl2RenderingContext::FLOAT_VEC4], required: false },
+	ExpectedInput { name: "a_normal", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3], required: false },
+	ExpectedInput { name: "a_tangent", gl_types: &[WebGl2RenderingContext::FLOAT_VEC4], required: false }
+];
+
+const EXPECTED_UNIFORMS: [ExpectedInput; 32] =
+[
+	ExpectedInput { name: "u_camera_matrix", gl_types: &[WebGl2RenderingContext::FLOAT_MAT4], required: true },
+	ExpectedInput { name: "u_projection_matrix", gl_types: &[WebGl2RenderingContext::FLOAT_MAT4], required: true },
+	ExpectedInput { name: "u_model_matrix", gl_types: &[WebGl2RenderingContext::FLOAT_MAT4], required: false },
+	ExpectedInput { name: "u_mouse_position", gl_types: &[WebGl2RenderingContext::FLOAT_VEC2], required: false },
+	ExpectedInput { name: "u_time", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_resolution", gl_types: &[WebGl2RenderingContext::FLOAT_VEC2], required: false },
+	ExpectedInput { name: "u_normal_matrix", gl_types: &[WebGl2RenderingContext::FLOAT_MAT3], required: false },
+	ExpectedInput { name: "u_light_count", gl_types: &[WebGl2RenderingContext::INT], required: false },
+	ExpectedInput { name: "u_light_type", gl_types: &[WebGl2RenderingContext::INT], required: false },
+	ExpectedInput { name: "u_light_color", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3], required: false },
+	ExpectedInput { name: "u_light_position", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3], required: false },
+	ExpectedInput { name: "u_light_direction", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3], required: false },
+	ExpectedInput { name: "u_light_range", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_light_cone", gl_types: &[WebGl2RenderingContext::FLOAT_VEC2], required: false },
+	ExpectedInput { name: "u_ambient_color", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3], required: false },
+	ExpectedInput { name: "u_camera_position", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3], required: false },
+	ExpectedInput { name: "u_base_color", gl_types: &[WebGl2RenderingContext::FLOAT_VEC4], required: false },
+	ExpectedInput { name: "u_metallic", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_roughness", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_emissive", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3], required: false },
+	ExpectedInput { name: "u_normal_scale", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_bump_scale", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_occlusion_strength", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_specular_color", gl_types: &[WebGl2RenderingContext::FLOAT_VEC3], required: false },
+	ExpectedInput { name: "u_shininess", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_alpha_cutoff", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_prefiltered_levels", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_environment_intensity", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_shadow_matrix", gl_types: &[WebGl2RenderingContext::FLOAT_MAT4], required: false },
+	ExpectedInput { name: "u_shadow_light", gl_types: &[WebGl2RenderingContext::INT], required: false },
+	ExpectedInput { name: "u_shadow_bias", gl_types: &[WebGl2RenderingContext::FLOAT], required: false },
+	ExpectedInput { name: "u_shadow_normal_offset", gl_types: &[WebGl2RenderingContext::FLOAT], required: false }
+];
+
+impl ShaderInput
+{
+	pub fn to_js(&self) -> JsValue
+	{
+		let out = Object::new();
+		let _ = Reflect::set(&out, &JsValue::from_str("name"), &JsValue::from_str(&self.name));
+		let _ = Reflect::set(&out, &JsValue::from_str("type"), &JsValue::from_str(gl_type_name(self.gl_type)));
+		let _ = Reflect::set(&out, &JsValue::from_str("size"), &JsValue::from(self.size));
+		let _ = Reflect::set(&out, &JsValue::from_str("location"), &self.location.map(JsValue::from).unwrap_or(JsValue::NULL));
+		return out.into();
+	}
+}
+
+impl WebGl2Frame
+{
+	pub fn get_active_attributes(&self) -> Vec<ShaderInput>
+	{
+		let program = match self.base_program.as_ref()
+		{
+			Some(program) => program,
+			None => return Vec::new()
+		};
+
+		return get_active_inputs(&self.context, program, WebGl2RenderingContext::ACTIVE_ATTRIBUTES, |n| self.context.get_active_attrib(program, n))
+			.into_iter()
+			.map(|mut input| {
+				input.location = Some(self.context.get_attrib_location(program, &input.name));
+				input
+			})
+			.collect();
+	}
+
+	pub fn get_active_uniforms(&self) -> Vec<ShaderInput>
+	{
+		let program = match self.base_program.as_ref()
+		{
+			Some(program) => program,
+			None => return Vec::new()
+		};
+
+		return get_active_inputs(&self.context, program, WebGl2RenderingContext::ACTIVE_UNIFORMS, |n| self.context.get_active_uniform(program, n));
+	}
+
+	/*
+	*
+	*	Look up the declared GL type of an active uniform. Array uniforms are reported by GL as `name[0]`
+	*
+	*/
+	pub(in super) fn get_active_uniform_type(&self, name: &str) -> Option<u32>
+	{
+		return self.base_program.as_ref().and_then(|program| get_uniform_type(&self.context, program, name));
+	}
+
+	/*
+	*
+	*	Build a JS object of the form { attributes: [...], uniforms: [...] } describing the linked program
+	*
+	*/
+	pub fn get_shader_inputs(&self) -> JsValue
+	{
+		let attributes: Array = self.get_active_attributes().iter().map(|input| input.to_js()).collect();
+		let uniforms: Array = self.get_active_uniforms().iter().map(|input| input.to_js()).collect();
+
+		let out = Object::new();
+		let _ = Reflect::set(&out, &JsValue::from_str("attributes"), &attributes);
+		let _ = Reflect::set(&out, &JsValue::from_str("uniforms"), &uniforms);
+		return out.into();
+	}
+
+	/*
+	*
+	*	Log the inputs of the newly linked program and warn about any the engine expects to feed but can't
+	*
+	*/
+	pub(in super) fn check_shader_inputs(&self)
+	{
+		let attributes = self.get_active_attributes();
+		let uniforms = self.get_active_uniforms();
+
+		for input in attributes.iter().chain(uniforms.iter())
+		{
+			rust_verbose(&format!("Program input {} is {} x{}", input.name, gl_type_name(input.gl_type), input.size));
+		}
+
+		check_expected_inputs("attribute", &EXPECTED_ATTRIBUTES, &attributes);
+		check_expected_inputs("uniform", &EXPECTED_UNIFORMS, &uniforms);
+	}
+}
+
+// The declared GL type of an active uniform of any program, such as a post effect's
+pub(in super) fn get_uniform_type(context: &WebGl2RenderingContext, program: &WebGlProgram, name: &str) -> Option<u32>
+{
+	return get_active_inputs(context, program, WebGl2RenderingContext::ACTIVE_UNIFORMS, |n| context.get_active_uniform(program, n))
+		.into_iter()
+		.find(|input| input.name == name || input.name.strip_suffix("[0]") == Some(name))
+		.map(|input| input.gl_type);
+}
+
+fn get_active_inputs<F>(context: &WebGl2RenderingContext, program: &WebGlProgram, parameter: u32, get_info: F) -> Vec<ShaderInput>
+	where F: Fn(u32) -> Option<WebGlActiveInfo>
+{
+	let count = context
+		.get_program_parameter(program, parameter)
+		.as_f64()
+		.unwrap_or(0.0) as u32;
+
+	let mut inputs: Vec<ShaderInput> = Vec::new();
+	for n in 0..count
+	{
+		if let Some(info) = get_info(n)
+		{
+			inputs.push(ShaderInput { name: info.name(), gl_type: info.type_(), size: info.size(), location: None });
+		}
+	}
+
+	return inputs;
+}
+
+fn check_expected_inputs(kind: &str, expected: &[ExpectedInput], inputs: &[ShaderInput])
+{
+	for expected_input in expected
+	{
+		// Array uniforms are reported by GL as `name[0]`
+		let array_name = format!("{}[0]", expected_input.name);
+		match inputs.iter().find(|input| input.name == expected_input.name || input.name == array_name)
+		{
+			Some(input) if !expected_input.gl_types.contains(&input.gl_type) =>
+			{
+				let expected_names: Vec<&str> = expected_input.gl_types.iter().map(|gl_type| gl_type_name(*gl_type)).collect();
+				rust_warn
+				(
+					&format!
+					(
+						"Shader {} {} is declared as {} but the engine feeds it as {}",
+						kind, input.name, gl_type_name(input.gl_type), expected_names.join(" or ")
+					)
+				);
+			},
+			Some(_) => (),
+			None if expected_input.required => rust_warn(&format!("Shader {} {} is missing or unused, nothing will be fed to it", kind, expected_input.name)),
+			None => rust_verbose(&format!("Shader {} {} is not used by the program", kind, expected_input.name))
+		}
+	}
+}
+
+pub fn gl_type_name(gl_type: u32) -> &'static str
+{
+	match gl_type
+	{
+		WebGl2RenderingContext::FLOAT => "float",
+		WebGl2RenderingContext::FLOAT_VEC2 => "vec2",
+		WebGl2RenderingContext::FLOAT_VEC3 => "vec3",
+		WebGl2RenderingContext::FLOAT_VEC4 => "vec4",
+		WebGl2RenderingContext::INT => "int",
+		WebGl2RenderingContext::INT_VEC2 => "ivec2",
+		WebGl2RenderingContext::INT_VEC3 => "ivec3",
+		WebGl2RenderingContext::INT_VEC4 => "ivec4",
+		WebGl2RenderingContext::UNSIGNED_INT => "uint",
+		WebGl2RenderingContext::BOOL => "bool",
+		WebGl2RenderingContext::FLOAT_MAT2 => "mat2",
+		WebGl2RenderingContext::FLOAT_MAT3 => "mat3",
+		WebGl2RenderingContext::FLOAT_MAT4 => "mat4",
+		WebGl2RenderingContext::SAMPLER_2D => "sampler2D",
+		WebGl2RenderingContext::SAMPLER_3D => "sampler3D",
+		WebGl2RenderingContext::SAMPLER_CUBE => "samplerCube",
+		WebGl2RenderingContext::SAMPLER_2D_SHADOW => "sampler2DShadow",
+		WebGl2RenderingContext::SAMPLER_2D_ARRAY => "sampler2DArray",
+		_ => "unknown"
+	}
+}
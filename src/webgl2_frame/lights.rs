@@ -0,0 +1,286 @@
+use wasm_bindgen::prelude::*;
+use js_sys::Array;
+use js_sys::Object;
+use js_sys::Reflect;
+use webgl_matrix::*;
+
+use crate::logger::*;
+use crate::matrix_helper::*;
+use crate::matrix_helper::normalize;
+
+use super::WebGl2Frame;
+
+// Must match MAX_LIGHTS in the built in lighting chunk
+pub const MAX_LIGHTS: usize = 8;
+
+// The camera attached directional light every frame starts with
+pub const HEADLIGHT: u32 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind
+{
+	Directional,
+	Point,
+	Spot
+}
+
+impl LightKind
+{
+	pub fn from_str(kind: &str) -> Result<Self, String>
+	{
+		match kind
+		{
+			"directional" => Ok(LightKind::Directional),
+			"point" => Ok(LightKind::Point),
+			"spot" => Ok(LightKind::Spot),
+			_ => Err(format!("Unknown light type {}, expected directional, point or spot", kind))
+		}
+	}
+
+	fn name(&self) -> &'static str
+	{
+		match self
+		{
+			LightKind::Directional => "directional",
+			LightKind::Point => "point",
+			LightKind::Spot => "spot"
+		}
+	}
+
+	// The LIGHT_* value the lighting chunk switches on
+	fn gl_value(&self) -> i32
+	{
+		match self
+		{
+			LightKind::Directional => 0,
+			LightKind::Point => 1,
+			LightKind::Spot => 2
+		}
+	}
+}
+
+/*
+*
+*	A light source. Directions point the way the light travels. Camera attached lights have their position
+*	and direction given in camera space so they follow the view, otherwise both are in world space.
+*	A range of 0 means the light doesn't fall off with distance
+*
+*/
+#[derive(Clone, Debug)]
+pub struct Light
+{
+	pub id: u32,
+	pub kind: LightKind,
+	pub color: [f32; 3],
+	pub intensity: f32,
+	pub position: [f32; 3],
+	pub direction: [f32; 3],
+	pub range: f32,
+	pub inner_angle: f32,
+	pub outer_angle: f32,
+	pub camera_attached: bool
+}
+
+impl Light
+{
+	pub fn new(id: u32, kind: LightKind) -> Self
+	{
+		Self
+		{
+			id,
+			kind,
+			color: [1.0, 1.0, 1.0],
+			intensity: 1.0,
+			position: [0.0, 0.0, 0.0],
+			direction: [0.0, 0.0, -1.0],
+			range: 0.0,
+			inner_angle: 20.0,
+			outer_angle: 30.0,
+			camera_attached: false
+		}
+	}
+
+	pub fn to_js(&self) -> JsValue
+	{
+		let out = Object::new();
+		let _ = Reflect::set(&out, &JsValue::from_str("id"), &JsValue::from(self.id));
+		let _ = Reflect::set(&out, &JsValue::from_str("type"), &JsValue::from_str(self.kind.name()));
+		let _ = Reflect::set(&out, &JsValue::from_str("color"), &js_sys::Float32Array::from(&self.color[..]));
+		let _ = Reflect::set(&out, &JsValue::from_str("intensity"), &JsValue::from(self.intensity));
+		let _ = Reflect::set(&out, &JsValue::from_str("position"), &js_sys::Float32Array::from(&self.position[..]));
+		let _ = Reflect::set(&out, &JsValue::from_str("direction"), &js_sys::Float32Array::from(&self.direction[..]));
+		let _ = Reflect::set(&out, &JsValue::from_str("range"), &JsValue::from(self.range));
+		let _ = Reflect::set(&out, &JsValue::from_str("inner_angle"), &JsValue::from(self.inner_angle));
+		let _ = Reflect::set(&out, &JsValue::from_str("outer_angle"), &JsValue::from(self.outer_angle));
+		let _ = Reflect::set(&out, &JsValue::from_str("camera_attached"), &JsValue::from(self.camera_attached));
+		return out.into();
+	}
+
+	// Position and direction in world space, given the inverse of the camera matrix for camera attached lights
+	fn get_world_space(&self, inverse_camera_matrix: &Mat4) -> ([f32; 3], [f32; 3])
+	{
+		if self.camera_attached
+		{
+			return
+			(
+				m4_transform_point(inverse_camera_matrix, &self.position),
+				normalize(&m4_transform_vector(inverse_camera_matrix, &self.direction))
+			);
+		}
+
+		return (self.position, normalize(&self.direction));
+	}
+}
+
+pub struct Lighting
+{
+	lights: Vec<Light>,
+	next_light_id: u32,
+	ambient_color: [f32; 3]
+}
+
+impl Lighting
+{
+	pub fn new() -> Self
+	{
+		let mut headlight = Light::new(HEADLIGHT, LightKind::Directional);
+		headlight.camera_attached = true;
+
+		Self
+		{
+			lights: vec![headlight],
+			next_light_id: HEADLIGHT + 1,
+			ambient_color: [0.1, 0.1, 0.1]
+		}
+	}
+}
+
+impl WebGl2Frame
+{
+	pub fn add_light(&mut self, kind: LightKind) -> Result<u32, String>
+	{
+		if self.lighting.lights.len() >= MAX_LIGHTS
+		{
+			return Err(format!("Can't add more than {} lights", MAX_LIGHTS));
+		}
+
+		let id = self.lighting.next_light_id;
+		self.lighting.next_light_id += 1;
+		self.lighting.lights.push(Light::new(id, kind));
+		rust_verbose(&format!("Added {} light {}", kind.name(), id));
+
+		return Ok(id);
+	}
+
+	pub fn remove_light(&mut self, id: u32) -> bool
+	{
+		let count = self.lighting.lights.len();
+		self.lighting.lights.retain(|light| light.id != id);
+
+		return self.lighting.lights.len() != count;
+	}
+
+	pub fn get_light_mut(&mut self, id: u32) -> Result<&mut Light, String>
+	{
+		return self.lighting.lights
+			.iter_mut()
+			.find(|light| light.id == id)
+			.ok_or_else(|| format!("No light with id {}", id));
+	}
+
+	pub fn list_lights(&self) -> Array
+	{
+		return self.lighting.lights.iter().map(Light::to_js).collect();
+	}
+
+	pub fn set_ambient_color(&mut self, color: [f32; 3])
+	{
+		self.lighting.ambient_color = color;
+	}
+
+	/*
+	*
+	*	The light that casts shadows, the first directional one. Returns its index in the light uniform arrays
+	*	and its world space direction
+	*
+	*/
+	pub(in super) fn get_shadow_light(&self) -> Option<(usize, [f32; 3])>
+	{
+		let inverse_camera_matrix = m4_inverse(&self.camera_matrix).unwrap_or(Mat4::identity());
+
+		return self.lighting.lights
+			.iter()
+			.enumerate()
+			.find(|(_, light)| light.kind == LightKind::Directional)
+			.map(|(index, light)| (index, light.get_world_space(&inverse_camera_matrix).1));
+	}
+
+	/*
+	*
+	*	Feed the lights to the bound program as uniform arrays, with camera attached lights moved into world space
+	*
+	*/
+	pub(in super) fn apply_light_uniforms(&self)
+	{
+		let program = match self.current_program.as_ref()
+		{
+			Some(program) => program,
+			None => return
+		};
+
+		let inverse_camera_matrix = m4_inverse(&self.camera_matrix).unwrap_or(Mat4::identity());
+
+		let mut types: Vec<i32> = Vec::new();
+		let mut colors: Vec<f32> = Vec::new();
+		let mut positions: Vec<f32> = Vec::new();
+		let mut directions: Vec<f32> = Vec::new();
+		let mut ranges: Vec<f32> = Vec::new();
+		let mut cones: Vec<f32> = Vec::new();
+
+		for light in &self.lighting.lights
+		{
+			let (position, direction) = light.get_world_space(&inverse_camera_matrix);
+
+			types.push(light.kind.gl_value());
+			colors.extend(light.color.iter().map(|channel| channel * light.intensity));
+			positions.extend_from_slice(&position);
+			directions.extend_from_slice(&direction);
+			ranges.push(light.range);
+			cones.push(light.inner_angle.to_radians().cos());
+			cones.push(light.outer_angle.max(light.inner_angle).to_radians().cos());
+		}
+
+		let count_index = self.context.get_uniform_location(program, "u_light_count");
+		self.context.uniform1i(count_index.as_ref(), types.len() as i32);
+
+		let ambient_index = self.context.get_uniform_location(program, "u_ambient_color");
+		self.context.uniform3fv_with_f32_array(ambient_index.as_ref(), &self.lighting.ambient_color);
+
+		let camera_position_index = self.context.get_uniform_location(program, "u_camera_position");
+		self.context.uniform3fv_with_f32_array(camera_position_index.as_ref(), &m4_transform_point(&inverse_camera_matrix, &[0.0, 0.0, 0.0]));
+
+		// GL rejects empty arrays, and a light count of 0 means the shader never reads them
+		if types.is_empty()
+		{
+			return;
+		}
+
+		let type_index = self.context.get_uniform_location(program, "u_light_type");
+		self.context.uniform1iv_with_i32_array(type_index.as_ref(), &types);
+
+		let color_index = self.context.get_uniform_location(program, "u_light_color");
+		self.context.uniform3fv_with_f32_array(color_index.as_ref(), &colors);
+
+		let position_index = self.context.get_uniform_location(program, "u_light_position");
+		self.context.uniform3fv_with_f32_array(position_index.as_ref(), &positions);
+
+		let direction_index = self.context.get_uniform_location(program, "u_light_direction");
+		self.context.uniform3fv_with_f32_array(direction_index.as_ref(), &directions);
+
+		let range_index = self.context.get_uniform_location(program, "u_light_range");
+		self.context.uniform1fv_with_f32_array(range_index.as_ref(), &ranges);
+
+		let cone_index = self.context.get_uniform_location(program, "u_light_cone");
+		self.context.uniform2fv_with_f32_array(cone_index.as_ref(), &cones);
+	}
+}
@@ -0,0 +1,97 @@
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::webgl2_wavefront_object::material::MaterialMap;
+use crate::webgl2_wavefront_object::sampler::TextureSampler;
+
+use super::WebGl2Frame;
+use super::scene::ObjectTarget;
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Update how the targeted objects sample a material map, or every map with None. Settings missing
+	*	from the options keep each map's current value. Returns how many objects were changed
+	*
+	*/
+	pub fn set_texture_sampler(&mut self, target: &ObjectTarget, map: Option<MaterialMap>, options: &JsValue) -> Result<usize, String>
+	{
+		let maps: Vec<MaterialMap> = match map
+		{
+			Some(map) => vec![map],
+			None => MaterialMap::ALL.to_vec()
+		};
+
+		let indices = self.resolve_object_target(target)?;
+		for n in &indices
+		{
+			for map in &maps
+			{
+				let sampler = TextureSampler::from_js(options, self.objects[*n].material.get_sampler(*map))?;
+				self.objects[*n].set_texture_sampler(&self.context, &mut self.texture_cache, *map, sampler)?;
+			}
+		}
+
+		return Ok(indices.len());
+	}
+
+	/*
+	*
+	*	Bind each of an object's material textures to its unit, point the matching samplers at them and
+	*	feed the material's factors to the bound program
+	*
+	*/
+	pub(in super) fn bind_material(&self, n: usize)
+	{
+		let program = match self.current_program.as_ref()
+		{
+			Some(program) => program,
+			None => return
+		};
+		let object = &self.objects[n];
+		let material = &object.material;
+
+		for map in MaterialMap::ALL.iter()
+		{
+			if let Some(texture) = object.get_map_texture(*map)
+			{
+				self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + map.unit());
+				self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+
+				let sampler_index = self.context.get_uniform_location(program, map.sampler());
+				self.context.uniform1i(sampler_index.as_ref(), map.unit() as i32);
+			}
+		}
+
+		let base_color_index = self.context.get_uniform_location(program, "u_base_color");
+		self.context.uniform4fv_with_f32_array(base_color_index.as_ref(), &material.base_color);
+
+		let metallic_index = self.context.get_uniform_location(program, "u_metallic");
+		self.context.uniform1f(metallic_index.as_ref(), material.metallic);
+
+		let roughness_index = self.context.get_uniform_location(program, "u_roughness");
+		self.context.uniform1f(roughness_index.as_ref(), material.roughness);
+
+		let emissive_index = self.context.get_uniform_location(program, "u_emissive");
+		self.context.uniform3fv_with_f32_array(emissive_index.as_ref(), &material.emissive);
+
+		let normal_scale_index = self.context.get_uniform_location(program, "u_normal_scale");
+		self.context.uniform1f(normal_scale_index.as_ref(), material.normal_scale);
+
+		let bump_scale_index = self.context.get_uniform_location(program, "u_bump_scale");
+		self.context.uniform1f(bump_scale_index.as_ref(), material.bump_scale);
+
+		let occlusion_strength_index = self.context.get_uniform_location(program, "u_occlusion_strength");
+		self.context.uniform1f(occlusion_strength_index.as_ref(), material.occlusion_strength);
+
+		let specular_color_index = self.context.get_uniform_location(program, "u_specular_color");
+		self.context.uniform3fv_with_f32_array(specular_color_index.as_ref(), &material.specular_color);
+
+		let shininess_index = self.context.get_uniform_location(program, "u_shininess");
+		self.context.uniform1f(shininess_index.as_ref(), material.shininess);
+
+		let alpha_cutoff_index = self.context.get_uniform_location(program, "u_alpha_cutoff");
+		self.context.uniform1f(alpha_cutoff_index.as_ref(), material.alpha_cutoff);
+	}
+}
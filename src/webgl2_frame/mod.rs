@@ -2,24 +2,78 @@ use crate::logger::*;
 use crate::utils::*;
 use crate::matrix_helper::*;
 use crate::webgl2_wavefront_object::*;
+use crate::webgl2_wavefront_object::material::*;
+use crate::webgl2_wavefront_object::texture_source::*;
+use crate::webgl2_wavefront_object::texture_cache::TextureCache;
+use crate::scene_graph::*;
+use uniforms::UniformValue;
+use preprocessor::ShaderFeatures;
+use selection::Selection;
+use selection::SelectionRole;
+use lights::Lighting;
+use environment::EnvironmentLighting;
+use background::Background;
+use shadows::Shadows;
+use render_modes::RenderModes;
+use transparency::Transparency;
+use antialias::Antialiasing;
+use post_process::PostProcessing;
+use ssao::AmbientOcclusion;
+use capture::Capture;
+use models::parse_materials;
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
+use web_sys::HtmlCanvasElement;
 use web_sys::WebGl2RenderingContext;
 use web_sys::WebGlProgram;
 use webgl_matrix::*;
 use js_sys::Map;
+use js_sys::Function;
 use std::collections::HashMap;
 
 #[wasm_bindgen]
 pub struct WebGl2Frame
 {
+	canvas: HtmlCanvasElement,
 	context: WebGl2RenderingContext,
-	program: Option<WebGlProgram>,
+	// The user's program built without any mesh features, which introspection and custom uniforms are checked against
+	base_program: Option<WebGlProgram>,
+	// Whichever program was bound last, by any pass
+	current_program: Option<WebGlProgram>,
+	programs: HashMap<ShaderFeatures, WebGlProgram>,
+	vert_source: String,
+	frag_source: String,
+	shader_chunks: HashMap<String, String>,
 	objects: Vec<WebGl2WavefrontObject>,
+	scene_graph: SceneGraph,
+	next_object_id: u32,
+	material_library: MaterialLibrary,
+	texture_cache: TextureCache,
 	largest: [f32; 3],
     smallest: [f32; 3],
-	camera_matrix: Mat4
+	camera_matrix: Mat4,
+	projection_matrix: Mat4,
+	resolution: [f32; 2],
+	mouse_position: [f32; 2],
+	time: f32,
+	custom_uniforms: HashMap<String, UniformValue>,
+	on_click: Option<Function>,
+	on_hover: Option<Function>,
+	hovered_object: Option<u32>,
+	selection: Selection,
+	lighting: Lighting,
+	flat_program: Option<WebGlProgram>,
+	environment: EnvironmentLighting,
+	background: Background,
+	background_program: Option<WebGlProgram>,
+	shadows: Shadows,
+	render_modes: RenderModes,
+	transparency: Transparency,
+	antialiasing: Antialiasing,
+	post_processing: PostProcessing,
+	ambient_occlusion: AmbientOcclusion,
+	capture: Capture
 } 
 
 #[wasm_bindgen]
@@ -32,33 +86,56 @@ impl WebGl2Frame
     	let canvas = document.get_element_by_id("glCanvas").unwrap();
     	let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>().map_err(|_| ()).unwrap();
 
+		// A stencil buffer is needed to outline selected objects
+		let antialiasing = Antialiasing::from_resources(&resources);
+		let context_options = js_sys::Object::new();
+		js_sys::Reflect::set(&context_options, &JsValue::from_str("stencil"), &JsValue::TRUE)?;
+		js_sys::Reflect::set(&context_options, &JsValue::from_str("antialias"), &JsValue::from(antialiasing.uses_context_antialias()))?;
+
 		let mut frame = 
 			Self 
 			{ 
-				context: canvas.get_context("webgl2")?.unwrap().dyn_into::<web_sys::WebGl2RenderingContext>()?,  
-				program: None,
+				context: canvas.get_context_with_context_options("webgl2", &context_options)?.unwrap().dyn_into::<web_sys::WebGl2RenderingContext>()?,  
+				canvas,
+				base_program: None,
+				current_program: None,
+				programs: HashMap::new(),
+				vert_source: String::new(),
+				frag_source: String::new(),
+				shader_chunks: HashMap::new(),
 				objects: Vec::new(),
+				scene_graph: SceneGraph::new(),
+				next_object_id: 0,
+				material_library: MaterialLibrary::new(),
+				texture_cache: TextureCache::new(),
 				largest: [0.0, 0.0, 0.0],
     			smallest: [0.0, 0.0, 0.0],
-				camera_matrix: Mat4::identity()
+				camera_matrix: Mat4::identity(),
+				projection_matrix: Mat4::identity(),
+				resolution: [0.0, 0.0],
+				mouse_position: [0.0, 0.0],
+				time: 0.0,
+				custom_uniforms: HashMap::new(),
+				on_click: None,
+				on_hover: None,
+				hovered_object: None,
+				selection: Selection::new(),
+				lighting: Lighting::new(),
+				flat_program: None,
+				environment: EnvironmentLighting::new(),
+				background: Background::Transparent,
+				background_program: None,
+				shadows: Shadows::new(),
+				render_modes: RenderModes::new(),
+				transparency: Transparency::new(),
+				antialiasing,
+				post_processing: PostProcessing::new(),
+				ambient_occlusion: AmbientOcclusion::new(),
+				capture: Capture::new()
 			};
 
-		rust_info(&"Loading shaders to memory...");
-		let vert_shader: &str = &(resources.get(&JsValue::from_str("vert_shader")).as_string().unwrap_or(String::from("bad_value")));
-		rust_super_verbose(&("Vertex Shader is: ".to_owned() + &vert_shader));
-		let frag_shader: &str = &(resources.get(&JsValue::from_str("frag_shader")).as_string().unwrap_or(String::from("bad_value")));
-		rust_super_verbose(&("Fragment Shader is: ".to_owned() + &frag_shader));
-		rust_info(&"...shaders load to memory complete.");
-
-		rust_info(&"Compiling shaders...");
-		let vert_shader = frame.compile_shader(WebGl2RenderingContext::VERTEX_SHADER, vert_shader)?;
-		let frag_shader = frame.compile_shader(WebGl2RenderingContext::FRAGMENT_SHADER, frag_shader)?;
-		rust_info(&"...shaders compilation complete.");
-
-		rust_info(&"Linking shaders...");
-		frame.link_program(&vert_shader, &frag_shader)?;
-		rust_info(&"...shaders linking complete");
-		frame.context.use_program(frame.program.as_ref());
+		frame.load_shaders(&resources)?;
+		frame.load_environment(&resources)?;
 
 		rust_info(&"Loading scene to memory...");
 		let scene: &str = &(resources.get(&JsValue::from_str("cube")).as_string().unwrap_or(String::from("bad_value")));
@@ -71,8 +148,9 @@ impl WebGl2Frame
 		//rust_super_verbose(&("...materials are:".to_owned() + &materials));
 
 		rust_info(&"Loading textures to memory...");
-		let textures: Option<HashMap<String, String>> = get_js_sys_map_to_hashmap(&resources, "textures");
+		let textures: Option<HashMap<String, TextureSource>> = get_texture_sources(&resources);
 		rust_info(&"...textures load to memory complete.");
+		frame.texture_cache.set_transcoder(resources.get(&JsValue::from_str("texture_transcoder")).dyn_into::<Function>().ok());
 
 		rust_verbose(&"Parsing scene...");
 		let objset = match wavefront_obj::obj::parse(scene)
@@ -83,22 +161,11 @@ impl WebGl2Frame
 		rust_verbose(&"...scene parsing complete.");
 
 		//If required parse the materials
-		let mtls: Option<wavefront_obj::mtl::MtlSet> = None;
-		if !materials.is_none()
-		{
-			rust_verbose(&"Parsing materials...");
-			let material_text = materials.unwrap().into_values().next().expect("bad_value");
-			let mtls = match wavefront_obj::mtl::parse(material_text)
-			{
-				Ok(mtls) => mtls,
-				Err(e) => panic!("{}", e)
-			};
-			rust_verbose(&"...Materials parsing complete.");
-			rust_info(&"...scene loading complete.");
-		}
+		let material_library = parse_materials(&materials)?;
+		rust_info("...scene loading complete.");
 
 		rust_info(&"Buffering scene to GPU...");
-		frame.buffer_scene(&objset, &mtls, &textures)?;
+		frame.buffer_scene(&objset, &material_library, &textures)?;
 		rust_info(&"...scene buffering complete.");
 
 		rust_super_verbose
@@ -131,15 +198,12 @@ impl WebGl2Frame
 				" to GPU"
 			)
 		);
-		let resolution_index = frame.context.get_uniform_location(frame.program.as_mut().unwrap(), "u_resolution");
-		frame.context.uniform2fv_with_f32_array(resolution_index.as_ref(), &resolution);
+		frame.resolution = resolution;
 
 		// Set up depth test
 		rust_verbose(&"Configuring GPU depth testing...");
 		frame.enable_depthtest()?;
 		rust_verbose(&"...configuration complete.");
-		
-		frame.context.clear_color(0.0, 0.0, 0.0, 0.0);
 
 		rust_info(&"Reseting the camera_matrix...");
 		let mut central_matrix = Mat4::identity(); //Create the translation matrix to centralise object ontop of camera
@@ -162,23 +226,10 @@ impl WebGl2Frame
 		{
 			object.marked_for_deletion = true;
 		}
+		self.scene_graph = SceneGraph::new();
 
-		rust_info(&"Loading shaders to memory...");
-		let vert_shader: &str = &(resources.get(&JsValue::from_str("vert_shader")).as_string().unwrap_or(String::from("bad_value")));
-		rust_super_verbose(&("Vertex Shader is: ".to_owned() + &vert_shader));
-		let frag_shader: &str = &(resources.get(&JsValue::from_str("frag_shader")).as_string().unwrap_or(String::from("bad_value")));
-		rust_super_verbose(&("Fragment Shader is: ".to_owned() + &frag_shader));
-		rust_info(&"...shaders load to memory complete.");
-
-		rust_info(&"Compiling shaders...");
-		let vert_shader = self.compile_shader(WebGl2RenderingContext::VERTEX_SHADER, vert_shader)?;
-		let frag_shader = self.compile_shader(WebGl2RenderingContext::FRAGMENT_SHADER, frag_shader)?;
-		rust_info(&"...shaders compilation complete.");
-
-		rust_info(&"Linking shaders...");
-		self.link_program(&vert_shader, &frag_shader)?;
-		rust_info(&"...shaders linking complete");
-		self.context.use_program(self.program.as_ref());
+		self.load_shaders(&resources)?;
+		self.load_environment(&resources)?;
 
 		rust_info(&"Loading scene to memory...");
 		let scene: &str = &(resources.get(&JsValue::from_str("cube")).as_string().unwrap_or(String::from("bad_value")));
@@ -191,8 +242,9 @@ impl WebGl2Frame
 
 		//Check if texture is available and load to memory if relevant
 		rust_info(&"Loading textures to memory...");
-		let textures: Option<HashMap<String, String>> = get_js_sys_map_to_hashmap(&resources, "textures");
+		let textures: Option<HashMap<String, TextureSource>> = get_texture_sources(&resources);
 		rust_info(&"...textures load to memory complete.");
+		self.texture_cache.set_transcoder(resources.get(&JsValue::from_str("texture_transcoder")).dyn_into::<Function>().ok());
 
 		rust_verbose(&"Parsing scene...");
 		let objset = match wavefront_obj::obj::parse(scene)
@@ -203,23 +255,12 @@ impl WebGl2Frame
 		rust_verbose(&"...scene parsing complete.");
 
 		//If required parse the materials
-		let mtls: Option<wavefront_obj::mtl::MtlSet> = None;
-		if !materials.is_none()
-		{
-			rust_verbose(&"Parsing materials...");
-			let material_text = materials.unwrap().into_values().next().expect("bad_value");
-			let mtls = match wavefront_obj::mtl::parse(material_text)
-			{
-				Ok(mtls) => mtls,
-				Err(e) => panic!("{}", e)
-			};
-			rust_verbose(&"...Materials parsing complete.");
-		}
+		let material_library = parse_materials(&materials)?;
 
 		rust_info(&"...scene loading complete.");
 
 		rust_info(&"Buffering scene to GPU...");
-		self.buffer_scene(&objset, &mtls, &textures)?;
+		self.buffer_scene(&objset, &material_library, &textures)?;
 		rust_info(&"...scene buffering complete.");
 
 		rust_verbose
@@ -252,15 +293,12 @@ impl WebGl2Frame
 				" to GPU"
 			)
 		);
-		let resolution_index = self.context.get_uniform_location(self.program.as_mut().unwrap(), "u_resolution");
-		self.context.uniform2fv_with_f32_array(resolution_index.as_ref(), &resolution);
+		self.resolution = resolution;
 
 		// Set up depth test
 		rust_verbose(&"Configuring GPU depth testing...");
 		self.enable_depthtest()?;
 		rust_verbose(&"...configuration complete.");
-		
-		self.context.clear_color(0.0, 0.0, 0.0, 0.0);
 
 		rust_info(&"Reseting the camera_matrix...");
 		let mut central_matrix = Mat4::identity(); //Create the translation matrix to centralise object ontop of camera
@@ -288,84 +326,221 @@ impl WebGl2Frame
 	{
 		// First pass - clean up any objects marked for deletion
 		let context = &self.context;
+		let texture_cache = &mut self.texture_cache;
+		let object_count = self.objects.len();
 		self.objects.retain_mut(|object| {
 			if object.marked_for_deletion {
 				rust_warn("Cleanup check");
-				object.cleanup(context);
+				object.cleanup(context, texture_cache);
 				false
 			} else {
 				true
 			}
 		});
+		if self.objects.len() != object_count
+		{
+			self.prune_selection();
+		}
+
+		// Resolve the scene graph transforms once for every object drawn this frame
+		let world_matrices = self.scene_graph.get_world_matrices();
+
+		// Render the shadow map first as it uses its own framebuffer
+		self.draw_shadow_map(&world_matrices);
 
-		// Clear the color & depth buffers before drawing
-		self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+		// As is the normal and depth pass SSAO works from
+		self.draw_normal_depth_pass(&world_matrices);
+
+		// With MSAA, FXAA or post effects the scene is drawn offscreen and copied to the canvas at the end
+		self.begin_scene_target();
+
+		// Clear the color, depth & stencil buffers before drawing, to the background color if it is a flat one
+		let [red, green, blue, alpha] = self.get_clear_color();
+		self.context.clear_color(red, green, blue, alpha);
+		self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT | WebGl2RenderingContext::STENCIL_BUFFER_BIT);
+
+		// Feed the per frame and custom uniforms to whichever program is still bound from the last frame
+		self.apply_frame_uniforms();
+
+		self.draw_background();
+
+		// Selected silhouettes are marked in the stencil buffer as they are drawn for the outline pass
+		let outline = self.needs_outline();
+		if outline
+		{
+			self.context.enable(WebGl2RenderingContext::STENCIL_TEST);
+		}
 
 		for n in 0..self.objects.len()
 		{
-			rust_super_super_verbose(&format!("Initiating draw call for object {}...", n));
-			rust_super_verbose(&("drawing ".to_owned() + self.objects[n].indices_size.to_string().as_str() + " indices"));
+			if !self.objects[n].visible
+			{
+				continue;
+			}
 
-			// If the object is untextured, just grab the position attribute for feeding from the model 
-			if !self.objects[n].vertex_buffer.is_none()
+			// Ghosted objects are drawn over the scene by the selection passes, hidden ones not at all
+			let role = self.selection.get_role(self.objects[n].id);
+			if role == SelectionRole::Ghosted || role == SelectionRole::Hidden
 			{
-				self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.objects[n].vertex_buffer.as_ref());
-				let position_attribute_location = self.context.get_attrib_location(self.program.as_ref().unwrap(), "a_position") as u32;
-				self.context.vertex_attrib_pointer_with_i32(position_attribute_location, 3, WebGl2RenderingContext::FLOAT, false, 0, 0);
-				self.context.enable_vertex_attrib_array(position_attribute_location);
-			// Else if there is a texture involved, grab the position and texture attributes
-			} else if !self.objects[n].vertex_and_texture_buffer.is_none()
+				continue;
+			}
+
+			// Pure wireframe and point objects are left entirely to the render mode passes, and blended
+			// objects to the transparent pass
+			let render_mode = self.get_render_mode(n);
+			if !render_mode.has_surface() || self.is_transparent(n)
 			{
-				self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.objects[n].vertex_and_texture_buffer.as_ref());				
-				
-				let position_attribute_location = self.context.get_attrib_location(self.program.as_ref().unwrap(), "a_position") as u32;
-				self.context.vertex_attrib_pointer_with_i32
-				(
-					position_attribute_location, //index
-					3, //size
-					WebGl2RenderingContext::FLOAT, //data type
-					false, //normalized
-					20, //stride
-					0 //offset
-				);
-
-				let texture_attribute_location = self.context.get_attrib_location(self.program.as_ref().unwrap(), "a_texcoord") as u32;
-				self.context.vertex_attrib_pointer_with_i32
-				(
-					texture_attribute_location, //index
-					2, //size
-					WebGl2RenderingContext::FLOAT, //data type
-					false, //normalized 
-					20, //stride
-					12 //offset
-				);
-
-				self.context.enable_vertex_attrib_array(position_attribute_location);
-				self.context.enable_vertex_attrib_array(texture_attribute_location);
+				continue;
 			}
 
+			rust_super_super_verbose(&format!("Initiating draw call for object {}...", n));
+			rust_super_verbose(&("drawing ".to_owned() + self.objects[n].indices_size.to_string().as_str() + " indices"));
 
-			// Bind the color buffer and set the shader attribute for it to read to
-			if !self.objects[n].color_buffer.is_none()
+			// Switch to the shader permutation built for this object's mesh features
+			if let Err(e) = self.use_program_for(self.get_features(&self.objects[n]))
 			{
-				self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.objects[n].color_buffer.as_ref());
-				let color_attribute_location = self.context.get_attrib_location(self.program.as_ref().unwrap(), "a_color") as u32;
-				self.context.vertex_attrib_pointer_with_i32(color_attribute_location, 4, WebGl2RenderingContext::FLOAT, false, 0, 0);
-				self.context.enable_vertex_attrib_array(color_attribute_location);
+				rust_error(&format!("Skipping draw call for object {}: {}", n, e));
+				continue;
 			}
 
-			// Bind the vertex indices buffer
-			if !self.objects[n].vertex_index_buffer.is_none()
+			if outline
 			{
-				self.context.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, self.objects[n].vertex_index_buffer.as_ref());
+				self.set_selection_stencil(role);
 			}
 
+			self.set_surface_state(render_mode);
+			self.bind_object(n, &world_matrices);
+
 			// Finally draw
-			self.context.draw_elements_with_f64(WebGl2RenderingContext::TRIANGLES, self.objects[n].indices_size as i32, WebGl2RenderingContext::UNSIGNED_SHORT, 0.0);
+			self.draw_object_elements(n);
 			
 			rust_super_super_verbose("...draw call complete.");
 		}
-		
+		self.reset_surface_state();
+
+		if outline
+		{
+			self.context.stencil_mask(0xff);
+			self.context.disable(WebGl2RenderingContext::STENCIL_TEST);
+		}
+
+		self.draw_render_mode_passes(&world_matrices);
+
+		// Blended under the objects already drawn, and under the transparent ones still to come
+		self.draw_ground_plane(&world_matrices);
+
+		self.draw_transparent_objects(&world_matrices, outline);
+
+		self.draw_selection_passes(&world_matrices);
+
+		self.end_scene_target();
+
+		// Passes bind programs of their own, but introspection and custom uniforms must still see the user's
+		debug_assert!(self.base_program.is_none() || self.base_program.as_ref() == self.programs.get(&ShaderFeatures::default()));
+	}
+
+	/*
+	*
+	*	Pass an object's transform to the bound program and point its attributes at the object's buffers
+	*
+	*/
+	fn bind_object(&self, n: usize, world_matrices: &[Mat4])
+	{
+		// Pass the object's transform to the GPU
+		let model_matrix = world_matrices.get(self.objects[n].node).copied().unwrap_or(Mat4::identity());
+		let model_matrix_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_model_matrix");
+		self.context.uniform_matrix4fv_with_f32_array(model_matrix_index.as_ref(), false, &model_matrix);
+
+		// Normals are moved to world space by the inverse transpose so non uniform scales keep them perpendicular
+		let normal_matrix_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_normal_matrix");
+		self.context.uniform_matrix3fv_with_f32_array(normal_matrix_index.as_ref(), false, &normal_matrix(&model_matrix));
+
+		// Clear out attributes left enabled by the previous object so they don't read from its buffers
+		self.unbind_attribute("a_texcoord");
+		self.unbind_attribute("a_color");
+		self.unbind_attribute("a_normal");
+		self.unbind_attribute("a_tangent");
+
+		// If the object is untextured, just grab the position attribute for feeding from the model 
+		if self.objects[n].vertex_buffer.is_some()
+		{
+			self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.objects[n].vertex_buffer.as_ref());
+			self.bind_attribute("a_position", 3, 0, 0);
+		// Else if there is a texture involved, grab the position and texture attributes
+		} else if self.objects[n].vertex_and_texture_buffer.is_some()
+		{
+			self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.objects[n].vertex_and_texture_buffer.as_ref());				
+			self.bind_attribute("a_position", 3, 20, 0);
+			self.bind_attribute("a_texcoord", 2, 20, 12);
+		}
+
+		// Bind the object's material textures and feed its material uniforms
+		self.bind_material(n);
+
+		// Bind the color buffer and set the shader attribute for it to read to
+		if self.objects[n].color_buffer.is_some()
+		{
+			self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.objects[n].color_buffer.as_ref());
+			self.bind_attribute("a_color", 4, 0, 0);
+		}
+
+		// Bind the normal buffer
+		if self.objects[n].normal_buffer.is_some()
+		{
+			self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.objects[n].normal_buffer.as_ref());
+			self.bind_attribute("a_normal", 3, 0, 0);
+		}
+
+		// Bind the tangent buffer
+		if self.objects[n].tangent_buffer.is_some()
+		{
+			self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.objects[n].tangent_buffer.as_ref());
+			self.bind_attribute("a_tangent", 4, 0, 0);
+		}
+
+		// Bind the vertex indices buffer
+		if self.objects[n].vertex_index_buffer.is_some()
+		{
+			self.context.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, self.objects[n].vertex_index_buffer.as_ref());
+		}
+	}
+
+	fn draw_object_elements(&self, n: usize)
+	{
+		self.context.draw_elements_with_f64(WebGl2RenderingContext::TRIANGLES, self.objects[n].indices_size as i32, WebGl2RenderingContext::UNSIGNED_SHORT, 0.0);
+	}
+
+	/*
+	*
+	*	Point a float attribute at the currently bound ARRAY_BUFFER. Attributes the program doesn't use are skipped
+	*
+	*/
+	fn bind_attribute(&self, name: &str, size: i32, stride: i32, offset: i32)
+	{
+		let location = match get_attribute_location(&self.context, self.current_program.as_ref().unwrap(), name)
+		{
+			Some(location) => location,
+			None => return
+		};
+
+		self.context.vertex_attrib_pointer_with_i32
+		(
+			location, //index
+			size, //size
+			WebGl2RenderingContext::FLOAT, //data type
+			false, //normalized
+			stride, //stride
+			offset //offset
+		);
+		self.context.enable_vertex_attrib_array(location);
+	}
+
+	fn unbind_attribute(&self, name: &str)
+	{
+		if let Some(location) = get_attribute_location(&self.context, self.current_program.as_ref().unwrap(), name)
+		{
+			self.context.disable_vertex_attrib_array(location);
+		}
 	}
 
 	fn window(&self) -> web_sys::Window 
@@ -386,15 +561,15 @@ impl WebGl2Frame
 	* TODO let user customise
 	*
 	*/
-	pub fn set_projection(&self)
+	pub fn set_projection(&mut self)
 	{
 		rust_info(&("Setting the projection matrix. Currently hard coded...".to_owned()));
-		let projection_matrix = Mat4::create_perspective(1.0471975511965976, 0.8260869565217391, 1.0, 2000.0);
-		let position_index = self.context.get_uniform_location(self.program.as_ref().unwrap(), "u_projection_matrix");
-		self.context.uniform_matrix4fv_with_f32_array(position_index.as_ref(), false, &projection_matrix);
+		self.projection_matrix = Mat4::create_perspective(std::f32::consts::FRAC_PI_3, 0.82608694, 1.0, 2000.0);
+		let position_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_projection_matrix");
+		self.context.uniform_matrix4fv_with_f32_array(position_index.as_ref(), false, &self.projection_matrix);
 		rust_info(&("...projection matri successfully set.".to_owned()));
 
-		m4_pretty_print_super_verbose("Projection Matrix", &projection_matrix);
+		m4_pretty_print_super_verbose("Projection Matrix", &self.projection_matrix);
 	}
 
 	/*
@@ -441,4 +616,23 @@ impl WebGl2Frame
 
 mod shaders;
 mod models;
-pub mod animations;
\ No newline at end of file
+mod uniforms;
+mod introspection;
+mod preprocessor;
+mod builtin_shaders;
+mod materials;
+mod environment;
+mod background;
+mod shadows;
+mod transparency;
+mod render_target;
+mod antialias;
+mod ssao;
+pub mod scene;
+pub mod picking;
+pub mod selection;
+pub mod lights;
+pub mod animations;
+pub mod render_modes;
+pub mod post_process;
+pub mod capture;
\ No newline at end of file
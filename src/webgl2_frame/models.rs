@@ -1,66 +1,433 @@
 use wavefront_obj::obj::ObjSet;
-use wavefront_obj::mtl::MtlSet;
+use wavefront_obj::obj::Object;
+use wavefront_obj::obj::Geometry;
+use wavefront_obj::obj::Primitive;
+use wavefront_obj::obj::VTNIndex;
 use std::collections::HashMap;
 
 use crate::logger::*;
+use crate::scene_graph::ROOT_NODE;
 use crate::webgl2_wavefront_object::WebGl2WavefrontObject;
+use crate::webgl2_wavefront_object::material::*;
+use crate::webgl2_wavefront_object::texture_source::*;
 
 use super::WebGl2Frame;
 
 impl WebGl2Frame
 {
-    pub(in super) fn buffer_scene(&mut self, objset: &ObjSet, mtls: &Option<MtlSet>, textures: &Option<HashMap<String, String>>) -> Result<(), String>
+    pub(in super) fn buffer_scene(&mut self, objset: &ObjSet, material_library: &MaterialLibrary, textures: &Option<HashMap<String, TextureSource>>) -> Result<(), String>
 	{
+		// Keep the materials and textures so objects added later can use them
+		self.material_library = material_library.clone();
+		self.texture_cache.set_sources(textures.clone());
+
+		self.buffer_objset(objset, ROOT_NODE)?;
+		self.set_projection();
+
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Buffer every object in the set under the given parent node and build the shader permutations they
+	*	need, returning the ids given to the new objects. Nothing from a set that fails part way is kept
+	*
+	*/
+	pub(in super) fn buffer_objset(&mut self, objset: &ObjSet, parent: usize) -> Result<Vec<u32>, String>
+	{
+		let first_added = self.objects.len();
+		let mut object_nodes: Vec<usize> = Vec::new();
+		let mut ids: Vec<u32> = Vec::new();
+
 		for n in 0..(&objset).objects.len()
 		{
 			//Ignore junk objects
 			if (&objset).objects[n].vertices.len() != 0	
 			{
-				rust_info(&("Buffering model ".to_owned() + n.to_string().as_str() + ": " + &objset.objects[n].name + "to GPU..."));
-				let mut tmp_obj: WebGl2WavefrontObject = WebGl2WavefrontObject::new(objset.objects[n].clone(), mtls.clone(), textures.clone())?;
-				let _ = tmp_obj.buffer(&self.context, &self.program);
-				self.update_l_and_s_values(&tmp_obj);
-				self.objects.push(tmp_obj);
-				rust_info(&"...model buffering complete.");
+				//Each OBJ object gets a node, with a child node per group if it has more than one
+				let object_node = self.scene_graph.add_node(&objset.objects[n].name, parent);
+				object_nodes.push(object_node);
+				let parts = split_object_by_group(&objset.objects[n]);
+				let part_count = parts.len();
+
+				for (group_name, part) in parts
+				{
+					let node = if part_count > 1
+					{
+						self.scene_graph.add_node(&group_name, object_node)
+					}
+					else
+					{
+						object_node
+					};
+
+					// Each material in the group is drawn as its own object on the group's node
+					for piece in split_object_by_material(part)
+					{
+						rust_info(&("Buffering model ".to_owned() + n.to_string().as_str() + ": " + &objset.objects[n].name + " (" + &group_name + ") to GPU..."));
+						match self.buffer_object(piece, node)
+						{
+							Ok(id) => ids.push(id),
+							Err(e) =>
+							{
+								self.discard_objects_from(first_added, &object_nodes);
+								return Err("Failed to buffer model ".to_owned() + &objset.objects[n].name + ": " + &e);
+							}
+						}
+						rust_info("...model buffering complete.");
+					}
+				}
 			}
 		}
 
-		self.set_projection();
+		if let Err(e) = self.build_program_permutations()
+		{
+			self.discard_objects_from(first_added, &object_nodes);
+			return Err(e);
+		}
 
-		return Ok(());
+		return Ok(ids);
+	}
+
+	/*
+	*
+	*	Release and drop every object from the given index on, along with the scene nodes made for them, to
+	*	undo a load that failed part way
+	*
+	*/
+	pub(in super) fn discard_objects_from(&mut self, first: usize, nodes: &[usize])
+	{
+		for mut object in self.objects.drain(first..)
+		{
+			object.cleanup(&self.context, &mut self.texture_cache);
+		}
+		for node in nodes
+		{
+			self.scene_graph.remove_node(*node);
+		}
+		self.reset_l_and_s_values();
+	}
+
+	/*
+	*
+	*	Buffer a single object to the GPU, attach it to a scene node and give it a new id
+	*
+	*/
+	pub(in super) fn buffer_object(&mut self, obj: Object, node: usize) -> Result<u32, String>
+	{
+		let mut tmp_obj: WebGl2WavefrontObject = WebGl2WavefrontObject::new(obj, &self.material_library)?;
+		tmp_obj.node = node;
+		tmp_obj.id = self.next_object_id;
+		self.next_object_id += 1;
+
+		if let Err(e) = tmp_obj.buffer(&self.context, &self.base_program, &mut self.texture_cache)
+		{
+			tmp_obj.cleanup(&self.context, &mut self.texture_cache);
+			return Err(e);
+		}
+
+		self.update_l_and_s_values(&tmp_obj);
+		let id = tmp_obj.id;
+		self.objects.push(tmp_obj);
+
+		return Ok(id);
+	}
+
+	/*
+	*
+	*	Recalculate the scene bounds from scratch, used once objects have been removed
+	*
+	*/
+	pub(in super) fn reset_l_and_s_values(&mut self)
+	{
+		self.largest = [0.0, 0.0, 0.0];
+		self.smallest = [0.0, 0.0, 0.0];
+
+		let bounds: Vec<([f32; 3], [f32; 3])> = self.objects
+			.iter()
+			.filter(|object| !object.marked_for_deletion)
+			.map(|object| (object.largest, object.smallest))
+			.collect();
+
+		for (largest, smallest) in bounds
+		{
+			self.update_l_and_s_bounds(&largest, &smallest);
+		}
 	}
 
 	// Checks the object's largest and smallest vertex positions and updates the frame if required. 
 	fn update_l_and_s_values(&mut self, obj: &WebGl2WavefrontObject)
+	{
+		self.update_l_and_s_bounds(&obj.largest, &obj.smallest);
+	}
+
+	// Checks the given largest and smallest vertex positions and updates the frame if required. 
+	fn update_l_and_s_bounds(&mut self, largest: &[f32; 3], smallest: &[f32; 3])
 	{
 		//Check and update x if required
-		if (obj.largest[0]) > self.largest[0]
+		if largest[0] > self.largest[0]
 		{
-			self.largest[0] = obj.largest[0] as f32;
+			self.largest[0] = largest[0];
 		} 
-		else if (obj.smallest[0] as f32) < self.smallest[0]
+		else if smallest[0] < self.smallest[0]
 		{
-			self.smallest[0] = obj.smallest[0] as f32;
+			self.smallest[0] = smallest[0];
 		}
 
 		//Check and update y if required
-		if (obj.largest[1] as f32) > self.largest[1]
+		if largest[1] > self.largest[1]
 		{
-			self.largest[1] = obj.largest[1] as f32;
+			self.largest[1] = largest[1];
 		} 
-		else if (obj.smallest[1] as f32) < self.smallest[1]
+		else if smallest[1] < self.smallest[1]
 		{
-			self.smallest[1] = obj.smallest[1] as f32;
+			self.smallest[1] = smallest[1];
 		}
 
 		//Check and update z if required
-		if (obj.largest[2] as f32) > self.largest[2]
+		if largest[2] > self.largest[2]
 		{
-			self.largest[2] = obj.largest[2] as f32;
+			self.largest[2] = largest[2];
 		} 
-		else if (obj.smallest[2] as f32) < self.smallest[2]
+		else if smallest[2] < self.smallest[2]
+		{
+			self.smallest[2] = smallest[2];
+		}
+	}
+}
+
+/*
+*
+*	Parse every MTL file in the materials resource into one library. Later files win on clashing names
+*
+*/
+pub(in super) fn parse_materials(materials: &Option<HashMap<String, String>>) -> Result<MaterialLibrary, String>
+{
+	let mut material_library = MaterialLibrary::new();
+
+	if let Some(materials) = materials
+	{
+		rust_verbose("Parsing materials...");
+		for (file_name, material_text) in materials
+		{
+			let library = parse_mtl(material_text).map_err(|e| "Failed to parse materials ".to_owned() + file_name + ": " + &e)?;
+			material_library.extend(library);
+		}
+		rust_verbose(&("...Materials parsing complete, found ".to_owned() + material_library.len().to_string().as_str() + " materials."));
+	}
+
+	return Ok(material_library);
+}
+
+/*
+*
+*	Split an OBJ object into one object per group. Shapes in several groups go to the first one listed and
+*	shapes without a group go to "default"
+*
+*/
+fn split_object_by_group(object: &Object) -> Vec<(String, Object)>
+{
+	let mut group_names: Vec<String> = Vec::new();
+	for geometry in &object.geometry
+	{
+		for shape in &geometry.shapes
+		{
+			let group_name = shape.groups.first().cloned().unwrap_or(String::from("default"));
+			if !group_names.contains(&group_name)
+			{
+				group_names.push(group_name);
+			}
+		}
+	}
+
+	if group_names.len() <= 1
+	{
+		let group_name = group_names.pop().unwrap_or(String::from("default"));
+		return vec![(group_name, object.clone())];
+	}
+
+	let mut parts: Vec<(String, Object)> = Vec::new();
+	for group_name in group_names
+	{
+		let geometry: Vec<Geometry> = object.geometry
+			.iter()
+			.map(|geometry| Geometry
+			{
+				material_name: geometry.material_name.clone(),
+				shapes: geometry.shapes
+					.iter()
+					.filter(|shape| shape.groups.first().map(|name| name.as_str()).unwrap_or("default") == group_name)
+					.cloned()
+					.collect()
+			})
+			.filter(|geometry| !geometry.shapes.is_empty())
+			.collect();
+		parts.push((group_name, compact_object(object, geometry)));
+	}
+
+	return parts;
+}
+
+/*
+*
+*	Split an object into one per material, merging the usemtl blocks that share a material, as only an
+*	object's first block is buffered
+*
+*/
+fn split_object_by_material(object: Object) -> Vec<Object>
+{
+	if object.geometry.len() <= 1
+	{
+		return vec![object];
+	}
+
+	let mut merged: Vec<Geometry> = Vec::new();
+	for geometry in &object.geometry
+	{
+		match merged.iter_mut().find(|merged| merged.material_name == geometry.material_name)
 		{
-			self.smallest[2] = obj.smallest[2] as f32;
+			Some(merged) => merged.shapes.extend(geometry.shapes.iter().cloned()),
+			None => merged.push(geometry.clone())
 		}
 	}
-}
\ No newline at end of file
+
+	return merged.into_iter().map(|geometry| compact_object(&object, vec![geometry])).collect();
+}
+
+/*
+*
+*	A copy of an object holding only the given geometry and the vertices, texture vertices and normals its
+*	shapes use, so each part of a split object only buffers its own. Indices are remapped to match
+*
+*/
+fn compact_object(object: &Object, mut geometry: Vec<Geometry>) -> Object
+{
+	let mut vertices = CompactList::new(&object.vertices);
+	let mut tex_vertices = CompactList::new(&object.tex_vertices);
+	let mut normals = CompactList::new(&object.normals);
+	let mut remap = |(vertex, tex_vertex, normal): VTNIndex| -> VTNIndex
+	{
+		return (vertices.remap(vertex), tex_vertex.map(|index| tex_vertices.remap(index)), normal.map(|index| normals.remap(index)));
+	};
+
+	for shape in geometry.iter_mut().flat_map(|geometry| geometry.shapes.iter_mut())
+	{
+		shape.primitive = match shape.primitive
+		{
+			Primitive::Point(a) => Primitive::Point(remap(a)),
+			Primitive::Line(a, b) => Primitive::Line(remap(a), remap(b)),
+			Primitive::Triangle(a, b, c) => Primitive::Triangle(remap(a), remap(b), remap(c))
+		};
+	}
+
+	return Object
+	{
+		name: object.name.clone(),
+		vertices: vertices.kept,
+		tex_vertices: tex_vertices.kept,
+		normals: normals.kept,
+		geometry
+	};
+}
+
+// The entries of a list that are used, in the order they are first used
+struct CompactList<'a, T: Copy>
+{
+	source: &'a [T],
+	positions: HashMap<usize, usize>,
+	kept: Vec<T>
+}
+
+impl<'a, T: Copy> CompactList<'a, T>
+{
+	fn new(source: &'a [T]) -> Self
+	{
+		return Self { source, positions: HashMap::new(), kept: Vec::new() };
+	}
+
+	fn remap(&mut self, index: usize) -> usize
+	{
+		let (source, kept) = (self.source, &mut self.kept);
+		return *self.positions.entry(index).or_insert_with(|| {
+			kept.push(source[index]);
+			kept.len() - 1
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const TWO_GROUPS: &str = "o thing
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 5 5 5
+v 6 5 5
+v 6 6 5
+vt 0 0
+vt 1 1
+usemtl red
+g first
+f 1/1 2/1 3/1
+g second
+f 4/2 5/2 6/2
+usemtl blue
+f 6/2 5/2 4/2
+";
+
+	fn get_triangles(object: &Object) -> Vec<[usize; 3]>
+	{
+		return object.geometry
+			.iter()
+			.flat_map(|geometry| geometry.shapes.iter())
+			.filter_map(|shape| match shape.primitive
+			{
+				Primitive::Triangle(a, b, c) => Some([a.0, b.0, c.0]),
+				_ => None
+			})
+			.collect();
+	}
+
+	#[test]
+	fn group_parts_only_keep_their_own_vertices()
+	{
+		let objset = wavefront_obj::obj::parse(TWO_GROUPS).unwrap();
+		let parts = split_object_by_group(&objset.objects[0]);
+
+		assert_eq!(parts.len(), 2);
+		let (first_name, first) = &parts[0];
+		let (second_name, second) = &parts[1];
+		assert_eq!((first_name.as_str(), second_name.as_str()), ("first", "second"));
+
+		assert_eq!(first.vertices.len(), 3);
+		assert_eq!(first.tex_vertices.len(), 1);
+		assert_eq!(second.vertices.len(), 3);
+		assert!(second.vertices.iter().all(|vertex| vertex.z == 5.0));
+		for triangle in get_triangles(second)
+		{
+			assert!(triangle.iter().all(|index| *index < 3));
+		}
+	}
+
+	#[test]
+	fn material_pieces_share_nothing_but_keep_their_faces()
+	{
+		let objset = wavefront_obj::obj::parse(TWO_GROUPS).unwrap();
+		let pieces = split_object_by_material(objset.objects[0].clone());
+
+		assert_eq!(pieces.len(), 2);
+		assert_eq!(pieces[0].geometry[0].material_name.as_deref(), Some("red"));
+		assert_eq!(pieces[0].vertices.len(), 6);
+		assert_eq!(get_triangles(&pieces[0]).len(), 2);
+
+		// The blue face is remapped onto the only three vertices it keeps
+		assert_eq!(pieces[1].vertices.len(), 3);
+		assert!(pieces[1].vertices.iter().all(|vertex| vertex.z == 5.0));
+		let mut triangle = get_triangles(&pieces[1])[0];
+		triangle.sort();
+		assert_eq!(triangle, [0, 1, 2]);
+	}
+}
@@ -0,0 +1,243 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::EventTarget;
+use web_sys::MouseEvent;
+use webgl_matrix::*;
+use js_sys::Function;
+use js_sys::Object;
+use js_sys::Reflect;
+
+use crate::logger::*;
+use crate::utils::*;
+use crate::matrix_helper::*;
+use crate::raycast::*;
+
+use super::WebGl2Frame;
+
+/*
+*
+*	What lies under a point on the canvas. Positions and normals are in world space
+*
+*/
+#[derive(Clone, Debug)]
+pub struct PickResult
+{
+	pub object_id: u32,
+	pub object_name: String,
+	pub node_name: String,
+	pub triangle: usize,
+	pub point: [f32; 3],
+	pub normal: [f32; 3],
+	pub uv: Option<[f32; 2]>,
+	pub distance: f32
+}
+
+impl PickResult
+{
+	pub fn to_js(&self) -> JsValue
+	{
+		let out = Object::new();
+		let _ = Reflect::set(&out, &JsValue::from_str("id"), &JsValue::from(self.object_id));
+		let _ = Reflect::set(&out, &JsValue::from_str("name"), &JsValue::from_str(&self.object_name));
+		let _ = Reflect::set(&out, &JsValue::from_str("node"), &JsValue::from_str(&self.node_name));
+		let _ = Reflect::set(&out, &JsValue::from_str("triangle"), &JsValue::from(self.triangle as u32));
+		let _ = Reflect::set(&out, &JsValue::from_str("point"), &js_sys::Float32Array::from(&self.point[..]));
+		let _ = Reflect::set(&out, &JsValue::from_str("normal"), &js_sys::Float32Array::from(&self.normal[..]));
+		let uv = match self.uv
+		{
+			Some(uv) => JsValue::from(js_sys::Float32Array::from(&uv[..])),
+			None => JsValue::NULL
+		};
+		let _ = Reflect::set(&out, &JsValue::from_str("uv"), &uv);
+		let _ = Reflect::set(&out, &JsValue::from_str("distance"), &JsValue::from(self.distance));
+		return out.into();
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Cast a ray from the camera through a point given in client (CSS pixel) coordinates, the same space
+	*	as the tracked mouse position, and return the closest visible surface it hits
+	*
+	*/
+	pub fn pick(&self, client_x: f32, client_y: f32) -> Option<PickResult>
+	{
+		let ray = self.get_pick_ray(client_x, client_y)?;
+		let world_matrices = self.scene_graph.get_world_matrices();
+
+		let mut closest: Option<(usize, RayHit)> = None;
+		for n in 0..self.objects.len()
+		{
+			let object = &self.objects[n];
+			if object.marked_for_deletion || !object.visible || !self.selection.is_pickable(object.id)
+			{
+				continue;
+			}
+
+			let model_matrix = world_matrices.get(object.node).copied().unwrap_or(Mat4::identity());
+			let inverse_model_matrix = match m4_inverse(&model_matrix)
+			{
+				Some(inverse) => inverse,
+				None => continue
+			};
+
+			// Move the ray into the object's space rather than moving every vertex into world space
+			let local_ray = Ray
+			{
+				origin: m4_transform_point(&inverse_model_matrix, &ray.origin),
+				direction: m4_transform_vector(&inverse_model_matrix, &ray.direction)
+			};
+
+			if let Some(hit) = object.mesh.intersect(&local_ray)
+			{
+				if closest.as_ref().map(|(_, closest_hit)| hit.t < closest_hit.t).unwrap_or(true)
+				{
+					closest = Some((n, hit));
+				}
+			}
+		}
+
+		let (n, hit) = closest?;
+		let object = &self.objects[n];
+		let model_matrix = world_matrices.get(object.node).copied().unwrap_or(Mat4::identity());
+
+		// Normals transform by the inverse transpose so non uniform scales keep them perpendicular
+		let mut inverse_transpose = m4_inverse(&model_matrix).unwrap_or(Mat4::identity());
+		inverse_transpose.transpose();
+		let normal = normalize(&m4_transform_vector(&inverse_transpose, &object.mesh.get_normal(&hit)));
+
+		let point = ray.at(hit.t);
+		let direction_length = dot(&ray.direction, &ray.direction).sqrt();
+
+		return Some
+		(
+			PickResult
+			{
+				object_id: object.id,
+				object_name: object.get_name().to_owned(),
+				node_name: self.scene_graph.get_node(object.node).map(|node| node.name.clone()).unwrap_or_default(),
+				triangle: hit.triangle,
+				point,
+				normal,
+				uv: object.mesh.get_uv(&hit),
+				distance: hit.t * direction_length
+			}
+		);
+	}
+
+	pub fn set_on_click(&mut self, callback: Option<Function>)
+	{
+		self.on_click = callback;
+	}
+
+	pub fn set_on_hover(&mut self, callback: Option<Function>)
+	{
+		self.on_hover = callback;
+		self.hovered_object = None;
+	}
+
+	/*
+	*
+	*	Unproject a client coordinate through the canvas into a world space ray from the near to the far plane
+	*
+	*/
+	fn get_pick_ray(&self, client_x: f32, client_y: f32) -> Option<Ray>
+	{
+		let rect = self.canvas.get_bounding_client_rect();
+		if rect.width() <= 0.0 || rect.height() <= 0.0
+		{
+			return None;
+		}
+
+		let ndc_x = ((client_x as f64 - rect.left()) / rect.width()) as f32 * 2.0 - 1.0;
+		let ndc_y = 1.0 - ((client_y as f64 - rect.top()) / rect.height()) as f32 * 2.0;
+
+		let view_projection = m4_multiply(&self.projection_matrix, &self.camera_matrix);
+		let inverse_view_projection = m4_inverse(&view_projection)?;
+
+		let near = m4_transform_point(&inverse_view_projection, &[ndc_x, ndc_y, -1.0]);
+		let far = m4_transform_point(&inverse_view_projection, &[ndc_x, ndc_y, 1.0]);
+
+		return Some(Ray { origin: near, direction: sub(&far, &near) });
+	}
+}
+
+/*
+*
+*	Listen for clicks and mouse movement over the canvas and forward the picked surface to the JS callbacks.
+*	Hover callbacks only fire when the object under the mouse changes
+*
+*/
+pub fn register_pick_listeners(frame_wrap: &Rc<RefCell<WebGl2Frame>>)
+{
+	let event_target: EventTarget = frame_wrap.borrow().canvas.clone().into();
+
+	//Clone the frame for the click closure
+	let frame_click = Rc::clone(frame_wrap);
+	let click_closure = Closure::wrap(Box::new(move |event: MouseEvent| {
+		let (callback, result) =
+		{
+			let frame = frame_click.borrow();
+			let callback = match frame.on_click.clone()
+			{
+				Some(callback) => callback,
+				None => return
+			};
+			let position = get_mouse_position(event).unwrap_or((0, 0));
+			(callback, frame.pick(position.0 as f32, position.1 as f32))
+		};
+
+		// The frame borrow is released first so the callback is free to call back into the engine
+		let argument = result.map(|result| result.to_js()).unwrap_or(JsValue::NULL);
+		if let Err(e) = callback.call1(&JsValue::NULL, &argument)
+		{
+			rust_error(&format!("Click callback failed: {:?}", e));
+		}
+	}) as Box<dyn FnMut(_)>);
+
+	//Clone the frame for the hover closure
+	let frame_hover = Rc::clone(frame_wrap);
+	let hover_closure = Closure::wrap(Box::new(move |event: MouseEvent| {
+		let (callback, result) =
+		{
+			let mut frame = frame_hover.borrow_mut();
+			let callback = match frame.on_hover.clone()
+			{
+				Some(callback) => callback,
+				None => return
+			};
+			let position = get_mouse_position(event).unwrap_or((0, 0));
+			let result = frame.pick(position.0 as f32, position.1 as f32);
+
+			let hovered_object = result.as_ref().map(|result| result.object_id);
+			if hovered_object == frame.hovered_object
+			{
+				return;
+			}
+			frame.hovered_object = hovered_object;
+
+			(callback, result)
+		};
+
+		let argument = result.map(|result| result.to_js()).unwrap_or(JsValue::NULL);
+		if let Err(e) = callback.call1(&JsValue::NULL, &argument)
+		{
+			rust_error(&format!("Hover callback failed: {:?}", e));
+		}
+	}) as Box<dyn FnMut(_)>);
+
+	event_target
+		.add_event_listener_with_callback("click", click_closure.as_ref().unchecked_ref())
+		.expect("Failed to add event listener");
+	event_target
+		.add_event_listener_with_callback("mousemove", hover_closure.as_ref().unchecked_ref())
+		.expect("Failed to add event listener");
+
+	// Prevent Rust from dropping the closures
+	click_closure.forget();
+	hover_closure.forget();
+}
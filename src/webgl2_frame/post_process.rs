@@ -0,0 +1,792 @@
+use wasm_bindgen::prelude::*;
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlProgram;
+use web_sys::WebGlTexture;
+use js_sys::Array;
+use js_sys::Object;
+use js_sys::Reflect;
+use std::collections::HashMap;
+
+use crate::logger::*;
+
+use super::WebGl2Frame;
+use super::builtin_shaders::FULLSCREEN_VERTEX_SHADER;
+use super::introspection::get_uniform_type;
+use super::preprocessor::preprocess;
+use super::render_target::RenderTarget;
+use super::uniforms::UniformValue;
+
+// The built in effects, one program switching on u_effect
+const POST_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+#define EFFECT_COPY 0
+#define EFFECT_TONE_MAPPING 1
+#define EFFECT_GAMMA 2
+#define EFFECT_VIGNETTE 3
+#define EFFECT_BLOOM_BRIGHT 4
+#define EFFECT_BLUR 5
+#define EFFECT_BLOOM_COMBINE 6
+#define EFFECT_AMBIENT_OCCLUSION 7
+
+#define TONE_MAPPING_LINEAR 0
+#define TONE_MAPPING_REINHARD 1
+#define TONE_MAPPING_ACES 2
+
+in vec2 v_texcoord;
+
+uniform sampler2D u_source_map;
+uniform sampler2D u_effect_map;
+uniform int u_effect;
+uniform int u_tone_mapping;
+uniform float u_exposure;
+uniform float u_gamma;
+uniform vec2 u_vignette;
+uniform vec4 u_screen_rect;
+uniform float u_bloom_threshold;
+uniform float u_bloom_intensity;
+uniform vec2 u_blur_step;
+
+out vec4 out_color;
+
+vec3 tone_map(vec3 color)
+{
+	color *= u_exposure;
+	if (u_tone_mapping == TONE_MAPPING_ACES)
+	{
+		// Narkowicz's fit of the ACES filmic curve
+		return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+	}
+	if (u_tone_mapping == TONE_MAPPING_REINHARD)
+	{
+		return color / (color + 1.0);
+	}
+	return clamp(color, 0.0, 1.0);
+}
+
+void main()
+{
+	vec4 color = texture(u_source_map, v_texcoord);
+
+	if (u_effect == EFFECT_TONE_MAPPING)
+	{
+		color.rgb = tone_map(color.rgb);
+	}
+	else if (u_effect == EFFECT_GAMMA)
+	{
+		color.rgb = pow(max(color.rgb, 0.0), vec3(1.0 / u_gamma));
+	}
+	else if (u_effect == EFFECT_VIGNETTE)
+	{
+		// Darkens towards the corners by the intensity, starting the smoothness in from them
+		vec2 uv = u_screen_rect.xy + v_texcoord * u_screen_rect.zw;
+		float distance_from_centre = length(uv - 0.5) * 1.41421356;
+		color.rgb *= 1.0 - u_vignette.x * smoothstep(1.0 - u_vignette.y, 1.0, distance_from_centre);
+	}
+	else if (u_effect == EFFECT_BLOOM_BRIGHT)
+	{
+		float brightness = max(color.r, max(color.g, color.b));
+		color.rgb *= max(brightness - u_bloom_threshold, 0.0) / max(brightness, 0.0001);
+	}
+	else if (u_effect == EFFECT_BLUR)
+	{
+		// Nine tap gaussian along u_blur_step, run once across and once down
+		float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+		color.rgb *= weights[0];
+		for (int i = 1; i < 5; i++)
+		{
+			color.rgb += texture(u_source_map, v_texcoord + u_blur_step * float(i)).rgb * weights[i];
+			color.rgb += texture(u_source_map, v_texcoord - u_blur_step * float(i)).rgb * weights[i];
+		}
+	}
+	else if (u_effect == EFFECT_BLOOM_COMBINE)
+	{
+		color.rgb += texture(u_effect_map, v_texcoord).rgb * u_bloom_intensity;
+	}
+	else if (u_effect == EFFECT_AMBIENT_OCCLUSION)
+	{
+		color.rgb *= texture(u_effect_map, v_texcoord).r;
+	}
+
+	out_color = color;
+}
+"#;
+
+// The u_effect values of the built in post program
+const EFFECT_COPY: i32 = 0;
+const EFFECT_TONE_MAPPING: i32 = 1;
+const EFFECT_GAMMA: i32 = 2;
+const EFFECT_VIGNETTE: i32 = 3;
+const EFFECT_BLOOM_BRIGHT: i32 = 4;
+pub(in super) const EFFECT_BLUR: i32 = 5;
+const EFFECT_BLOOM_COMBINE: i32 = 6;
+const EFFECT_AMBIENT_OCCLUSION: i32 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapper
+{
+	Linear,
+	Reinhard,
+	Aces
+}
+
+impl ToneMapper
+{
+	pub fn from_str(operator: &str) -> Result<Self, String>
+	{
+		match operator
+		{
+			"linear" => Ok(ToneMapper::Linear),
+			"reinhard" => Ok(ToneMapper::Reinhard),
+			"aces" => Ok(ToneMapper::Aces),
+			_ => Err(format!("Unknown tone mapping operator {}, expected linear, reinhard or aces", operator))
+		}
+	}
+
+	// The TONE_MAPPING_* value the post shader switches on
+	fn gl_value(&self) -> i32
+	{
+		match self
+		{
+			ToneMapper::Linear => 0,
+			ToneMapper::Reinhard => 1,
+			ToneMapper::Aces => 2
+		}
+	}
+}
+
+/*
+*
+*	What a post effect does and its settings. Ambient occlusion darkens creases and contacts from a normal
+*	and depth pass of the opaque objects, so it works the same whatever shader drew them. Custom effects
+*	run a user fragment shader, which gets the image so far as u_source_map, v_texcoord from the fullscreen
+*	triangle and the frame's u_time, u_resolution and u_mouse_position, along with any uniforms set on the
+*	effect itself
+*
+*/
+#[derive(Clone, Debug)]
+pub enum PostEffectKind
+{
+	ToneMapping { operator: ToneMapper, exposure: f32 },
+	Gamma { gamma: f32 },
+	Bloom { threshold: f32, intensity: f32, radius: f32 },
+	Vignette { intensity: f32, smoothness: f32 },
+	AmbientOcclusion { radius: f32, intensity: f32, blur: f32 },
+	Custom { program: WebGlProgram, uniforms: HashMap<String, UniformValue> }
+}
+
+impl PostEffectKind
+{
+	// A built in effect with its default settings. Custom effects are made from their shader instead
+	pub fn from_str(kind: &str) -> Result<Self, String>
+	{
+		match kind
+		{
+			"tone_mapping" => Ok(PostEffectKind::ToneMapping { operator: ToneMapper::Aces, exposure: 1.0 }),
+			"gamma" => Ok(PostEffectKind::Gamma { gamma: 2.2 }),
+			"bloom" => Ok(PostEffectKind::Bloom { threshold: 1.0, intensity: 0.5, radius: 1.0 }),
+			"vignette" => Ok(PostEffectKind::Vignette { intensity: 0.5, smoothness: 0.5 }),
+			"ssao" => Ok(PostEffectKind::AmbientOcclusion { radius: 0.1, intensity: 1.0, blur: 2.0 }),
+			_ => Err(format!("Unknown post effect {}, expected tone_mapping, gamma, bloom, vignette or ssao", kind))
+		}
+	}
+
+	fn name(&self) -> &'static str
+	{
+		match self
+		{
+			PostEffectKind::ToneMapping { .. } => "tone_mapping",
+			PostEffectKind::Gamma { .. } => "gamma",
+			PostEffectKind::Bloom { .. } => "bloom",
+			PostEffectKind::Vignette { .. } => "vignette",
+			PostEffectKind::AmbientOcclusion { .. } => "ssao",
+			PostEffectKind::Custom { .. } => "custom"
+		}
+	}
+}
+
+pub struct PostEffect
+{
+	pub id: u32,
+	pub enabled: bool,
+	pub kind: PostEffectKind
+}
+
+impl PostEffect
+{
+	pub fn to_js(&self) -> JsValue
+	{
+		let out = Object::new();
+		let _ = Reflect::set(&out, &JsValue::from_str("id"), &JsValue::from(self.id));
+		let _ = Reflect::set(&out, &JsValue::from_str("type"), &JsValue::from_str(self.kind.name()));
+		let _ = Reflect::set(&out, &JsValue::from_str("enabled"), &JsValue::from(self.enabled));
+		return out.into();
+	}
+}
+
+/*
+*
+*	The ordered chain of effects run over the finished frame, and the offscreen targets the frame is drawn
+*	into when there are effects or anti-aliasing to apply. Effects ping-pong between two targets on their
+*	way to the canvas, which are half float when the device can render to them so tone mapping gets the
+*	full range of the lighting
+*
+*/
+pub struct PostProcessing
+{
+	effects: Vec<PostEffect>,
+	next_effect_id: u32,
+	program: Option<WebGlProgram>,
+	float_targets: Option<bool>,
+	scene_target: Option<RenderTarget>,
+	resolve_target: Option<RenderTarget>,
+	targets: [Option<RenderTarget>; 2],
+	bloom_targets: [Option<RenderTarget>; 2]
+}
+
+impl PostProcessing
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			effects: Vec::new(),
+			next_effect_id: 0,
+			program: None,
+			float_targets: None,
+			scene_target: None,
+			resolve_target: None,
+			targets: [None, None],
+			bloom_targets: [None, None]
+		}
+	}
+
+	fn has_effects(&self) -> bool
+	{
+		return self.effects.iter().any(|effect| effect.enabled);
+	}
+
+	fn has_effect(&self, name: &str) -> bool
+	{
+		return self.effects.iter().any(|effect| effect.enabled && effect.kind.name() == name);
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Add a built in effect to the end of the chain
+	*
+	*/
+	pub fn add_post_effect(&mut self, kind: PostEffectKind) -> Result<u32, String>
+	{
+		if let PostEffectKind::Custom { .. } = kind
+		{
+			return Err(String::from("Custom post effects are added from their shader source"));
+		}
+
+		return Ok(self.push_post_effect(kind));
+	}
+
+	/*
+	*
+	*	Build a user fragment shader, which may include shader chunks, and add it to the end of the chain
+	*
+	*/
+	pub fn add_custom_post_effect(&mut self, frag_source: &str) -> Result<u32, String>
+	{
+		let frag_source = preprocess(frag_source, &self.shader_chunks, &[])?;
+		let program = self.build_builtin_program(FULLSCREEN_VERTEX_SHADER, &frag_source)?;
+
+		return Ok(self.push_post_effect(PostEffectKind::Custom { program, uniforms: HashMap::new() }));
+	}
+
+	fn push_post_effect(&mut self, kind: PostEffectKind) -> u32
+	{
+		let id = self.post_processing.next_effect_id;
+		self.post_processing.next_effect_id += 1;
+		rust_verbose(&format!("Added {} post effect {}", kind.name(), id));
+		self.post_processing.effects.push(PostEffect { id, enabled: true, kind });
+
+		return id;
+	}
+
+	pub fn remove_post_effect(&mut self, id: u32) -> bool
+	{
+		let position = match self.post_processing.effects.iter().position(|effect| effect.id == id)
+		{
+			Some(position) => position,
+			None => return false
+		};
+
+		if let PostEffectKind::Custom { program, .. } = self.post_processing.effects.remove(position).kind
+		{
+			self.context.delete_program(Some(&program));
+		}
+
+		return true;
+	}
+
+	pub fn clear_post_effects(&mut self)
+	{
+		let ids: Vec<u32> = self.post_processing.effects.iter().map(|effect| effect.id).collect();
+		for id in ids
+		{
+			self.remove_post_effect(id);
+		}
+	}
+
+	/*
+	*
+	*	Move an effect to the given position in the chain, clamped to the end
+	*
+	*/
+	pub fn move_post_effect(&mut self, id: u32, position: usize) -> Result<(), String>
+	{
+		let current = self.post_processing.effects
+			.iter()
+			.position(|effect| effect.id == id)
+			.ok_or_else(|| format!("No post effect with id {}", id))?;
+
+		let effect = self.post_processing.effects.remove(current);
+		let position = position.min(self.post_processing.effects.len());
+		self.post_processing.effects.insert(position, effect);
+
+		return Ok(());
+	}
+
+	pub fn get_post_effect_mut(&mut self, id: u32) -> Result<&mut PostEffect, String>
+	{
+		return self.post_processing.effects
+			.iter_mut()
+			.find(|effect| effect.id == id)
+			.ok_or_else(|| format!("No post effect with id {}", id));
+	}
+
+	pub fn list_post_effects(&self) -> Array
+	{
+		return self.post_processing.effects.iter().map(PostEffect::to_js).collect();
+	}
+
+	/*
+	*
+	*	Replace the settings of a built in effect, which must be of the same type
+	*
+	*/
+	pub fn set_post_effect_settings(&mut self, id: u32, settings: PostEffectKind) -> Result<(), String>
+	{
+		let effect = self.get_post_effect_mut(id)?;
+		if effect.kind.name() != settings.name() || matches!(settings, PostEffectKind::Custom { .. })
+		{
+			return Err(format!("Post effect {} is {}, not {}", id, effect.kind.name(), settings.name()));
+		}
+
+		effect.kind = settings;
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Validate a uniform against a custom effect's program and store it so it is fed every frame
+	*
+	*/
+	pub fn set_post_effect_uniform(&mut self, id: u32, name: &str, value: &JsValue) -> Result<(), String>
+	{
+		let context = self.context.clone();
+		let effect = self.get_post_effect_mut(id)?;
+		let (program, uniforms) = match &mut effect.kind
+		{
+			PostEffectKind::Custom { program, uniforms } => (program, uniforms),
+			_ => return Err(format!("Post effect {} is built in and takes its settings instead of uniforms", id))
+		};
+
+		let gl_type = get_uniform_type(&context, program, name)
+			.ok_or_else(|| format!("Uniform {} is not an active uniform of post effect {}", name, id))?;
+		let uniform = UniformValue::from_js(value, gl_type).map_err(|e| format!("Uniform {}: {}", name, e))?;
+		uniforms.insert(name.to_owned(), uniform);
+
+		return Ok(());
+	}
+
+	pub(in super) fn uses_post_effect(&self, name: &str) -> bool
+	{
+		return self.post_processing.has_effect(name);
+	}
+
+	pub(in super) fn disable_post_effects(&mut self, name: &str)
+	{
+		for effect in &mut self.post_processing.effects
+		{
+			if effect.kind.name() == name
+			{
+				effect.enabled = false;
+			}
+		}
+	}
+
+	// With nothing to apply afterwards the scene is drawn straight to the canvas
+	fn renders_offscreen(&self) -> bool
+	{
+		return self.antialiasing.renders_offscreen() || self.post_processing.has_effects();
+	}
+
+	/*
+	*
+	*	Point drawing at the offscreen scene target when anti-aliasing or post effects need one, creating
+	*	it or matching it to the output size first, otherwise straight at the output
+	*
+	*/
+	pub(in super) fn begin_scene_target(&mut self)
+	{
+		let (width, height) = self.get_output_size();
+		self.context.viewport(0, 0, width, height);
+
+		if !self.renders_offscreen()
+		{
+			self.delete_scene_targets();
+			self.bind_output_framebuffer();
+			return;
+		}
+
+		if let Err(e) = self.prepare_scene_targets()
+		{
+			rust_error(&format!("Turning off MSAA, FXAA and post effects: {}", e));
+			self.antialiasing.disable();
+			for effect in &mut self.post_processing.effects
+			{
+				effect.enabled = false;
+			}
+			self.delete_scene_targets();
+			self.bind_output_framebuffer();
+			return;
+		}
+
+		self.bind_scene_framebuffer();
+	}
+
+	// Passes that render elsewhere use this to return to the scene
+	pub(in super) fn bind_scene_framebuffer(&self)
+	{
+		match self.post_processing.scene_target.as_ref()
+		{
+			Some(target) => self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&target.framebuffer)),
+			None => self.bind_output_framebuffer()
+		}
+	}
+
+	/*
+	*
+	*	Resolve the multisampled scene and run the post effects and FXAA over it on the way to the output
+	*
+	*/
+	pub(in super) fn end_scene_target(&mut self)
+	{
+		let scene_target = match self.post_processing.scene_target.as_ref()
+		{
+			Some(target) => target,
+			None => return
+		};
+
+		let effects: Vec<usize> = (0..self.post_processing.effects.len())
+			.filter(|n| self.post_processing.effects[*n].enabled)
+			.collect();
+		let fxaa = self.antialiasing.uses_fxaa();
+		if effects.is_empty() && !fxaa
+		{
+			scene_target.blit_to(&self.context, self.get_output_target());
+			return;
+		}
+
+		let mut source = match self.post_processing.resolve_target.as_ref()
+		{
+			Some(resolve_target) =>
+			{
+				scene_target.blit_to(&self.context, Some(resolve_target));
+				resolve_target.color.clone()
+			},
+			None => scene_target.color.clone()
+		};
+
+		self.context.disable(WebGl2RenderingContext::DEPTH_TEST);
+
+		for (step, n) in effects.iter().enumerate()
+		{
+			// The last step writes to the output, the others alternate between the two targets
+			let destination = if step + 1 == effects.len() && !fxaa { None } else { Some(step % 2) };
+
+			if let Err(e) = self.draw_post_effect(*n, source.as_ref(), destination)
+			{
+				rust_error(&format!("Turning off post effect {}: {}", self.post_processing.effects[*n].id, e));
+				self.post_processing.effects[*n].enabled = false;
+				self.bind_post_destination(destination);
+				self.use_post_program(EFFECT_COPY, source.as_ref());
+				self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+			}
+
+			if let Some(index) = destination
+			{
+				source = self.post_processing.targets[index].as_ref().and_then(|target| target.color.clone());
+			}
+		}
+
+		if fxaa
+		{
+			self.bind_output_framebuffer();
+			self.draw_fxaa(source.as_ref());
+		}
+
+		self.context.enable(WebGl2RenderingContext::DEPTH_TEST);
+	}
+
+	fn draw_post_effect(&mut self, n: usize, source: Option<&WebGlTexture>, destination: Option<usize>) -> Result<(), String>
+	{
+		match self.post_processing.effects[n].kind.clone()
+		{
+			PostEffectKind::ToneMapping { operator, exposure } =>
+			{
+				self.bind_post_destination(destination);
+				self.use_post_program(EFFECT_TONE_MAPPING, source);
+				self.set_post_uniform_i32("u_tone_mapping", operator.gl_value());
+				self.set_post_uniform_f32("u_exposure", exposure);
+			},
+			PostEffectKind::Gamma { gamma } =>
+			{
+				if gamma <= 0.0
+				{
+					return Err(String::from("gamma must be greater than 0"));
+				}
+
+				self.bind_post_destination(destination);
+				self.use_post_program(EFFECT_GAMMA, source);
+				self.set_post_uniform_f32("u_gamma", gamma);
+			},
+			PostEffectKind::Vignette { intensity, smoothness } =>
+			{
+				self.bind_post_destination(destination);
+				self.use_post_program(EFFECT_VIGNETTE, source);
+				let vignette_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_vignette");
+				self.context.uniform2f(vignette_index.as_ref(), intensity, smoothness.clamp(0.001, 1.0));
+				let screen_rect_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_screen_rect");
+				self.context.uniform4fv_with_f32_array(screen_rect_index.as_ref(), &self.get_screen_rect());
+			},
+			PostEffectKind::Bloom { threshold, intensity, radius } =>
+			{
+				let bloom = self.draw_bloom(source, threshold, radius)?;
+				self.bind_post_destination(destination);
+				self.use_post_program(EFFECT_BLOOM_COMBINE, source);
+				self.set_post_uniform_f32("u_bloom_intensity", intensity);
+				self.context.active_texture(WebGl2RenderingContext::TEXTURE1);
+				self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, bloom.as_ref());
+				self.set_post_uniform_i32("u_effect_map", 1);
+			},
+			PostEffectKind::AmbientOcclusion { radius, intensity, blur } =>
+			{
+				let occlusion = self.draw_ambient_occlusion(radius, intensity, blur)?;
+				self.bind_post_destination(destination);
+				self.use_post_program(EFFECT_AMBIENT_OCCLUSION, source);
+				self.context.active_texture(WebGl2RenderingContext::TEXTURE1);
+				self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, occlusion.as_ref());
+				self.set_post_uniform_i32("u_effect_map", 1);
+			},
+			PostEffectKind::Custom { program, uniforms } =>
+			{
+				self.bind_post_destination(destination);
+				self.current_program = Some(program);
+				self.context.use_program(self.current_program.as_ref());
+				let program = self.current_program.as_ref().unwrap();
+
+				self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+				self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, source);
+				let source_index = self.context.get_uniform_location(program, "u_source_map");
+				self.context.uniform1i(source_index.as_ref(), 0);
+
+				let time_index = self.context.get_uniform_location(program, "u_time");
+				self.context.uniform1f(time_index.as_ref(), self.time);
+				let resolution_index = self.context.get_uniform_location(program, "u_resolution");
+				self.context.uniform2fv_with_f32_array(resolution_index.as_ref(), &self.resolution);
+				let mouse_position_index = self.context.get_uniform_location(program, "u_mouse_position");
+				self.context.uniform2fv_with_f32_array(mouse_position_index.as_ref(), &self.mouse_position);
+
+				for (name, value) in &uniforms
+				{
+					let location = self.context.get_uniform_location(program, name);
+					value.apply(&self.context, location.as_ref());
+				}
+			}
+		}
+
+		self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Keep what is brighter than the threshold at half resolution and blur it, across then down, twice.
+	*	Returns the texture holding the blurred highlights
+	*
+	*/
+	fn draw_bloom(&mut self, source: Option<&WebGlTexture>, threshold: f32, radius: f32) -> Result<Option<WebGlTexture>, String>
+	{
+		let (width, height, textures) = match &self.post_processing.bloom_targets
+		{
+			[Some(first), Some(second)] => (first.width, first.height, [first.color.clone(), second.color.clone()]),
+			_ => return Err(String::from("bloom targets are missing"))
+		};
+		self.context.viewport(0, 0, width, height);
+
+		self.bind_bloom_target(0);
+		self.use_post_program(EFFECT_BLOOM_BRIGHT, source);
+		self.set_post_uniform_f32("u_bloom_threshold", threshold);
+		self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+
+		let steps = [[radius / width as f32, 0.0], [0.0, radius / height as f32]];
+		for _ in 0..2
+		{
+			for (pass, step) in steps.iter().enumerate()
+			{
+				self.bind_bloom_target(1 - pass);
+				self.use_post_program(EFFECT_BLUR, textures[pass].as_ref());
+				let step_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_blur_step");
+				self.context.uniform2fv_with_f32_array(step_index.as_ref(), step);
+				self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+			}
+		}
+
+		return Ok(textures[0].clone());
+	}
+
+	fn bind_bloom_target(&self, index: usize)
+	{
+		let framebuffer = self.post_processing.bloom_targets[index].as_ref().map(|target| &target.framebuffer);
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer);
+	}
+
+	// One of the two ping-pong targets, or the output with None
+	fn bind_post_destination(&self, destination: Option<usize>)
+	{
+		match destination.and_then(|index| self.post_processing.targets[index].as_ref())
+		{
+			Some(target) => self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&target.framebuffer)),
+			None => self.bind_output_framebuffer()
+		}
+
+		let (width, height) = self.get_output_size();
+		self.context.viewport(0, 0, width, height);
+	}
+
+	// Bind the built in post program for one of its effects, reading from the source on unit 0
+	pub(in super) fn use_post_program(&mut self, effect: i32, source: Option<&WebGlTexture>)
+	{
+		self.current_program = self.post_processing.program.clone();
+		self.context.use_program(self.current_program.as_ref());
+
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+		self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, source);
+		self.set_post_uniform_i32("u_source_map", 0);
+		self.set_post_uniform_i32("u_effect", effect);
+	}
+
+	pub(in super) fn set_post_uniform_i32(&self, name: &str, value: i32)
+	{
+		let index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), name);
+		self.context.uniform1i(index.as_ref(), value);
+	}
+
+	pub(in super) fn set_post_uniform_f32(&self, name: &str, value: f32)
+	{
+		let index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), name);
+		self.context.uniform1f(index.as_ref(), value);
+	}
+
+	fn prepare_scene_targets(&mut self) -> Result<(), String>
+	{
+		let (width, height) = self.get_output_size();
+		let effects = self.post_processing.has_effects();
+		let format = if effects && self.supports_float_targets() { WebGl2RenderingContext::RGBA16F } else { WebGl2RenderingContext::RGBA8 };
+
+		let max_samples = self.context
+			.get_parameter(WebGl2RenderingContext::MAX_SAMPLES)
+			.ok()
+			.and_then(|value| value.as_f64())
+			.unwrap_or(4.0) as i32;
+		self.antialiasing.limit_samples(max_samples);
+		let samples = self.antialiasing.samples();
+
+		RenderTarget::prepare(&self.context, &mut self.post_processing.scene_target, width, height, format, samples, true)?;
+
+		// Effects and FXAA sample a texture, which a multisampled target has to be resolved into first
+		if samples > 0 && (effects || self.antialiasing.uses_fxaa())
+		{
+			RenderTarget::prepare(&self.context, &mut self.post_processing.resolve_target, width, height, format, 0, false)?;
+		}
+		else
+		{
+			RenderTarget::release(&self.context, &mut self.post_processing.resolve_target);
+		}
+
+		if effects
+		{
+			for target in &mut self.post_processing.targets
+			{
+				RenderTarget::prepare(&self.context, target, width, height, format, 0, false)?;
+			}
+
+			if self.post_processing.program.is_none()
+			{
+				rust_verbose("Building post processing shader...");
+				self.post_processing.program = Some(self.build_builtin_program(FULLSCREEN_VERTEX_SHADER, POST_FRAGMENT_SHADER)?);
+				rust_verbose("...post processing shader build complete.");
+			}
+		}
+		else
+		{
+			for target in &mut self.post_processing.targets
+			{
+				RenderTarget::release(&self.context, target);
+			}
+		}
+
+		if self.post_processing.has_effect("bloom")
+		{
+			for target in &mut self.post_processing.bloom_targets
+			{
+				RenderTarget::prepare(&self.context, target, (width / 2).max(1), (height / 2).max(1), format, 0, false)?;
+			}
+		}
+		else
+		{
+			for target in &mut self.post_processing.bloom_targets
+			{
+				RenderTarget::release(&self.context, target);
+			}
+		}
+
+		return Ok(());
+	}
+
+	pub(in super) fn supports_float_targets(&mut self) -> bool
+	{
+		if self.post_processing.float_targets.is_none()
+		{
+			let supported = matches!(self.context.get_extension("EXT_color_buffer_float"), Ok(Some(_)));
+			if !supported
+			{
+				rust_warn("EXT_color_buffer_float is missing, post effects will work on an 8 bit frame");
+			}
+			self.post_processing.float_targets = Some(supported);
+		}
+
+		return self.post_processing.float_targets == Some(true);
+	}
+
+	fn delete_scene_targets(&mut self)
+	{
+		RenderTarget::release(&self.context, &mut self.post_processing.scene_target);
+		RenderTarget::release(&self.context, &mut self.post_processing.resolve_target);
+		for target in self.post_processing.targets.iter_mut().chain(self.post_processing.bloom_targets.iter_mut())
+		{
+			RenderTarget::release(&self.context, target);
+		}
+	}
+}
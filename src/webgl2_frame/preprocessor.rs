@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use crate::webgl2_wavefront_object::WebGl2WavefrontObject;
+use crate::webgl2_wavefront_object::material::AlphaMode;
+use crate::webgl2_wavefront_object::material::MaterialMap;
+
+/*
+*
+*	The mesh features a shader permutation is built for. Each feature becomes a #define in both shader stages
+*
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderFeatures
+{
+	pub has_texcoord: bool,
+	pub has_normals: bool,
+	pub has_tangents: bool,
+	pub has_vertex_color: bool,
+	// One bit per MaterialMap with a texture bound
+	pub material_maps: u16,
+	pub metallic_roughness_packed: bool,
+	pub has_pbr_material: bool,
+	pub alpha_blend: bool,
+	pub alpha_mask: bool,
+	// Set by the frame rather than the object, when an environment map is loaded
+	pub has_environment: bool,
+	// Set by the frame when shadows are turned on
+	pub has_shadows: bool,
+	// Set by the frame for blended objects drawn with order independent transparency
+	pub weighted_oit: bool
+}
+
+impl ShaderFeatures
+{
+	pub fn from_object(object: &WebGl2WavefrontObject) -> Self
+	{
+		return Self
+		{
+			has_texcoord: object.vertex_and_texture_buffer.is_some(),
+			has_normals: object.normal_buffer.is_some(),
+			has_tangents: object.tangent_buffer.is_some(),
+			has_vertex_color: object.color_buffer.is_some(),
+			material_maps: object.map_textures.iter().fold(0, |maps, (map, _)| maps | map.bit()),
+			metallic_roughness_packed: object.is_metallic_roughness_packed(),
+			has_pbr_material: object.material.is_pbr,
+			alpha_blend: object.material.alpha_mode == AlphaMode::Blend,
+			alpha_mask: object.material.alpha_mode == AlphaMode::Mask,
+			has_environment: false,
+			has_shadows: false,
+			weighted_oit: false
+		};
+	}
+
+	pub fn defines(&self) -> Vec<&'static str>
+	{
+		let mut defines: Vec<&'static str> = Vec::new();
+
+		if self.has_texcoord
+		{
+			defines.push("HAS_TEXCOORD");
+		}
+		if self.has_normals
+		{
+			defines.push("HAS_NORMALS");
+		}
+		if self.has_tangents
+		{
+			defines.push("HAS_TANGENTS");
+		}
+		if self.has_vertex_color
+		{
+			defines.push("HAS_VERTEX_COLOR");
+		}
+		for map in MaterialMap::ALL.iter()
+		{
+			if self.material_maps & map.bit() != 0
+			{
+				defines.push(map.define());
+			}
+		}
+		if self.metallic_roughness_packed
+		{
+			defines.push("METALLIC_ROUGHNESS_PACKED");
+		}
+		if self.has_pbr_material
+		{
+			defines.push("MATERIAL_PBR");
+		}
+		if self.alpha_blend
+		{
+			defines.push("ALPHA_BLEND");
+		}
+		if self.alpha_mask
+		{
+			defines.push("ALPHA_MASK");
+		}
+		if self.has_environment
+		{
+			defines.push("HAS_ENVIRONMENT");
+		}
+		if self.has_shadows
+		{
+			defines.push("HAS_SHADOWS");
+		}
+		if self.weighted_oit
+		{
+			defines.push("WEIGHTED_OIT");
+		}
+
+		return defines;
+	}
+}
+
+/*
+*
+*	Resolve #include "name" directives against the shader chunks and inject the given #defines.
+*	Defines go directly after the #version line as GLSL requires it to be the first statement.
+*
+*/
+pub fn preprocess(source: &str, chunks: &HashMap<String, String>, defines: &[&str]) -> Result<String, String>
+{
+	let mut include_stack: Vec<String> = Vec::new();
+	let resolved = resolve_includes(source, chunks, &mut include_stack)?;
+
+	let define_lines: String = defines.iter().map(|define| format!("#define {}\n", define)).collect();
+
+	let mut out = String::new();
+	let mut injected = false;
+	for line in resolved.lines()
+	{
+		out.push_str(line);
+		out.push('\n');
+
+		if !injected && line.trim_start().starts_with("#version")
+		{
+			out.push_str(&define_lines);
+			injected = true;
+		}
+	}
+
+	if !injected
+	{
+		out = define_lines + &out;
+	}
+
+	return Ok(out);
+}
+
+fn resolve_includes(source: &str, chunks: &HashMap<String, String>, include_stack: &mut Vec<String>) -> Result<String, String>
+{
+	let mut out = String::new();
+
+	for line in source.lines()
+	{
+		let name = match parse_include(line)?
+		{
+			Some(name) => name,
+			None =>
+			{
+				out.push_str(line);
+				out.push('\n');
+				continue;
+			}
+		};
+
+		if include_stack.iter().any(|included| included == name)
+		{
+			return Err(format!("Shader chunk {} includes itself via {}", name, include_stack.join(" -> ")));
+		}
+
+		let chunk = chunks.get(name).ok_or_else(|| format!("Shader chunk {} could not be found", name))?;
+
+		include_stack.push(name.to_owned());
+		out.push_str(&resolve_includes(chunk, chunks, include_stack)?);
+		include_stack.pop();
+	}
+
+	return Ok(out);
+}
+
+/*
+*
+*	Pull the chunk name out of a line of the form #include "name". Returns None for any other line
+*
+*/
+fn parse_include(line: &str) -> Result<Option<&str>, String>
+{
+	let rest = match line.trim().strip_prefix('#')
+	{
+		Some(rest) => rest.trim_start(),
+		None => return Ok(None)
+	};
+
+	let rest = match rest.strip_prefix("include")
+	{
+		Some(rest) => rest.trim(),
+		None => return Ok(None)
+	};
+
+	match rest.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+	{
+		Some(name) if !name.is_empty() => Ok(Some(name)),
+		_ => Err(format!("Malformed include directive: {}", line.trim()))
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn chunks(entries: &[(&str, &str)]) -> HashMap<String, String>
+	{
+		return entries.iter().map(|(name, source)| (name.to_string(), source.to_string())).collect();
+	}
+
+	#[test]
+	fn includes_are_replaced_by_their_chunks()
+	{
+		let chunks = chunks(&[("lighting", "float light;\n#include \"common\""), ("common", "float common;")]);
+		let out = preprocess("#version 300 es\n#include \"lighting\"\nvoid main() {}", &chunks, &[]).unwrap();
+
+		assert_eq!(out, "#version 300 es\nfloat light;\nfloat common;\nvoid main() {}\n");
+	}
+
+	#[test]
+	fn a_chunk_may_be_included_more_than_once()
+	{
+		let chunks = chunks(&[("common", "float common;")]);
+		let out = preprocess("#include \"common\"\n  #  include \"common\"", &chunks, &[]).unwrap();
+
+		assert_eq!(out, "float common;\nfloat common;\n");
+	}
+
+	#[test]
+	fn include_cycles_are_reported()
+	{
+		let chunks = chunks(&[("a", "#include \"b\""), ("b", "#include \"a\""), ("self", "#include \"self\"")]);
+
+		let error = preprocess("#include \"a\"", &chunks, &[]).unwrap_err();
+		assert!(error.contains("a includes itself via a -> b"), "{}", error);
+		assert!(preprocess("#include \"self\"", &chunks, &[]).is_err());
+	}
+
+	#[test]
+	fn missing_and_malformed_includes_are_reported()
+	{
+		let error = preprocess("#include \"missing\"", &HashMap::new(), &[]).unwrap_err();
+		assert!(error.contains("missing could not be found"), "{}", error);
+
+		assert!(preprocess("#include missing", &HashMap::new(), &[]).is_err());
+		assert!(preprocess("#include \"\"", &HashMap::new(), &[]).is_err());
+	}
+
+	#[test]
+	fn defines_follow_the_version_line()
+	{
+		let out = preprocess("#version 300 es\nprecision highp float;", &HashMap::new(), &["HAS_NORMALS", "ALPHA_MASK"]).unwrap();
+
+		assert_eq!(out, "#version 300 es\n#define HAS_NORMALS\n#define ALPHA_MASK\nprecision highp float;\n");
+	}
+
+	#[test]
+	fn defines_follow_a_version_line_after_leading_comments()
+	{
+		let source = "// Lit shader\n\n  #version 300 es\nprecision highp float;";
+		let out = preprocess(source, &HashMap::new(), &["HAS_NORMALS"]).unwrap();
+
+		assert_eq!(out, "// Lit shader\n\n  #version 300 es\n#define HAS_NORMALS\nprecision highp float;\n");
+	}
+
+	#[test]
+	fn defines_lead_a_source_without_a_version_line()
+	{
+		let out = preprocess("precision highp float;", &HashMap::new(), &["HAS_NORMALS"]).unwrap();
+
+		assert_eq!(out, "#define HAS_NORMALS\nprecision highp float;\n");
+	}
+
+	#[test]
+	fn defines_are_injected_once()
+	{
+		let chunks = chunks(&[("header", "#version 300 es")]);
+		let out = preprocess("#include \"header\"\n#version 300 es", &chunks, &["HAS_NORMALS"]).unwrap();
+
+		assert_eq!(out.matches("#define HAS_NORMALS").count(), 1);
+		assert!(out.starts_with("#version 300 es\n#define HAS_NORMALS\n"));
+	}
+}
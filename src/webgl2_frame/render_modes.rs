@@ -0,0 +1,200 @@
+use web_sys::WebGl2RenderingContext;
+
+use crate::logger::*;
+
+use super::WebGl2Frame;
+use super::scene::ObjectTarget;
+use super::selection::SelectionRole;
+
+/*
+*
+*	How an object's mesh is drawn. WebGL has no polygon mode, so the line modes draw an edge index buffer
+*	derived from the triangles. Hidden line fills the depth buffer without color so only the front edges show
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode
+{
+	Shaded,
+	Wireframe,
+	ShadedWireframe,
+	Points,
+	HiddenLine
+}
+
+impl RenderMode
+{
+	pub fn from_str(mode: &str) -> Result<Self, String>
+	{
+		match mode
+		{
+			"shaded" => Ok(RenderMode::Shaded),
+			"wireframe" => Ok(RenderMode::Wireframe),
+			"shaded_wireframe" => Ok(RenderMode::ShadedWireframe),
+			"points" => Ok(RenderMode::Points),
+			"hidden_line" => Ok(RenderMode::HiddenLine),
+			_ => Err(format!("Unknown render mode {}, expected shaded, wireframe, shaded_wireframe, points or hidden_line", mode))
+		}
+	}
+
+	// Whether the triangles are drawn in the main pass, if only into the depth buffer
+	pub(in super) fn has_surface(&self) -> bool
+	{
+		return *self == RenderMode::Shaded || *self == RenderMode::ShadedWireframe || *self == RenderMode::HiddenLine;
+	}
+
+	fn has_edges(&self) -> bool
+	{
+		return *self == RenderMode::Wireframe || *self == RenderMode::ShadedWireframe || *self == RenderMode::HiddenLine;
+	}
+}
+
+pub struct RenderModes
+{
+	mode: RenderMode,
+	line_color: [f32; 4],
+	point_size: f32
+}
+
+impl RenderModes
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			mode: RenderMode::Shaded,
+			line_color: [0.0, 0.0, 0.0, 1.0],
+			point_size: 4.0
+		}
+	}
+}
+
+impl WebGl2Frame
+{
+	// The mode objects without their own are drawn in
+	pub fn set_render_mode(&mut self, mode: RenderMode)
+	{
+		self.render_modes.mode = mode;
+	}
+
+	/*
+	*
+	*	Give the targeted objects their own render mode, or with None return them to the frame's mode
+	*
+	*/
+	pub fn set_object_render_mode(&mut self, target: &ObjectTarget, mode: Option<RenderMode>) -> Result<usize, String>
+	{
+		let indices = self.resolve_object_target(target)?;
+		for n in &indices
+		{
+			self.objects[*n].render_mode = mode;
+		}
+
+		return Ok(indices.len());
+	}
+
+	/*
+	*
+	*	The color of edges and points, and the point size in pixels. Lines are always one pixel wide as
+	*	browsers ignore lineWidth
+	*
+	*/
+	pub fn set_wireframe_style(&mut self, color: [f32; 4], point_size: f32)
+	{
+		self.render_modes.line_color = color;
+		self.render_modes.point_size = point_size.max(1.0);
+	}
+
+	pub(in super) fn get_render_mode(&self, n: usize) -> RenderMode
+	{
+		return self.objects[n].render_mode.unwrap_or(self.render_modes.mode);
+	}
+
+	/*
+	*
+	*	Set up the main pass for an object's render mode. Surfaces under edges are pushed back so the edges
+	*	win the depth test, and hidden line surfaces only write depth
+	*
+	*/
+	pub(in super) fn set_surface_state(&self, mode: RenderMode)
+	{
+		if mode.has_edges()
+		{
+			self.context.enable(WebGl2RenderingContext::POLYGON_OFFSET_FILL);
+			self.context.polygon_offset(1.0, 1.0);
+		}
+		else
+		{
+			self.context.disable(WebGl2RenderingContext::POLYGON_OFFSET_FILL);
+		}
+
+		let write_color = mode != RenderMode::HiddenLine;
+		self.context.color_mask(write_color, write_color, write_color, write_color);
+	}
+
+	pub(in super) fn reset_surface_state(&self)
+	{
+		self.context.disable(WebGl2RenderingContext::POLYGON_OFFSET_FILL);
+		self.context.color_mask(true, true, true, true);
+	}
+
+	/*
+	*
+	*	Draw the edges and points of every object whose render mode has them, over the main pass
+	*
+	*/
+	pub(in super) fn draw_render_mode_passes(&mut self, world_matrices: &[webgl_matrix::Mat4])
+	{
+		let drawn: Vec<usize> = (0..self.objects.len())
+			.filter(|n| {
+				let role = self.selection.get_role(self.objects[*n].id);
+				self.objects[*n].visible && role != SelectionRole::Ghosted && role != SelectionRole::Hidden
+			})
+			.filter(|n| self.get_render_mode(*n) != RenderMode::Shaded)
+			.collect();
+		if drawn.is_empty()
+		{
+			return;
+		}
+
+		if let Err(e) = self.use_flat_program()
+		{
+			rust_error(&format!("Skipping wireframe and points: {}", e));
+			return;
+		}
+
+		self.set_clip_offset([0.0, 0.0]);
+		self.set_flat_color(self.render_modes.line_color);
+		let point_size_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_point_size");
+		self.context.uniform1f(point_size_index.as_ref(), self.render_modes.point_size);
+
+		// Edges lie exactly on the surfaces drawn under them
+		self.context.depth_func(WebGl2RenderingContext::LEQUAL);
+
+		for n in drawn
+		{
+			let mode = self.get_render_mode(n);
+			if mode.has_edges() && self.objects[n].edge_index_buffer.is_none()
+			{
+				if let Err(e) = self.objects[n].buffer_edges(&self.context)
+				{
+					rust_error(&format!("Skipping edges of object {}: {}", n, e));
+					continue;
+				}
+			}
+
+			self.bind_object(n, world_matrices);
+			if mode == RenderMode::Points
+			{
+				self.context.draw_arrays(WebGl2RenderingContext::POINTS, 0, (self.objects[n].mesh.positions.len() / 3) as i32);
+			}
+			else
+			{
+				self.context.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, self.objects[n].edge_index_buffer.as_ref());
+				self.context.draw_elements_with_f64(WebGl2RenderingContext::LINES, self.objects[n].edge_indices_size as i32, WebGl2RenderingContext::UNSIGNED_SHORT, 0.0);
+			}
+		}
+
+		self.context.depth_func(WebGl2RenderingContext::LESS);
+	}
+}
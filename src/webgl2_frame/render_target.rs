@@ -0,0 +1,145 @@
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlFramebuffer;
+use web_sys::WebGlRenderbuffer;
+use web_sys::WebGlTexture;
+
+/*
+*
+*	An offscreen framebuffer with one color attachment and optionally a depth and stencil buffer. Single
+*	sampled targets render into a texture that later passes can sample, multisampled ones into renderbuffers
+*	that have to be resolved into another target, or the canvas, with blit_to
+*
+*/
+pub struct RenderTarget
+{
+	pub framebuffer: WebGlFramebuffer,
+	pub color: Option<WebGlTexture>,
+	color_buffer: Option<WebGlRenderbuffer>,
+	depth_stencil: Option<WebGlRenderbuffer>,
+	pub width: i32,
+	pub height: i32,
+	pub format: u32,
+	pub samples: i32
+}
+
+impl RenderTarget
+{
+	pub fn new(context: &WebGl2RenderingContext, width: i32, height: i32, format: u32, samples: i32, depth_stencil: bool) -> Result<Self, String>
+	{
+		let framebuffer = context.create_framebuffer().ok_or("failed to create framebuffer")?;
+		context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&framebuffer));
+
+		let mut target = Self
+		{
+			framebuffer,
+			color: None,
+			color_buffer: None,
+			depth_stencil: None,
+			width,
+			height,
+			format,
+			samples
+		};
+
+		if samples > 0
+		{
+			let color_buffer = context.create_renderbuffer().ok_or("failed to create color renderbuffer")?;
+			context.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, Some(&color_buffer));
+			context.renderbuffer_storage_multisample(WebGl2RenderingContext::RENDERBUFFER, samples, format, width, height);
+			context.framebuffer_renderbuffer(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::COLOR_ATTACHMENT0, WebGl2RenderingContext::RENDERBUFFER, Some(&color_buffer));
+			target.color_buffer = Some(color_buffer);
+		}
+		else
+		{
+			let texture_target = WebGl2RenderingContext::TEXTURE_2D;
+			let color = context.create_texture().ok_or("failed to create color texture")?;
+			context.bind_texture(texture_target, Some(&color));
+			context.tex_storage_2d(texture_target, 1, format, width, height);
+			context.tex_parameteri(texture_target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR as i32);
+			context.tex_parameteri(texture_target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR as i32);
+			context.tex_parameteri(texture_target, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+			context.tex_parameteri(texture_target, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+			context.bind_texture(texture_target, None);
+			context.framebuffer_texture_2d(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::COLOR_ATTACHMENT0, texture_target, Some(&color), 0);
+			target.color = Some(color);
+		}
+
+		if depth_stencil
+		{
+			let buffer = context.create_renderbuffer().ok_or("failed to create depth renderbuffer")?;
+			context.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, Some(&buffer));
+			context.renderbuffer_storage_multisample(WebGl2RenderingContext::RENDERBUFFER, samples, WebGl2RenderingContext::DEPTH24_STENCIL8, width, height);
+			context.framebuffer_renderbuffer(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::DEPTH_STENCIL_ATTACHMENT, WebGl2RenderingContext::RENDERBUFFER, Some(&buffer));
+			target.depth_stencil = Some(buffer);
+		}
+		context.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, None);
+
+		let status = context.check_framebuffer_status(WebGl2RenderingContext::FRAMEBUFFER);
+		context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+		if status != WebGl2RenderingContext::FRAMEBUFFER_COMPLETE
+		{
+			target.delete(context);
+			return Err(format!("framebuffer is incomplete ({:#x})", status));
+		}
+
+		return Ok(target);
+	}
+
+	pub fn matches(&self, width: i32, height: i32, format: u32, samples: i32) -> bool
+	{
+		return self.width == width && self.height == height && self.format == format && self.samples == samples;
+	}
+
+	/*
+	*
+	*	Keep a target in the slot that matches the given size and format, replacing whatever was there if not
+	*
+	*/
+	pub fn prepare(context: &WebGl2RenderingContext, slot: &mut Option<RenderTarget>, width: i32, height: i32, format: u32, samples: i32, depth_stencil: bool) -> Result<(), String>
+	{
+		if slot.as_ref().is_some_and(|target| target.matches(width, height, format, samples))
+		{
+			return Ok(());
+		}
+
+		RenderTarget::release(context, slot);
+		*slot = Some(RenderTarget::new(context, width, height, format, samples, depth_stencil)?);
+
+		return Ok(());
+	}
+
+	pub fn release(context: &WebGl2RenderingContext, slot: &mut Option<RenderTarget>)
+	{
+		if let Some(target) = slot.take()
+		{
+			target.delete(context);
+		}
+	}
+
+	/*
+	*
+	*	Copy the color into another target of the same size, or the canvas with None, resolving samples on the way
+	*
+	*/
+	pub fn blit_to(&self, context: &WebGl2RenderingContext, target: Option<&RenderTarget>)
+	{
+		context.bind_framebuffer(WebGl2RenderingContext::READ_FRAMEBUFFER, Some(&self.framebuffer));
+		context.bind_framebuffer(WebGl2RenderingContext::DRAW_FRAMEBUFFER, target.map(|target| &target.framebuffer));
+		context.blit_framebuffer
+		(
+			0, 0, self.width, self.height,
+			0, 0, self.width, self.height,
+			WebGl2RenderingContext::COLOR_BUFFER_BIT,
+			WebGl2RenderingContext::NEAREST
+		);
+		context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+	}
+
+	pub fn delete(self, context: &WebGl2RenderingContext)
+	{
+		context.delete_framebuffer(Some(&self.framebuffer));
+		context.delete_texture(self.color.as_ref());
+		context.delete_renderbuffer(self.color_buffer.as_ref());
+		context.delete_renderbuffer(self.depth_stencil.as_ref());
+	}
+}
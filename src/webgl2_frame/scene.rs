@@ -0,0 +1,289 @@
+use wasm_bindgen::prelude::*;
+use js_sys::Array;
+use js_sys::Object;
+use js_sys::Reflect;
+
+use crate::logger::*;
+use crate::scene_graph::ROOT_NODE;
+
+use super::WebGl2Frame;
+
+/*
+*
+*	How JS refers to objects: a number is an object id, a string is a scene node name or path and covers
+*	every object on that node or below it
+*
+*/
+pub enum ObjectTarget
+{
+	Id(u32),
+	Name(String)
+}
+
+impl ObjectTarget
+{
+	pub fn from_js(value: &JsValue) -> Result<Self, String>
+	{
+		if let Some(id) = value.as_f64()
+		{
+			// Casting would quietly turn -1, 1.5 or NaN into some other object's id
+			if !id.is_finite() || id < 0.0 || id.fract() != 0.0 || id > u32::MAX as f64
+			{
+				return Err(format!("{} is not a valid object id", id));
+			}
+			return Ok(ObjectTarget::Id(id as u32));
+		}
+
+		match value.as_string()
+		{
+			Some(name) => Ok(ObjectTarget::Name(name)),
+			None => Err(String::from("Expected an object id or a scene node name"))
+		}
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Parse OBJ text and add its objects to the scene using the currently loaded materials and textures
+	*
+	*/
+	pub fn add_object(&mut self, obj_text: &str) -> Result<Vec<u32>, String>
+	{
+		rust_verbose("Parsing added object...");
+		let objset = wavefront_obj::obj::parse(obj_text).map_err(|e| e.to_string())?;
+		rust_verbose("...added object parsing complete.");
+
+		let ids = self.buffer_objset(&objset, ROOT_NODE)?;
+		rust_info(&format!("Added {} object(s) to the scene", ids.len()));
+
+		return Ok(ids);
+	}
+
+	/*
+	*
+	*	Remove objects from the scene. Removing by name also removes the scene node and its children, and
+	*	removing by id removes any node left with no objects or children. The GPU resources are released
+	*	by the next draw call
+	*
+	*/
+	pub fn remove_object(&mut self, target: &ObjectTarget) -> Result<usize, String>
+	{
+		let indices = self.resolve_object_target(target)?;
+		for n in &indices
+		{
+			self.objects[*n].marked_for_deletion = true;
+		}
+
+		match target
+		{
+			ObjectTarget::Name(name) =>
+			{
+				if let Ok(node) = self.scene_graph.find_node(name)
+				{
+					self.scene_graph.remove_node(node);
+				}
+			},
+			ObjectTarget::Id(_) =>
+			{
+				let nodes: Vec<usize> = indices.iter().map(|n| self.objects[*n].node).collect();
+				self.prune_empty_nodes(&nodes);
+			}
+		}
+
+		self.reset_l_and_s_values();
+		rust_info(&format!("Removed {} object(s) from the scene", indices.len()));
+
+		return Ok(indices.len());
+	}
+
+	/*
+	*
+	*	Remove the given nodes once no live object is on them and they have no children left, then their
+	*	parents in turn, so removing objects one by one doesn't leave their nodes behind
+	*
+	*/
+	fn prune_empty_nodes(&mut self, nodes: &[usize])
+	{
+		for node in nodes
+		{
+			let mut node = *node;
+			while node != ROOT_NODE
+			{
+				let parent = match self.scene_graph.get_node(node)
+				{
+					Some(scene_node) if scene_node.children.is_empty() => scene_node.parent,
+					_ => break
+				};
+				if self.objects.iter().any(|object| !object.marked_for_deletion && object.node == node)
+				{
+					break;
+				}
+
+				self.scene_graph.remove_node(node);
+				node = match parent
+				{
+					Some(parent) => parent,
+					None => break
+				};
+			}
+		}
+	}
+
+	/*
+	*
+	*	Swap the geometry of the targeted objects for the objects in the given OBJ text. The new objects take
+	*	the scene node, visibility and render mode of the first object replaced and are given new ids
+	*
+	*/
+	pub fn replace_geometry(&mut self, target: &ObjectTarget, obj_text: &str) -> Result<Vec<u32>, String>
+	{
+		let indices = self.resolve_object_target(target)?;
+		let objset = wavefront_obj::obj::parse(obj_text).map_err(|e| e.to_string())?;
+
+		let node = self.objects[indices[0]].node;
+		let visible = self.objects[indices[0]].visible;
+		let render_mode = self.objects[indices[0]].render_mode;
+
+		// The replaced objects are only let go once every new one is ready, so a failure leaves the scene as it was
+		let first_added = self.objects.len();
+		let mut ids: Vec<u32> = Vec::new();
+		let mut result: Result<(), String> = Ok(());
+		for obj in objset.objects.into_iter().filter(|obj| !obj.vertices.is_empty())
+		{
+			match self.buffer_object(obj, node)
+			{
+				Ok(id) => ids.push(id),
+				Err(e) =>
+				{
+					result = Err(e);
+					break;
+				}
+			}
+		}
+
+		for object in self.objects.iter_mut().filter(|object| ids.contains(&object.id))
+		{
+			object.visible = visible;
+			object.render_mode = render_mode;
+		}
+
+		if let Err(e) = result.and_then(|_| self.build_program_permutations())
+		{
+			self.discard_objects_from(first_added, &[]);
+			return Err(e);
+		}
+
+		for n in &indices
+		{
+			self.objects[*n].marked_for_deletion = true;
+		}
+		self.reset_l_and_s_values();
+		rust_info(&format!("Replaced {} object(s) with {} new object(s)", indices.len(), ids.len()));
+
+		return Ok(ids);
+	}
+
+	pub fn set_visible(&mut self, target: &ObjectTarget, visible: bool) -> Result<usize, String>
+	{
+		let indices = self.resolve_object_target(target)?;
+		for n in &indices
+		{
+			self.objects[*n].visible = visible;
+		}
+
+		return Ok(indices.len());
+	}
+
+	/*
+	*
+	*	Describe every object in the scene as an array of { id, name, node, vertices, triangles, textured, material, visible }
+	*
+	*/
+	pub fn list_objects(&self) -> Array
+	{
+		return self.objects
+			.iter()
+			.filter(|object| !object.marked_for_deletion)
+			.map(|object| {
+				let node_name = self.scene_graph.get_node(object.node).map(|node| node.name.clone()).unwrap_or_default();
+
+				let out = Object::new();
+				let _ = Reflect::set(&out, &JsValue::from_str("id"), &JsValue::from(object.id));
+				let _ = Reflect::set(&out, &JsValue::from_str("name"), &JsValue::from_str(object.get_name()));
+				let _ = Reflect::set(&out, &JsValue::from_str("node"), &JsValue::from_str(&node_name));
+				let _ = Reflect::set(&out, &JsValue::from_str("vertices"), &JsValue::from(object.get_vertex_count() as u32));
+				let _ = Reflect::set(&out, &JsValue::from_str("triangles"), &JsValue::from((object.indices_size / 3) as u32));
+				let _ = Reflect::set(&out, &JsValue::from_str("textured"), &JsValue::from(!object.map_textures.is_empty()));
+				let _ = Reflect::set(&out, &JsValue::from_str("material"), &JsValue::from_str(&object.material.name));
+				let _ = Reflect::set(&out, &JsValue::from_str("visible"), &JsValue::from(object.visible));
+				JsValue::from(out)
+			})
+			.collect();
+	}
+
+	/*
+	*
+	*	Find the indices into `objects` of the live objects a target refers to
+	*
+	*/
+	pub(in super) fn resolve_object_target(&self, target: &ObjectTarget) -> Result<Vec<usize>, String>
+	{
+		let indices: Vec<usize> = match target
+		{
+			ObjectTarget::Id(id) =>
+			{
+				(0..self.objects.len())
+					.filter(|n| !self.objects[*n].marked_for_deletion && self.objects[*n].id == *id)
+					.collect()
+			},
+			ObjectTarget::Name(name) =>
+			{
+				let node = self.scene_graph.find_node(name)?;
+				let subtree = self.scene_graph.get_subtree(node);
+				(0..self.objects.len())
+					.filter(|n| !self.objects[*n].marked_for_deletion && subtree.contains(&self.objects[*n].node))
+					.collect()
+			}
+		};
+
+		if indices.is_empty()
+		{
+			return Err(String::from("No objects match the given id or name"));
+		}
+
+		return Ok(indices);
+	}
+
+	/*
+	*
+	*	Set the local transform of a scene graph node by name. Rotation is in degrees
+	*
+	*/
+	pub fn set_node_transform(&mut self, name: &str, translation: Option<[f32; 3]>, rotation: Option<[f32; 3]>, scale: Option<[f32; 3]>) -> Result<(), String>
+	{
+		let id = self.scene_graph.find_node(name)?;
+		let node = self.scene_graph.get_node_mut(id).unwrap();
+
+		if let Some(translation) = translation
+		{
+			node.translation = translation;
+		}
+		if let Some(rotation) = rotation
+		{
+			node.rotation = rotation;
+		}
+		if let Some(scale) = scale
+		{
+			node.scale = scale;
+		}
+
+		return Ok(());
+	}
+
+	pub fn get_node_names(&self) -> Vec<String>
+	{
+		return self.scene_graph.get_node_names();
+	}
+}
@@ -0,0 +1,420 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+use web_sys::WebGl2RenderingContext;
+
+use crate::logger::*;
+
+use super::WebGl2Frame;
+use super::scene::ObjectTarget;
+
+// Flat colour shader used for the highlight and render mode passes, independent of whatever shaders the scene was loaded with
+const FLAT_VERTEX_SHADER: &str = r#"#version 300 es
+in vec4 a_position;
+
+uniform mat4 u_projection_matrix;
+uniform mat4 u_camera_matrix;
+uniform mat4 u_model_matrix;
+uniform vec2 u_clip_offset;
+uniform float u_point_size;
+
+void main()
+{
+	gl_Position = u_projection_matrix * u_camera_matrix * u_model_matrix * a_position;
+	gl_Position.xy += u_clip_offset * gl_Position.w;
+	gl_PointSize = u_point_size;
+}
+"#;
+
+const FLAT_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+uniform vec4 u_flat_color;
+
+out vec4 out_color;
+
+void main()
+{
+	out_color = u_flat_color;
+}
+"#;
+
+// Screen space directions the silhouette is shifted in to build the outline
+const OUTLINE_DIRECTIONS: [[f32; 2]; 8] =
+[
+	[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0],
+	[FRAC_1_SQRT_2, FRAC_1_SQRT_2], [-FRAC_1_SQRT_2, FRAC_1_SQRT_2], [FRAC_1_SQRT_2, -FRAC_1_SQRT_2], [-FRAC_1_SQRT_2, -FRAC_1_SQRT_2]
+];
+
+/*
+*
+*	How selected objects are highlighted
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectionStyle
+{
+	None,
+	Outline,
+	Tint,
+	OutlineAndTint
+}
+
+impl SelectionStyle
+{
+	pub fn from_str(style: &str) -> Result<Self, String>
+	{
+		match style
+		{
+			"none" => Ok(SelectionStyle::None),
+			"outline" => Ok(SelectionStyle::Outline),
+			"tint" => Ok(SelectionStyle::Tint),
+			"outline_tint" => Ok(SelectionStyle::OutlineAndTint),
+			_ => Err(format!("Unknown selection style {}, expected none, outline, tint or outline_tint", style))
+		}
+	}
+
+	fn has_outline(&self) -> bool
+	{
+		return *self == SelectionStyle::Outline || *self == SelectionStyle::OutlineAndTint;
+	}
+
+	fn has_tint(&self) -> bool
+	{
+		return *self == SelectionStyle::Tint || *self == SelectionStyle::OutlineAndTint;
+	}
+}
+
+/*
+*
+*	What happens to everything that isn't selected. Isolate draws the rest of the scene as faint ghosts,
+*	HideOthers doesn't draw it at all. Neither applies while the selection is empty
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IsolationMode
+{
+	Off,
+	Isolate,
+	HideOthers
+}
+
+impl IsolationMode
+{
+	pub fn from_str(mode: &str) -> Result<Self, String>
+	{
+		match mode
+		{
+			"off" => Ok(IsolationMode::Off),
+			"isolate" => Ok(IsolationMode::Isolate),
+			"hide_others" => Ok(IsolationMode::HideOthers),
+			_ => Err(format!("Unknown isolation mode {}, expected off, isolate or hide_others", mode))
+		}
+	}
+}
+
+/*
+*
+*	How an object takes part in the frame given the current selection
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(in super) enum SelectionRole
+{
+	Normal,
+	Selected,
+	Ghosted,
+	Hidden
+}
+
+pub struct Selection
+{
+	ids: Vec<u32>,
+	style: SelectionStyle,
+	isolation: IsolationMode,
+	outline_color: [f32; 4],
+	outline_width: f32,
+	tint_color: [f32; 4],
+	ghost_color: [f32; 4]
+}
+
+impl Selection
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			ids: Vec::new(),
+			style: SelectionStyle::Outline,
+			isolation: IsolationMode::Off,
+			outline_color: [1.0, 0.6, 0.0, 1.0],
+			outline_width: 2.0,
+			tint_color: [1.0, 0.6, 0.0, 0.35],
+			ghost_color: [0.6, 0.6, 0.6, 0.12]
+		}
+	}
+
+	pub(in super) fn get_role(&self, id: u32) -> SelectionRole
+	{
+		let selected = self.ids.contains(&id);
+		if selected
+		{
+			return SelectionRole::Selected;
+		}
+
+		if self.ids.is_empty()
+		{
+			return SelectionRole::Normal;
+		}
+
+		match self.isolation
+		{
+			IsolationMode::Off => SelectionRole::Normal,
+			IsolationMode::Isolate => SelectionRole::Ghosted,
+			IsolationMode::HideOthers => SelectionRole::Hidden
+		}
+	}
+
+	/*
+	*
+	*	Whether clicks and hovers can land on the object. Ghosted and hidden objects are looked through
+	*
+	*/
+	pub(in super) fn is_pickable(&self, id: u32) -> bool
+	{
+		let role = self.get_role(id);
+		return role == SelectionRole::Normal || role == SelectionRole::Selected;
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Add objects to the selection, replacing it unless additive. Returns how many objects were selected
+	*
+	*/
+	pub fn select(&mut self, target: &ObjectTarget, additive: bool) -> Result<usize, String>
+	{
+		let ids: Vec<u32> = self.resolve_object_target(target)?.iter().map(|n| self.objects[*n].id).collect();
+
+		if !additive
+		{
+			self.selection.ids.clear();
+		}
+
+		for id in &ids
+		{
+			if !self.selection.ids.contains(id)
+			{
+				self.selection.ids.push(*id);
+			}
+		}
+		rust_verbose(&format!("Selection is now {:?}", self.selection.ids));
+
+		return Ok(ids.len());
+	}
+
+	pub fn deselect(&mut self, target: &ObjectTarget) -> Result<usize, String>
+	{
+		let ids: Vec<u32> = self.resolve_object_target(target)?.iter().map(|n| self.objects[*n].id).collect();
+		self.selection.ids.retain(|id| !ids.contains(id));
+
+		return Ok(ids.len());
+	}
+
+	pub fn clear_selection(&mut self)
+	{
+		self.selection.ids.clear();
+	}
+
+	pub fn get_selection(&self) -> Vec<u32>
+	{
+		return self.selection.ids.clone();
+	}
+
+	pub fn set_selection_style(&mut self, style: SelectionStyle)
+	{
+		self.selection.style = style;
+	}
+
+	pub fn set_isolation(&mut self, isolation: IsolationMode)
+	{
+		self.selection.isolation = isolation;
+	}
+
+	pub fn set_outline(&mut self, color: [f32; 4], width: f32)
+	{
+		self.selection.outline_color = color;
+		self.selection.outline_width = width.max(0.0);
+	}
+
+	pub fn set_tint(&mut self, color: [f32; 4])
+	{
+		self.selection.tint_color = color;
+	}
+
+	/*
+	*
+	*	Forget selected ids whose objects have since been removed from the scene
+	*
+	*/
+	pub(in super) fn prune_selection(&mut self)
+	{
+		let objects = &self.objects;
+		self.selection.ids.retain(|id| objects.iter().any(|object| object.id == *id && !object.marked_for_deletion));
+	}
+
+	/*
+	*
+	*	Whether the main pass needs to mark selected silhouettes in the stencil buffer for the outline pass
+	*
+	*/
+	pub(in super) fn needs_outline(&self) -> bool
+	{
+		return self.selection.style.has_outline() && !self.selection.ids.is_empty() && self.selection.outline_width > 0.0;
+	}
+
+	/*
+	*
+	*	Set the stencil state for an object in the main pass. Selected objects mark their whole silhouette,
+	*	including occluded parts, so the outline wraps the full shape
+	*
+	*/
+	pub(in super) fn set_selection_stencil(&self, role: SelectionRole)
+	{
+		if role == SelectionRole::Selected
+		{
+			self.context.stencil_mask(0xff);
+			self.context.stencil_func(WebGl2RenderingContext::ALWAYS, 1, 0xff);
+			self.context.stencil_op(WebGl2RenderingContext::KEEP, WebGl2RenderingContext::REPLACE, WebGl2RenderingContext::REPLACE);
+		}
+		else
+		{
+			self.context.stencil_mask(0x00);
+		}
+	}
+
+	/*
+	*
+	*	Draw the ghosts, tints and outlines on top of the main pass, in that order
+	*
+	*/
+	pub(in super) fn draw_selection_passes(&mut self, world_matrices: &[webgl_matrix::Mat4])
+	{
+		let ghosted: Vec<usize> = (0..self.objects.len())
+			.filter(|n| self.objects[*n].visible && self.selection.get_role(self.objects[*n].id) == SelectionRole::Ghosted)
+			.collect();
+		let selected: Vec<usize> = (0..self.objects.len())
+			.filter(|n| self.objects[*n].visible && self.selection.get_role(self.objects[*n].id) == SelectionRole::Selected)
+			.collect();
+
+		let tint = self.selection.style.has_tint() && !selected.is_empty();
+		let outline = self.needs_outline() && !selected.is_empty();
+		if ghosted.is_empty() && !tint && !outline
+		{
+			return;
+		}
+
+		if let Err(e) = self.use_flat_program()
+		{
+			rust_error(&format!("Skipping selection highlight: {}", e));
+			return;
+		}
+
+		// Ghosts and tints blend over the main pass without disturbing its depth
+		self.context.disable(WebGl2RenderingContext::STENCIL_TEST);
+		self.context.enable(WebGl2RenderingContext::BLEND);
+		self.context.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
+		self.context.depth_mask(false);
+		self.set_clip_offset([0.0, 0.0]);
+
+		self.set_flat_color(self.selection.ghost_color);
+		for n in &ghosted
+		{
+			self.bind_object(*n, world_matrices);
+			self.draw_object_elements(*n);
+		}
+
+		if tint
+		{
+			self.context.depth_func(WebGl2RenderingContext::LEQUAL);
+			self.set_flat_color(self.selection.tint_color);
+			for n in &selected
+			{
+				self.bind_object(*n, world_matrices);
+				self.draw_object_elements(*n);
+			}
+		}
+
+		// The outline is the silhouette shifted a few pixels each way, kept only outside the stencilled original
+		if outline
+		{
+			self.context.enable(WebGl2RenderingContext::STENCIL_TEST);
+			self.context.stencil_mask(0x00);
+			self.context.stencil_func(WebGl2RenderingContext::NOTEQUAL, 1, 0xff);
+			self.context.disable(WebGl2RenderingContext::DEPTH_TEST);
+			self.set_flat_color(self.selection.outline_color);
+
+			let (width, height) = self.get_output_size();
+			let pixel_size = [2.0 / width.max(1) as f32, 2.0 / height.max(1) as f32];
+			for n in &selected
+			{
+				self.bind_object(*n, world_matrices);
+				for direction in OUTLINE_DIRECTIONS.iter()
+				{
+					self.set_clip_offset
+					(
+						[
+							direction[0] * pixel_size[0] * self.selection.outline_width,
+							direction[1] * pixel_size[1] * self.selection.outline_width
+						]
+					);
+					self.draw_object_elements(*n);
+				}
+			}
+
+			self.context.enable(WebGl2RenderingContext::DEPTH_TEST);
+			self.context.disable(WebGl2RenderingContext::STENCIL_TEST);
+			self.context.stencil_mask(0xff);
+		}
+
+		self.context.depth_func(WebGl2RenderingContext::LESS);
+		self.context.depth_mask(true);
+		self.context.disable(WebGl2RenderingContext::BLEND);
+	}
+
+	/*
+	*
+	*	Bind the built in flat colour program, building it on first use
+	*
+	*/
+	pub(in super) fn use_flat_program(&mut self) -> Result<(), String>
+	{
+		if self.flat_program.is_none()
+		{
+			rust_verbose("Building flat colour shader...");
+			self.flat_program = Some(self.build_builtin_program(FLAT_VERTEX_SHADER, FLAT_FRAGMENT_SHADER)?);
+			rust_verbose("...flat colour shader build complete.");
+		}
+
+		if self.current_program != self.flat_program
+		{
+			self.current_program = self.flat_program.clone();
+			self.context.use_program(self.current_program.as_ref());
+			self.apply_frame_uniforms();
+		}
+
+		return Ok(());
+	}
+
+	pub(in super) fn set_flat_color(&self, color: [f32; 4])
+	{
+		let color_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_flat_color");
+		self.context.uniform4fv_with_f32_array(color_index.as_ref(), &color);
+	}
+
+	pub(in super) fn set_clip_offset(&self, offset: [f32; 2])
+	{
+		let offset_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_clip_offset");
+		self.context.uniform2fv_with_f32_array(offset_index.as_ref(), &offset);
+	}
+}
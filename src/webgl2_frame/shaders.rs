@@ -1,5 +1,14 @@
+use wasm_bindgen::prelude::*;
 use web_sys::*;
+use js_sys::Map;
+
+use crate::logger::*;
+use crate::utils::*;
+use crate::webgl2_wavefront_object::WebGl2WavefrontObject;
+
 use super::WebGl2Frame;
+use super::preprocessor::*;
+use super::builtin_shaders::*;
 
 impl WebGl2Frame
 {
@@ -8,7 +17,7 @@ impl WebGl2Frame
         &self,
         shader_type: u32,
         source: &str,
-    ) -> Result<WebGlShader, String> 
+    ) -> Result<WebGlShader, String>
     {
         let shader = self.context
             .create_shader(shader_type)
@@ -31,30 +40,217 @@ impl WebGl2Frame
 
     pub fn link_program
     (
-        &mut self,
+        &self,
         vert_shader: &WebGlShader,
         frag_shader: &WebGlShader,
-    ) -> Result<(), String> 
+    ) -> Result<WebGlProgram, String>
     {
-        let tmp = self.context
-        .create_program()
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
-        self.program = Some(tmp);
+        let program = self.context
+            .create_program()
+            .ok_or_else(|| String::from("Unable to create shader object"))?;
 
-        self.context.attach_shader(self.program.as_mut().unwrap(), vert_shader);
-        self.context.attach_shader(self.program.as_mut().unwrap(), frag_shader);
-        self.context.link_program(self.program.as_mut().unwrap());
+        self.context.attach_shader(&program, vert_shader);
+        self.context.attach_shader(&program, frag_shader);
+        self.context.link_program(&program);
 
         if self.context
-            .get_program_parameter(self.program.as_mut().unwrap(), WebGl2RenderingContext::LINK_STATUS)
+            .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
             .as_bool()
             .unwrap_or(false)
         {
-            Ok(())
+            Ok(program)
         } else {
             Err(self.context
-                .get_program_info_log(self.program.as_mut().unwrap())
+                .get_program_info_log(&program)
                 .unwrap_or_else(|| String::from("Unknown error creating program object")))
         }
     }
+
+    /*
+    *
+    *   Compile and link one of the engine's own fixed programs, which bypass the preprocessor
+    *
+    */
+    pub(in super) fn build_builtin_program(&self, vert_source: &str, frag_source: &str) -> Result<WebGlProgram, String>
+    {
+        let vert_shader = self.compile_shader(WebGl2RenderingContext::VERTEX_SHADER, vert_source)?;
+        let frag_shader = self.compile_shader(WebGl2RenderingContext::FRAGMENT_SHADER, frag_source)?;
+        let program = self.link_program(&vert_shader, &frag_shader)?;
+
+        self.context.delete_shader(Some(&vert_shader));
+        self.context.delete_shader(Some(&frag_shader));
+
+        return Ok(program);
+    }
+
+    /*
+    *
+    *   Read the shader sources and chunks from the resources, drop any previously built permutations
+    *   and build the base permutation used for introspection and custom uniform validation
+    *
+    */
+    pub(in super) fn load_shaders(&mut self, resources: &Map) -> Result<(), String>
+    {
+        rust_info("Loading shaders to memory...");
+        let vert_source = resources.get(&JsValue::from_str("vert_shader")).as_string();
+        let frag_source = resources.get(&JsValue::from_str("frag_shader")).as_string();
+        match (vert_source, frag_source)
+        {
+            (Some(vert_source), Some(frag_source)) =>
+            {
+                self.vert_source = vert_source;
+                self.frag_source = frag_source;
+            },
+            (None, None) =>
+            {
+                rust_info("No shaders supplied, using the built in lit shader");
+                self.vert_source = String::from(LIT_VERTEX_SHADER);
+                self.frag_source = String::from(LIT_FRAGMENT_SHADER);
+            },
+            _ => return Err(String::from("Both vert_shader and frag_shader must be supplied, or neither to use the built in lit shader"))
+        };
+        rust_super_verbose(&("Vertex Shader is: ".to_owned() + &self.vert_source));
+        rust_super_verbose(&("Fragment Shader is: ".to_owned() + &self.frag_source));
+
+        self.shader_chunks = builtin_chunks();
+        self.shader_chunks.extend(get_js_sys_map_to_hashmap(resources, "shader_chunks").unwrap_or_default());
+        rust_info("...shaders load to memory complete.");
+
+        for (_, program) in self.programs.drain()
+        {
+            self.context.delete_program(Some(&program));
+        }
+
+        let program = self.get_program(ShaderFeatures::default())?;
+        self.base_program = Some(program.clone());
+        self.current_program = Some(program);
+        self.context.use_program(self.current_program.as_ref());
+        self.check_shader_inputs();
+        self.revalidate_custom_uniforms();
+
+        return Ok(());
+    }
+
+    /*
+    *
+    *   Fetch the program for the given mesh features, preprocessing, compiling and linking it on first use
+    *
+    */
+    pub(in super) fn get_program(&mut self, features: ShaderFeatures) -> Result<WebGlProgram, String>
+    {
+        if let Some(program) = self.programs.get(&features)
+        {
+            return Ok(program.clone());
+        }
+
+        let defines = features.defines();
+        rust_info(&("Building shader permutation [".to_owned() + &defines.join(", ") + "]..."));
+
+        let vert_source = preprocess(&self.vert_source, &self.shader_chunks, &defines)?;
+        let frag_source = preprocess(&self.frag_source, &self.shader_chunks, &defines)?;
+        rust_super_super_verbose(&("Preprocessed vertex shader is: ".to_owned() + &vert_source));
+        rust_super_super_verbose(&("Preprocessed fragment shader is: ".to_owned() + &frag_source));
+
+        rust_info("Compiling shaders...");
+        let vert_shader = self.compile_shader(WebGl2RenderingContext::VERTEX_SHADER, &vert_source)?;
+        let frag_shader = self.compile_shader(WebGl2RenderingContext::FRAGMENT_SHADER, &frag_source)?;
+        rust_info("...shaders compilation complete.");
+
+        rust_info("Linking shaders...");
+        let program = self.link_program(&vert_shader, &frag_shader)?;
+        rust_info("...shaders linking complete");
+
+        // The shaders are no longer needed once linked
+        self.context.delete_shader(Some(&vert_shader));
+        self.context.delete_shader(Some(&frag_shader));
+
+        self.programs.insert(features, program.clone());
+        rust_info("...shader permutation build complete.");
+
+        return Ok(program);
+    }
+
+    /*
+    *
+    *   The features an object is drawn with, its own plus those that apply to the whole frame
+    *
+    */
+    pub(in super) fn get_features(&self, object: &WebGl2WavefrontObject) -> ShaderFeatures
+    {
+        let mut features = ShaderFeatures::from_object(object);
+        features.has_environment = self.environment.has_maps();
+        features.has_shadows = self.shadows.is_enabled();
+        features.weighted_oit = features.alpha_blend && self.transparency.is_order_independent();
+
+        return features;
+    }
+
+    /*
+    *
+    *   Build every permutation the current objects need so that failures surface while loading rather than drawing
+    *
+    */
+    pub(in super) fn build_program_permutations(&mut self) -> Result<(), String>
+    {
+        let features: Vec<ShaderFeatures> = self.objects.iter().map(|object| self.get_features(object)).collect();
+        for feature_set in features
+        {
+            self.get_program(feature_set)?;
+        }
+
+        return Ok(());
+    }
+
+    /*
+    *
+    *   Bind the program for the given features and feed it the per frame uniforms if it isn't already bound
+    *
+    */
+    pub(in super) fn use_program_for(&mut self, features: ShaderFeatures) -> Result<(), String>
+    {
+        let program = self.get_program(features)?;
+
+        if self.current_program.as_ref() != Some(&program)
+        {
+            self.current_program = Some(program);
+            self.context.use_program(self.current_program.as_ref());
+            self.apply_frame_uniforms();
+        }
+
+        return Ok(());
+    }
+
+    /*
+    *
+    *   Feed the engine managed and custom uniforms to the currently bound program
+    *
+    */
+    pub(in super) fn apply_frame_uniforms(&self)
+    {
+        let program = match self.current_program.as_ref()
+        {
+            Some(program) => program,
+            None => return
+        };
+
+        let camera_index = self.context.get_uniform_location(program, "u_camera_matrix");
+        self.context.uniform_matrix4fv_with_f32_array(camera_index.as_ref(), false, &self.camera_matrix);
+
+        let projection_index = self.context.get_uniform_location(program, "u_projection_matrix");
+        self.context.uniform_matrix4fv_with_f32_array(projection_index.as_ref(), false, &self.projection_matrix);
+
+        let mouse_position_index = self.context.get_uniform_location(program, "u_mouse_position");
+        self.context.uniform2fv_with_f32_array(mouse_position_index.as_ref(), &self.mouse_position);
+
+        let time_index = self.context.get_uniform_location(program, "u_time");
+        self.context.uniform1f(time_index.as_ref(), self.time);
+
+        let resolution_index = self.context.get_uniform_location(program, "u_resolution");
+        self.context.uniform2fv_with_f32_array(resolution_index.as_ref(), &self.resolution);
+
+        self.apply_light_uniforms();
+        self.apply_environment_uniforms();
+        self.apply_shadow_uniforms();
+        self.apply_custom_uniforms();
+    }
 }
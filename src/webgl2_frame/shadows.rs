@@ -0,0 +1,554 @@
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlBuffer;
+use web_sys::WebGlFramebuffer;
+use web_sys::WebGlTexture;
+use webgl_matrix::*;
+
+use crate::logger::*;
+use crate::matrix_helper::*;
+use crate::raycast::bvh::Aabb;
+
+use super::WebGl2Frame;
+use super::builtin_shaders::builtin_chunks;
+use super::preprocessor::preprocess;
+use super::selection::SelectionRole;
+
+// After the background units
+const SHADOW_UNIT: u32 = 11;
+
+const DEFAULT_SHADOW_MAP_SIZE: i32 = 2048;
+
+// Only depth is written, from the light for the shadow map or from the camera for other passes
+const DEPTH_VERTEX_SHADER: &str = r#"#version 300 es
+in vec4 a_position;
+
+uniform mat4 u_view_projection_matrix;
+uniform mat4 u_model_matrix;
+
+void main()
+{
+	gl_Position = u_view_projection_matrix * u_model_matrix * a_position;
+}
+"#;
+
+const DEPTH_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+void main()
+{
+}
+"#;
+
+// A plane under the scene that is invisible apart from the shadows falling on it, unless given a color
+const GROUND_VERTEX_SHADER: &str = r#"#version 300 es
+in vec4 a_position;
+
+uniform mat4 u_projection_matrix;
+uniform mat4 u_camera_matrix;
+uniform mat4 u_model_matrix;
+
+out vec3 v_position;
+out vec2 v_local;
+
+void main()
+{
+	vec4 world_position = u_model_matrix * a_position;
+	v_position = world_position.xyz;
+	v_local = a_position.xz;
+	gl_Position = u_projection_matrix * u_camera_matrix * world_position;
+}
+"#;
+
+const GROUND_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+#include "lighting"
+
+in vec3 v_position;
+in vec2 v_local;
+
+uniform vec4 u_ground_color;
+uniform float u_ground_shadow_opacity;
+
+out vec4 out_color;
+
+void main()
+{
+	float shadow = (1.0 - get_light_shadow(u_shadow_light, v_position, vec3(0.0, 1.0, 0.0))) * u_ground_shadow_opacity;
+	// Fade out towards the edge so the plane has no visible border
+	float fade = 1.0 - smoothstep(0.5, 1.0, length(v_local));
+	// The black shadow laid over the ground color
+	float alpha = shadow + u_ground_color.a * (1.0 - shadow);
+	vec3 color = u_ground_color.rgb * u_ground_color.a * (1.0 - shadow) / max(alpha, 0.0001);
+
+	out_color = vec4(color, alpha * fade);
+}
+"#;
+
+/*
+*
+*	The shadow map for the primary directional light, and the optional ground plane catching its shadows
+*
+*/
+pub struct Shadows
+{
+	enabled: bool,
+	map_size: i32,
+	bias: f32,
+	framebuffer: Option<WebGlFramebuffer>,
+	depth_texture: Option<WebGlTexture>,
+	depth_program: Option<web_sys::WebGlProgram>,
+	// Index of the shadowed light in the light uniforms, and where the map was rendered from this frame
+	light_index: Option<usize>,
+	shadow_matrix: Mat4,
+	normal_offset: f32,
+	ground: GroundPlane
+}
+
+pub struct GroundPlane
+{
+	enabled: bool,
+	color: [f32; 4],
+	shadow_opacity: f32,
+	program: Option<web_sys::WebGlProgram>,
+	buffer: Option<WebGlBuffer>
+}
+
+impl Shadows
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			enabled: false,
+			map_size: DEFAULT_SHADOW_MAP_SIZE,
+			bias: 0.002,
+			framebuffer: None,
+			depth_texture: None,
+			depth_program: None,
+			light_index: None,
+			shadow_matrix: Mat4::identity(),
+			normal_offset: 0.0,
+			ground: GroundPlane
+			{
+				enabled: false,
+				color: [0.5, 0.5, 0.5, 0.0],
+				shadow_opacity: 0.5,
+				program: None,
+				buffer: None
+			}
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool
+	{
+		return self.enabled;
+	}
+}
+
+impl WebGl2Frame
+{
+	pub fn set_shadows(&mut self, enabled: bool) -> Result<(), String>
+	{
+		self.shadows.enabled = enabled;
+		if !enabled
+		{
+			self.shadows.light_index = None;
+		}
+
+		// Objects need the HAS_SHADOWS permutations, or the ones without
+		return self.build_program_permutations();
+	}
+
+	pub fn set_shadow_bias(&mut self, bias: f32)
+	{
+		self.shadows.bias = bias.max(0.0);
+	}
+
+	pub fn set_shadow_map_size(&mut self, size: u32) -> Result<(), String>
+	{
+		let max_size = self.context
+			.get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)
+			.ok()
+			.and_then(|value| value.as_f64())
+			.unwrap_or(4096.0) as u32;
+		if size == 0 || size > max_size
+		{
+			return Err(format!("Shadow map size must be between 1 and {}", max_size));
+		}
+
+		self.shadows.map_size = size as i32;
+		// Recreated at the new size on the next frame
+		self.delete_shadow_map();
+
+		return Ok(());
+	}
+
+	pub fn set_ground_plane(&mut self, enabled: bool)
+	{
+		self.shadows.ground.enabled = enabled;
+	}
+
+	/*
+	*
+	*	The ground's own color, transparent by default so only the shadows show, and how dark the shadows on it are
+	*
+	*/
+	pub fn set_ground_style(&mut self, color: [f32; 4], shadow_opacity: f32)
+	{
+		self.shadows.ground.color = color;
+		self.shadows.ground.shadow_opacity = shadow_opacity.clamp(0.0, 1.0);
+	}
+
+	fn delete_shadow_map(&mut self)
+	{
+		self.context.delete_framebuffer(self.shadows.framebuffer.take().as_ref());
+		self.context.delete_texture(self.shadows.depth_texture.take().as_ref());
+	}
+
+	/*
+	*
+	*	Render the depth of every shadow casting object as seen from the primary directional light
+	*
+	*/
+	pub(in super) fn draw_shadow_map(&mut self, world_matrices: &[Mat4])
+	{
+		self.shadows.light_index = None;
+		if !self.shadows.enabled
+		{
+			return;
+		}
+
+		let (light_index, direction) = match self.get_shadow_light()
+		{
+			Some(light) => light,
+			None => return
+		};
+		let bounds = match self.get_shadow_bounds(world_matrices)
+		{
+			Some(bounds) => bounds,
+			None => return
+		};
+
+		if let Err(e) = self.prepare_shadow_map()
+		{
+			rust_error(&format!("Disabling shadows: {}", e));
+			self.shadows.enabled = false;
+			return;
+		}
+
+		// Fit an orthographic view from the light around the sphere bounding the scene
+		let center = [(bounds.min[0] + bounds.max[0]) / 2.0, (bounds.min[1] + bounds.max[1]) / 2.0, (bounds.min[2] + bounds.max[2]) / 2.0];
+		let radius = (0..3).map(|axis| (bounds.max[axis] - bounds.min[axis]).powi(2)).sum::<f32>().sqrt().max(0.0001) / 2.0;
+		let eye = [center[0] - direction[0] * radius * 2.0, center[1] - direction[1] * radius * 2.0, center[2] - direction[2] * radius * 2.0];
+		let up = if direction[1].abs() > 0.99 { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+
+		let light_view = look_at_matrix(&eye, &center, &up);
+		let light_projection = orthographic_matrix(-radius, radius, -radius, radius, 0.0, radius * 4.0);
+		let light_matrix = m4_multiply(&light_projection, &light_view);
+
+		// Clip space to texture space, [-1, 1] to [0, 1]
+		let texture_matrix: Mat4 = [
+			0.5, 0.0, 0.0, 0.0,
+			0.0, 0.5, 0.0, 0.0,
+			0.0, 0.0, 0.5, 0.0,
+			0.5, 0.5, 0.5, 1.0
+		];
+		self.shadows.shadow_matrix = m4_multiply(&texture_matrix, &light_matrix);
+		// About a texel and a half, enough to keep surfaces from shadowing themselves
+		self.shadows.normal_offset = radius * 2.0 / self.shadows.map_size as f32 * 1.5;
+
+		if let Err(e) = self.use_depth_program(&light_matrix)
+		{
+			rust_error(&format!("Disabling shadows: {}", e));
+			self.shadows.enabled = false;
+			return;
+		}
+
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, self.shadows.framebuffer.as_ref());
+		self.context.viewport(0, 0, self.shadows.map_size, self.shadows.map_size);
+		self.context.clear(WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+		self.context.enable(WebGl2RenderingContext::POLYGON_OFFSET_FILL);
+		self.context.polygon_offset(2.0, 4.0);
+
+		for n in 0..self.objects.len()
+		{
+			if self.casts_shadow(n)
+			{
+				self.bind_object(n, world_matrices);
+				self.draw_object_elements(n);
+			}
+		}
+
+		self.context.disable(WebGl2RenderingContext::POLYGON_OFFSET_FILL);
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+		let (width, height) = self.get_output_size();
+		self.context.viewport(0, 0, width, height);
+
+		self.shadows.light_index = Some(light_index);
+	}
+
+	fn casts_shadow(&self, n: usize) -> bool
+	{
+		let role = self.selection.get_role(self.objects[n].id);
+		return self.objects[n].visible && role != SelectionRole::Hidden && role != SelectionRole::Ghosted;
+	}
+
+	/*
+	*
+	*	The world space box around every shadow casting object, widened to take in the ground plane under it
+	*
+	*/
+	fn get_shadow_bounds(&self, world_matrices: &[Mat4]) -> Option<Aabb>
+	{
+		let bounds = self.get_world_bounds(world_matrices)?;
+		if !self.shadows.ground.enabled
+		{
+			return Some(bounds);
+		}
+
+		let mut widened = bounds;
+		for corner in get_ground_corners(&bounds).iter()
+		{
+			widened.grow(corner);
+		}
+
+		return Some(widened);
+	}
+
+	pub(in super) fn get_world_bounds(&self, world_matrices: &[Mat4]) -> Option<Aabb>
+	{
+		let mut bounds = Aabb::empty();
+		let mut found = false;
+
+		for n in 0..self.objects.len()
+		{
+			if !self.casts_shadow(n)
+			{
+				continue;
+			}
+
+			let object = &self.objects[n];
+			let model_matrix = world_matrices.get(object.node).copied().unwrap_or(Mat4::identity());
+			for corner in 0..8
+			{
+				let point =
+				[
+					if corner & 1 == 0 { object.smallest[0] } else { object.largest[0] },
+					if corner & 2 == 0 { object.smallest[1] } else { object.largest[1] },
+					if corner & 4 == 0 { object.smallest[2] } else { object.largest[2] }
+				];
+				bounds.grow(&m4_transform_point(&model_matrix, &point));
+			}
+			found = true;
+		}
+
+		return if found { Some(bounds) } else { None };
+	}
+
+	/*
+	*
+	*	Bind the depth only program, building it on first use, to draw objects seen through the given matrix
+	*
+	*/
+	pub(in super) fn use_depth_program(&mut self, view_projection: &Mat4) -> Result<(), String>
+	{
+		if self.shadows.depth_program.is_none()
+		{
+			rust_verbose("Building depth only shader...");
+			self.shadows.depth_program = Some(self.build_builtin_program(DEPTH_VERTEX_SHADER, DEPTH_FRAGMENT_SHADER)?);
+			rust_verbose("...depth only shader build complete.");
+		}
+
+		self.current_program = self.shadows.depth_program.clone();
+		self.context.use_program(self.current_program.as_ref());
+		let view_projection_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_view_projection_matrix");
+		self.context.uniform_matrix4fv_with_f32_array(view_projection_index.as_ref(), false, view_projection);
+
+		return Ok(());
+	}
+
+	// Create the depth texture and framebuffer on first use
+	fn prepare_shadow_map(&mut self) -> Result<(), String>
+	{
+		if self.shadows.framebuffer.is_some()
+		{
+			return Ok(());
+		}
+
+		let target = WebGl2RenderingContext::TEXTURE_2D;
+		let depth_texture = self.context.create_texture().ok_or("failed to create shadow map texture")?;
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + SHADOW_UNIT);
+		self.context.bind_texture(target, Some(&depth_texture));
+		self.context.tex_storage_2d(target, 1, WebGl2RenderingContext::DEPTH_COMPONENT24, self.shadows.map_size, self.shadows.map_size);
+		// Comparison sampling gives 2x2 filtering in hardware on top of the shader's own
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_COMPARE_MODE, WebGl2RenderingContext::COMPARE_REF_TO_TEXTURE as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_COMPARE_FUNC, WebGl2RenderingContext::LEQUAL as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+
+		let framebuffer = self.context.create_framebuffer().ok_or("failed to create shadow map framebuffer")?;
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&framebuffer));
+		self.context.framebuffer_texture_2d(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::DEPTH_ATTACHMENT, target, Some(&depth_texture), 0);
+		let status = self.context.check_framebuffer_status(WebGl2RenderingContext::FRAMEBUFFER);
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+		self.shadows.depth_texture = Some(depth_texture);
+		self.shadows.framebuffer = Some(framebuffer);
+		if status != WebGl2RenderingContext::FRAMEBUFFER_COMPLETE
+		{
+			self.delete_shadow_map();
+			return Err(format!("shadow map framebuffer is incomplete ({:#x})", status));
+		}
+
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Feed the shadow map and the light it was rendered from to the bound program
+	*
+	*/
+	pub(in super) fn apply_shadow_uniforms(&self)
+	{
+		let program = match self.current_program.as_ref()
+		{
+			Some(program) => program,
+			None => return
+		};
+
+		// Always on its own unit, even with nothing to sample, as a shadow sampler may not share one with a plain sampler
+		let map_index = self.context.get_uniform_location(program, "u_shadow_map");
+		self.context.uniform1i(map_index.as_ref(), SHADOW_UNIT as i32);
+
+		let light_index = self.shadows.light_index.map(|index| index as i32).unwrap_or(-1);
+		let light_index_location = self.context.get_uniform_location(program, "u_shadow_light");
+		self.context.uniform1i(light_index_location.as_ref(), light_index);
+		if light_index < 0
+		{
+			return;
+		}
+
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + SHADOW_UNIT);
+		self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.shadows.depth_texture.as_ref());
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+
+		let matrix_index = self.context.get_uniform_location(program, "u_shadow_matrix");
+		self.context.uniform_matrix4fv_with_f32_array(matrix_index.as_ref(), false, &self.shadows.shadow_matrix);
+
+		let bias_index = self.context.get_uniform_location(program, "u_shadow_bias");
+		self.context.uniform1f(bias_index.as_ref(), self.shadows.bias);
+
+		let normal_offset_index = self.context.get_uniform_location(program, "u_shadow_normal_offset");
+		self.context.uniform1f(normal_offset_index.as_ref(), self.shadows.normal_offset);
+	}
+
+	/*
+	*
+	*	Blend the ground plane over the scene under the bounding box of the visible objects
+	*
+	*/
+	pub(in super) fn draw_ground_plane(&mut self, world_matrices: &[Mat4])
+	{
+		if !self.shadows.ground.enabled
+		{
+			return;
+		}
+
+		let bounds = match self.get_world_bounds(world_matrices)
+		{
+			Some(bounds) => bounds,
+			None => return
+		};
+
+		if let Err(e) = self.prepare_ground_plane()
+		{
+			rust_error(&format!("Disabling ground plane: {}", e));
+			self.shadows.ground.enabled = false;
+			return;
+		}
+
+		// The unit quad is scaled out to a little past the scene on every side
+		let corners = get_ground_corners(&bounds);
+		let half_size = (corners[1][0] - corners[0][0]) / 2.0;
+		let center = [(bounds.min[0] + bounds.max[0]) / 2.0, bounds.min[1], (bounds.min[2] + bounds.max[2]) / 2.0];
+		let model_matrix = trs_matrix(&center, &[0.0, 0.0, 0.0], &[half_size, 1.0, half_size]);
+
+		self.current_program = self.shadows.ground.program.clone();
+		self.context.use_program(self.current_program.as_ref());
+		self.apply_frame_uniforms();
+
+		let program = self.current_program.as_ref().unwrap();
+		let model_matrix_index = self.context.get_uniform_location(program, "u_model_matrix");
+		self.context.uniform_matrix4fv_with_f32_array(model_matrix_index.as_ref(), false, &model_matrix);
+		let color_index = self.context.get_uniform_location(program, "u_ground_color");
+		self.context.uniform4fv_with_f32_array(color_index.as_ref(), &self.shadows.ground.color);
+		let opacity_index = self.context.get_uniform_location(program, "u_ground_shadow_opacity");
+		self.context.uniform1f(opacity_index.as_ref(), self.shadows.ground.shadow_opacity);
+
+		self.unbind_attribute("a_texcoord");
+		self.unbind_attribute("a_color");
+		self.unbind_attribute("a_normal");
+		self.unbind_attribute("a_tangent");
+		self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.shadows.ground.buffer.as_ref());
+		self.bind_attribute("a_position", 3, 0, 0);
+
+		// Behind anything it intersects, and not hiding what is drawn after it
+		self.context.enable(WebGl2RenderingContext::BLEND);
+		self.context.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
+		self.context.depth_mask(false);
+		self.context.draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+		self.context.depth_mask(true);
+		self.context.disable(WebGl2RenderingContext::BLEND);
+	}
+
+	fn prepare_ground_plane(&mut self) -> Result<(), String>
+	{
+		if self.shadows.ground.program.is_none()
+		{
+			rust_verbose("Building ground plane shader...");
+			// Always built with shadows, a light index of -1 turns them off
+			let frag_source = preprocess(GROUND_FRAGMENT_SHADER, &builtin_chunks(), &["HAS_SHADOWS"])?;
+			self.shadows.ground.program = Some(self.build_builtin_program(GROUND_VERTEX_SHADER, &frag_source)?);
+			rust_verbose("...ground plane shader build complete.");
+		}
+
+		if self.shadows.ground.buffer.is_none()
+		{
+			let quad: [f32; 12] =
+			[
+				-1.0, 0.0, -1.0,
+				1.0, 0.0, -1.0,
+				-1.0, 0.0, 1.0,
+				1.0, 0.0, 1.0
+			];
+			let buffer = self.context.create_buffer().ok_or("failed to create ground plane buffer")?;
+			self.context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
+			self.context.buffer_data_with_array_buffer_view
+			(
+				WebGl2RenderingContext::ARRAY_BUFFER,
+				&js_sys::Float32Array::from(&quad[..]),
+				WebGl2RenderingContext::STATIC_DRAW
+			);
+			self.shadows.ground.buffer = Some(buffer);
+		}
+
+		return Ok(());
+	}
+}
+
+// The corners of the ground square under a box, reaching out by the box's largest extent on every side
+fn get_ground_corners(bounds: &Aabb) -> [[f32; 3]; 2]
+{
+	let extent = (bounds.max[0] - bounds.min[0]).max(bounds.max[2] - bounds.min[2]).max(bounds.max[1] - bounds.min[1]);
+	let center = [(bounds.min[0] + bounds.max[0]) / 2.0, (bounds.min[2] + bounds.max[2]) / 2.0];
+
+	return
+	[
+		[center[0] - extent * 1.5, bounds.min[1], center[1] - extent * 1.5],
+		[center[0] + extent * 1.5, bounds.min[1], center[1] + extent * 1.5]
+	];
+}
@@ -0,0 +1,323 @@
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlProgram;
+use web_sys::WebGlTexture;
+use webgl_matrix::*;
+
+use crate::logger::*;
+use crate::matrix_helper::normalize;
+
+use super::WebGl2Frame;
+use super::builtin_shaders::FULLSCREEN_VERTEX_SHADER;
+use super::post_process::EFFECT_BLUR;
+use super::render_target::RenderTarget;
+
+// Must match KERNEL_SIZE in the occlusion shader
+const KERNEL_SIZE: usize = 16;
+
+// View space normals and linear depth of the opaque surfaces, for the occlusion pass to sample
+const NORMAL_DEPTH_VERTEX_SHADER: &str = r#"#version 300 es
+in vec4 a_position;
+in vec3 a_normal;
+
+uniform mat4 u_projection_matrix;
+uniform mat4 u_camera_matrix;
+uniform mat4 u_model_matrix;
+uniform mat3 u_normal_matrix;
+
+out vec3 v_view_position;
+out vec3 v_view_normal;
+
+void main()
+{
+	vec4 view_position = u_camera_matrix * u_model_matrix * a_position;
+	v_view_position = view_position.xyz / view_position.w;
+	v_view_normal = mat3(u_camera_matrix) * u_normal_matrix * a_normal;
+	gl_Position = u_projection_matrix * view_position;
+}
+"#;
+
+const NORMAL_DEPTH_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+in vec3 v_view_position;
+in vec3 v_view_normal;
+
+out vec4 out_normal_depth;
+
+void main()
+{
+	// Meshes without normals read a zero normal attribute and fall back to the face normal
+	vec3 normal = normalize(cross(dFdx(v_view_position), dFdy(v_view_position)));
+	if (dot(v_view_normal, v_view_normal) > 0.0)
+	{
+		normal = normalize(gl_FrontFacing ? v_view_normal : -v_view_normal);
+	}
+
+	// A depth of zero is left where nothing was drawn
+	out_normal_depth = vec4(normal, -v_view_position.z);
+}
+"#;
+
+// Samples a hemisphere around each surface's normal and counts how many fall behind other surfaces
+const OCCLUSION_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+#define KERNEL_SIZE 16
+
+in vec2 v_ndc;
+in vec2 v_texcoord;
+
+uniform sampler2D u_normal_depth_map;
+uniform mat4 u_projection_matrix;
+uniform vec3 u_kernel[KERNEL_SIZE];
+uniform float u_radius;
+uniform float u_intensity;
+
+out vec4 out_occlusion;
+
+// Undoes the projection, including the off center ones tiled captures use
+vec3 get_view_position(vec2 ndc, float depth)
+{
+	vec2 view = (ndc + vec2(u_projection_matrix[2][0], u_projection_matrix[2][1])) * depth;
+	return vec3(view.x / u_projection_matrix[0][0], view.y / u_projection_matrix[1][1], -depth);
+}
+
+void main()
+{
+	vec4 normal_depth = texture(u_normal_depth_map, v_texcoord);
+	if (normal_depth.w <= 0.0)
+	{
+		out_occlusion = vec4(1.0);
+		return;
+	}
+
+	vec3 position = get_view_position(v_ndc, normal_depth.w);
+	vec3 normal = normalize(normal_depth.xyz);
+
+	// The kernel is turned by a different angle at every pixel, which the blur afterwards smooths into noise free shading
+	float angle = fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453) * 6.2831853;
+	vec3 random = vec3(cos(angle), sin(angle), 0.0);
+	vec3 tangent = normalize(random - normal * dot(random, normal));
+	mat3 tbn = mat3(tangent, cross(normal, tangent), normal);
+
+	float bias = u_radius * 0.025;
+	float occlusion = 0.0;
+	for (int i = 0; i < KERNEL_SIZE; i++)
+	{
+		vec3 sample_position = position + tbn * u_kernel[i] * u_radius;
+		vec4 sample_clip = u_projection_matrix * vec4(sample_position, 1.0);
+		vec2 sample_uv = sample_clip.xy / sample_clip.w * 0.5 + 0.5;
+
+		float scene_depth = texture(u_normal_depth_map, sample_uv).w;
+		if (scene_depth <= 0.0)
+		{
+			continue;
+		}
+
+		// Surfaces much further away than the radius are separate objects and shouldn't darken this one
+		float range = smoothstep(0.0, 1.0, u_radius / abs(normal_depth.w - scene_depth));
+		occlusion += (-scene_depth >= sample_position.z + bias ? 1.0 : 0.0) * range;
+	}
+
+	float ambient = pow(1.0 - occlusion / float(KERNEL_SIZE), u_intensity);
+	out_occlusion = vec4(ambient, ambient, ambient, 1.0);
+}
+"#;
+
+/*
+*
+*	Screen space ambient occlusion. The opaque objects are drawn again with their own program into a view
+*	space normal and depth target, so the occlusion doesn't depend on the shader the frame was lit with, and
+*	the occlusion worked out from it is blurred and multiplied into the frame by the ssao post effect
+*
+*/
+pub struct AmbientOcclusion
+{
+	normal_depth_target: Option<RenderTarget>,
+	normal_depth_program: Option<WebGlProgram>,
+	occlusion_program: Option<WebGlProgram>,
+	occlusion_targets: [Option<RenderTarget>; 2],
+	kernel: Vec<f32>
+}
+
+impl AmbientOcclusion
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			normal_depth_target: None,
+			normal_depth_program: None,
+			occlusion_program: None,
+			occlusion_targets: [None, None],
+			kernel: get_kernel()
+		}
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Draw the normal and depth of every opaque surface when the chain has an ssao effect to use them
+	*
+	*/
+	pub(in super) fn draw_normal_depth_pass(&mut self, world_matrices: &[Mat4])
+	{
+		if !self.uses_post_effect("ssao")
+		{
+			self.delete_occlusion_targets();
+			return;
+		}
+
+		if let Err(e) = self.prepare_occlusion_targets()
+		{
+			rust_error(&format!("Turning off SSAO: {}", e));
+			self.disable_post_effects("ssao");
+			self.delete_occlusion_targets();
+			return;
+		}
+
+		let framebuffer = self.ambient_occlusion.normal_depth_target.as_ref().map(|target| target.framebuffer.clone());
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer.as_ref());
+		self.context.clear_color(0.0, 0.0, 0.0, 0.0);
+		self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+
+		self.current_program = self.ambient_occlusion.normal_depth_program.clone();
+		self.context.use_program(self.current_program.as_ref());
+		let camera_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_camera_matrix");
+		self.context.uniform_matrix4fv_with_f32_array(camera_index.as_ref(), false, &self.camera_matrix);
+		let projection_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_projection_matrix");
+		self.context.uniform_matrix4fv_with_f32_array(projection_index.as_ref(), false, &self.projection_matrix);
+
+		for n in 0..self.objects.len()
+		{
+			if self.is_drawn(n) && self.get_render_mode(n).has_surface() && !self.is_transparent(n)
+			{
+				self.bind_object(n, world_matrices);
+				self.draw_object_elements(n);
+			}
+		}
+
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+	}
+
+	/*
+	*
+	*	Work out the occlusion from the normal and depth pass and blur it by the given number of pixels.
+	*	Returns the texture holding it
+	*
+	*/
+	pub(in super) fn draw_ambient_occlusion(&mut self, radius: f32, intensity: f32, blur: f32) -> Result<Option<WebGlTexture>, String>
+	{
+		let normal_depth = self.ambient_occlusion.normal_depth_target.as_ref().and_then(|target| target.color.clone());
+		let (width, height, textures) = match &self.ambient_occlusion.occlusion_targets
+		{
+			[Some(first), Some(second)] => (first.width, first.height, [first.color.clone(), second.color.clone()]),
+			_ => return Err(String::from("occlusion targets are missing"))
+		};
+
+		self.bind_occlusion_target(0);
+		self.current_program = self.ambient_occlusion.occlusion_program.clone();
+		self.context.use_program(self.current_program.as_ref());
+		let program = self.current_program.as_ref().unwrap();
+
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+		self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, normal_depth.as_ref());
+		let normal_depth_index = self.context.get_uniform_location(program, "u_normal_depth_map");
+		self.context.uniform1i(normal_depth_index.as_ref(), 0);
+		let projection_index = self.context.get_uniform_location(program, "u_projection_matrix");
+		self.context.uniform_matrix4fv_with_f32_array(projection_index.as_ref(), false, &self.projection_matrix);
+		let kernel_index = self.context.get_uniform_location(program, "u_kernel");
+		self.context.uniform3fv_with_f32_array(kernel_index.as_ref(), &self.ambient_occlusion.kernel);
+		let radius_index = self.context.get_uniform_location(program, "u_radius");
+		self.context.uniform1f(radius_index.as_ref(), radius.max(0.0001));
+		let intensity_index = self.context.get_uniform_location(program, "u_intensity");
+		self.context.uniform1f(intensity_index.as_ref(), intensity.max(0.0));
+		self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+
+		if blur > 0.0
+		{
+			let steps = [[blur / width as f32, 0.0], [0.0, blur / height as f32]];
+			for (pass, step) in steps.iter().enumerate()
+			{
+				self.bind_occlusion_target(1 - pass);
+				self.use_post_program(EFFECT_BLUR, textures[pass].as_ref());
+				let step_index = self.context.get_uniform_location(self.current_program.as_ref().unwrap(), "u_blur_step");
+				self.context.uniform2fv_with_f32_array(step_index.as_ref(), step);
+				self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+			}
+		}
+
+		return Ok(textures[0].clone());
+	}
+
+	fn bind_occlusion_target(&self, index: usize)
+	{
+		let framebuffer = self.ambient_occlusion.occlusion_targets[index].as_ref().map(|target| &target.framebuffer);
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer);
+	}
+
+	fn prepare_occlusion_targets(&mut self) -> Result<(), String>
+	{
+		// Depth has to keep its precision through the target, which needs float color buffers
+		if !self.supports_float_targets()
+		{
+			return Err(String::from("SSAO needs EXT_color_buffer_float, which this device lacks"));
+		}
+
+		let (width, height) = self.get_output_size();
+		RenderTarget::prepare(&self.context, &mut self.ambient_occlusion.normal_depth_target, width, height, WebGl2RenderingContext::RGBA16F, 0, true)?;
+		for target in &mut self.ambient_occlusion.occlusion_targets
+		{
+			RenderTarget::prepare(&self.context, target, width, height, WebGl2RenderingContext::RGBA8, 0, false)?;
+		}
+
+		if self.ambient_occlusion.normal_depth_program.is_none()
+		{
+			rust_verbose("Building SSAO shaders...");
+			self.ambient_occlusion.normal_depth_program = Some(self.build_builtin_program(NORMAL_DEPTH_VERTEX_SHADER, NORMAL_DEPTH_FRAGMENT_SHADER)?);
+			self.ambient_occlusion.occlusion_program = Some(self.build_builtin_program(FULLSCREEN_VERTEX_SHADER, OCCLUSION_FRAGMENT_SHADER)?);
+			rust_verbose("...SSAO shaders build complete.");
+		}
+
+		return Ok(());
+	}
+
+	fn delete_occlusion_targets(&mut self)
+	{
+		RenderTarget::release(&self.context, &mut self.ambient_occlusion.normal_depth_target);
+		for target in &mut self.ambient_occlusion.occlusion_targets
+		{
+			RenderTarget::release(&self.context, target);
+		}
+	}
+}
+
+/*
+*
+*	Points in the unit hemisphere around +z, more of them close to the center where occlusion matters most.
+*	A fixed sequence so the shading doesn't shimmer from one frame, or one run, to the next
+*
+*/
+fn get_kernel() -> Vec<f32>
+{
+	let mut seed: u32 = 0x9e3779b9;
+	let mut random = || {
+		seed ^= seed << 13;
+		seed ^= seed >> 17;
+		seed ^= seed << 5;
+		seed as f32 / u32::MAX as f32
+	};
+
+	let mut kernel: Vec<f32> = Vec::with_capacity(KERNEL_SIZE * 3);
+	for n in 0..KERNEL_SIZE
+	{
+		let direction = normalize(&[random() * 2.0 - 1.0, random() * 2.0 - 1.0, random().max(0.1)]);
+		let scale = n as f32 / KERNEL_SIZE as f32;
+		let length = random() * (0.1 + 0.9 * scale * scale);
+		kernel.extend(direction.iter().map(|value| value * length));
+	}
+
+	return kernel;
+}
@@ -0,0 +1,445 @@
+use js_sys::Array;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlFramebuffer;
+use web_sys::WebGlProgram;
+use web_sys::WebGlRenderbuffer;
+use web_sys::WebGlTexture;
+use webgl_matrix::*;
+
+use crate::logger::*;
+use crate::matrix_helper::*;
+use crate::webgl2_wavefront_object::material::AlphaMode;
+
+use super::WebGl2Frame;
+use super::builtin_shaders::FULLSCREEN_VERTEX_SHADER;
+use super::render_modes::RenderMode;
+use super::scene::ObjectTarget;
+use super::selection::SelectionRole;
+
+// Resolves the accumulated weighted colors over the scene
+const COMPOSITE_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+
+uniform sampler2D u_accumulation_map;
+uniform sampler2D u_weight_map;
+
+out vec4 out_color;
+
+void main()
+{
+	ivec2 texel = ivec2(gl_FragCoord.xy);
+	vec4 accumulation = texelFetch(u_accumulation_map, texel, 0);
+	float revealage = accumulation.a;
+	if (revealage >= 1.0)
+	{
+		discard;
+	}
+
+	float weight = texelFetch(u_weight_map, texel, 0).r;
+	out_color = vec4(accumulation.rgb / clamp(weight, 0.0001, 50000.0), 1.0 - revealage);
+}
+"#;
+
+/*
+*
+*	Render targets for weighted blended order independent transparency, sized to the canvas
+*
+*/
+struct WeightedTargets
+{
+	framebuffer: WebGlFramebuffer,
+	accumulation: WebGlTexture,
+	weight: WebGlTexture,
+	depth: WebGlRenderbuffer,
+	width: i32,
+	height: i32
+}
+
+pub struct Transparency
+{
+	order_independent: bool,
+	targets: Option<WeightedTargets>,
+	composite_program: Option<WebGlProgram>
+}
+
+impl Transparency
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			order_independent: false,
+			targets: None,
+			composite_program: None
+		}
+	}
+
+	pub fn is_order_independent(&self) -> bool
+	{
+		return self.order_independent;
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Override how the targeted objects use their alpha, with the cutoff used by mask mode
+	*
+	*/
+	pub fn set_alpha_mode(&mut self, target: &ObjectTarget, mode: AlphaMode, cutoff: f32) -> Result<usize, String>
+	{
+		let indices = self.resolve_object_target(target)?;
+		for n in &indices
+		{
+			self.objects[*n].material.alpha_mode = mode;
+			self.objects[*n].material.alpha_cutoff = cutoff.clamp(0.0, 1.0);
+		}
+
+		self.build_program_permutations()?;
+
+		return Ok(indices.len());
+	}
+
+	/*
+	*
+	*	Draw blended objects with weighted blended order independent transparency rather than sorting them.
+	*	Needs float render targets, which not every device has
+	*
+	*/
+	pub fn set_order_independent_transparency(&mut self, enabled: bool) -> Result<(), String>
+	{
+		if enabled && !matches!(self.context.get_extension("EXT_color_buffer_float"), Ok(Some(_)))
+		{
+			return Err(String::from("Order independent transparency needs EXT_color_buffer_float, which this device lacks"));
+		}
+
+		self.transparency.order_independent = enabled;
+		if !enabled
+		{
+			self.delete_weighted_targets();
+		}
+
+		// Blended objects need the WEIGHTED_OIT permutations, or the ones without
+		return self.build_program_permutations();
+	}
+
+	/*
+	*
+	*	Whether an object is left out of the opaque pass to be blended after it. Hidden line surfaces only
+	*	write depth, so they stay in the opaque pass whatever their material
+	*
+	*/
+	pub(in super) fn is_transparent(&self, n: usize) -> bool
+	{
+		let mode = self.get_render_mode(n);
+		return self.objects[n].material.alpha_mode == AlphaMode::Blend && mode.has_surface() && mode != RenderMode::HiddenLine;
+	}
+
+	pub(in super) fn is_drawn(&self, n: usize) -> bool
+	{
+		let role = self.selection.get_role(self.objects[n].id);
+		return self.objects[n].visible && role != SelectionRole::Ghosted && role != SelectionRole::Hidden;
+	}
+
+	/*
+	*
+	*	Blend the transparent objects over the opaque scene. With order independent transparency on, objects
+	*	whose shader writes the weight output are accumulated in any order, everything else is sorted back to front
+	*
+	*/
+	pub(in super) fn draw_transparent_objects(&mut self, world_matrices: &[Mat4], outline: bool)
+	{
+		let camera_position = m4_transform_point(&m4_inverse(&self.camera_matrix).unwrap_or(Mat4::identity()), &[0.0, 0.0, 0.0]);
+		let mut transparent: Vec<(usize, f32)> = (0..self.objects.len())
+			.filter(|n| self.is_drawn(*n) && self.is_transparent(*n))
+			.map(|n| (n, self.get_camera_distance(n, world_matrices, &camera_position)))
+			.collect();
+		if transparent.is_empty()
+		{
+			return;
+		}
+
+		// Furthest first
+		transparent.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+		let mut sorted: Vec<usize> = transparent.iter().map(|(n, _)| *n).collect();
+
+		if self.transparency.order_independent
+		{
+			let weighted: Vec<usize> = sorted.iter().copied().filter(|n| self.writes_weight(*n)).collect();
+			sorted.retain(|n| !weighted.contains(n));
+
+			if !weighted.is_empty()
+			{
+				if let Err(e) = self.draw_weighted_transparency(&weighted, world_matrices)
+				{
+					rust_error(&format!("Turning off order independent transparency: {}", e));
+					let _ = self.set_order_independent_transparency(false);
+				}
+			}
+		}
+
+		self.draw_sorted_transparency(&sorted, world_matrices, outline);
+	}
+
+	// Distance from the camera to the center of an object's bounds
+	fn get_camera_distance(&self, n: usize, world_matrices: &[Mat4], camera_position: &[f32; 3]) -> f32
+	{
+		let object = &self.objects[n];
+		let model_matrix = world_matrices.get(object.node).copied().unwrap_or(Mat4::identity());
+		let center = m4_transform_point
+		(
+			&model_matrix,
+			&[
+				(object.smallest[0] + object.largest[0]) / 2.0,
+				(object.smallest[1] + object.largest[1]) / 2.0,
+				(object.smallest[2] + object.largest[2]) / 2.0
+			]
+		);
+
+		return (0..3).map(|axis| (center[axis] - camera_position[axis]).powi(2)).sum::<f32>().sqrt();
+	}
+
+	// Custom shaders that don't write out_weight can't take part in the weighted pass
+	fn writes_weight(&mut self, n: usize) -> bool
+	{
+		return match self.get_program(self.get_features(&self.objects[n]))
+		{
+			Ok(program) => self.context.get_frag_data_location(&program, "out_weight") >= 0,
+			Err(_) => false
+		};
+	}
+
+	/*
+	*
+	*	Blend objects in the given order without writing depth. Each is drawn back faces first so the far
+	*	side of a closed object ends up under its near side
+	*
+	*/
+	fn draw_sorted_transparency(&mut self, objects: &[usize], world_matrices: &[Mat4], outline: bool)
+	{
+		if objects.is_empty()
+		{
+			return;
+		}
+
+		self.context.enable(WebGl2RenderingContext::BLEND);
+		self.context.blend_func_separate
+		(
+			WebGl2RenderingContext::SRC_ALPHA,
+			WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+			WebGl2RenderingContext::ONE,
+			WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA
+		);
+		self.context.depth_mask(false);
+		self.context.enable(WebGl2RenderingContext::CULL_FACE);
+		if outline
+		{
+			self.context.enable(WebGl2RenderingContext::STENCIL_TEST);
+		}
+
+		for n in objects
+		{
+			if let Err(e) = self.use_program_for(self.get_features(&self.objects[*n]))
+			{
+				rust_error(&format!("Skipping draw call for object {}: {}", n, e));
+				continue;
+			}
+
+			if outline
+			{
+				self.set_selection_stencil(self.selection.get_role(self.objects[*n].id));
+			}
+
+			self.set_surface_state(self.get_render_mode(*n));
+			self.bind_object(*n, world_matrices);
+			for face in [WebGl2RenderingContext::FRONT, WebGl2RenderingContext::BACK].iter()
+			{
+				self.context.cull_face(*face);
+				self.draw_object_elements(*n);
+			}
+		}
+
+		self.reset_surface_state();
+		if outline
+		{
+			self.context.stencil_mask(0xff);
+			self.context.disable(WebGl2RenderingContext::STENCIL_TEST);
+		}
+		self.context.cull_face(WebGl2RenderingContext::BACK);
+		self.context.disable(WebGl2RenderingContext::CULL_FACE);
+		self.context.depth_mask(true);
+		self.context.disable(WebGl2RenderingContext::BLEND);
+	}
+
+	/*
+	*
+	*	Accumulate the objects into the weighted targets, tested against the depth of the opaque scene, then
+	*	resolve them over the canvas
+	*
+	*/
+	fn draw_weighted_transparency(&mut self, objects: &[usize], world_matrices: &[Mat4]) -> Result<(), String>
+	{
+		self.prepare_weighted_targets()?;
+		let framebuffer = self.transparency.targets.as_ref().map(|targets| targets.framebuffer.clone());
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer.as_ref());
+
+		// The canvas depth can't be shared with a framebuffer, so the opaque objects are drawn again for theirs
+		self.context.clear(WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+		let view_projection = m4_multiply(&self.projection_matrix, &self.camera_matrix);
+		if let Err(e) = self.use_depth_program(&view_projection)
+		{
+			self.bind_scene_framebuffer();
+			return Err(e);
+		}
+		self.context.color_mask(false, false, false, false);
+		for n in 0..self.objects.len()
+		{
+			if self.is_drawn(n) && self.get_render_mode(n).has_surface() && !self.is_transparent(n)
+			{
+				self.bind_object(n, world_matrices);
+				self.draw_object_elements(n);
+			}
+		}
+		self.context.color_mask(true, true, true, true);
+
+		// Nothing accumulated, and nothing hidden
+		self.context.clear_bufferfv_with_f32_array(WebGl2RenderingContext::COLOR, 0, &[0.0, 0.0, 0.0, 1.0]);
+		self.context.clear_bufferfv_with_f32_array(WebGl2RenderingContext::COLOR, 1, &[0.0, 0.0, 0.0, 0.0]);
+
+		// Colors and weights add up, revealage multiplies down
+		self.context.enable(WebGl2RenderingContext::BLEND);
+		self.context.blend_func_separate
+		(
+			WebGl2RenderingContext::ONE,
+			WebGl2RenderingContext::ONE,
+			WebGl2RenderingContext::ZERO,
+			WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA
+		);
+		self.context.depth_mask(false);
+
+		for n in objects
+		{
+			if let Err(e) = self.use_program_for(self.get_features(&self.objects[*n]))
+			{
+				rust_error(&format!("Skipping draw call for object {}: {}", n, e));
+				continue;
+			}
+
+			self.set_surface_state(self.get_render_mode(*n));
+			self.bind_object(*n, world_matrices);
+			self.draw_object_elements(*n);
+		}
+		self.reset_surface_state();
+
+		self.context.depth_mask(true);
+		self.bind_scene_framebuffer();
+
+		let result = self.composite_weighted_transparency();
+		self.context.disable(WebGl2RenderingContext::BLEND);
+
+		return result;
+	}
+
+	fn composite_weighted_transparency(&mut self) -> Result<(), String>
+	{
+		if self.transparency.composite_program.is_none()
+		{
+			rust_verbose("Building transparency composite shader...");
+			self.transparency.composite_program = Some(self.build_builtin_program(FULLSCREEN_VERTEX_SHADER, COMPOSITE_FRAGMENT_SHADER)?);
+			rust_verbose("...transparency composite shader build complete.");
+		}
+
+		self.current_program = self.transparency.composite_program.clone();
+		self.context.use_program(self.current_program.as_ref());
+		let program = self.current_program.as_ref().unwrap();
+		let targets = self.transparency.targets.as_ref().ok_or("transparency targets are missing")?;
+
+		for (unit, texture, sampler) in [(0, &targets.accumulation, "u_accumulation_map"), (1, &targets.weight, "u_weight_map")].iter()
+		{
+			self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + unit);
+			self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+			let sampler_index = self.context.get_uniform_location(program, sampler);
+			self.context.uniform1i(sampler_index.as_ref(), *unit as i32);
+		}
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+
+		self.context.blend_func_separate
+		(
+			WebGl2RenderingContext::SRC_ALPHA,
+			WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+			WebGl2RenderingContext::ONE,
+			WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA
+		);
+		self.context.disable(WebGl2RenderingContext::DEPTH_TEST);
+		self.context.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+		self.context.enable(WebGl2RenderingContext::DEPTH_TEST);
+
+		return Ok(());
+	}
+
+	// Create the weighted targets, or recreate them when the canvas has changed size
+	fn prepare_weighted_targets(&mut self) -> Result<(), String>
+	{
+		let (width, height) = self.get_output_size();
+		if let Some(targets) = self.transparency.targets.as_ref()
+		{
+			if targets.width == width && targets.height == height
+			{
+				return Ok(());
+			}
+		}
+		self.delete_weighted_targets();
+
+		let accumulation = self.create_target_texture(WebGl2RenderingContext::RGBA16F, width, height)?;
+		let weight = self.create_target_texture(WebGl2RenderingContext::R16F, width, height)?;
+		let depth = self.context.create_renderbuffer().ok_or("failed to create transparency depth buffer")?;
+		self.context.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, Some(&depth));
+		self.context.renderbuffer_storage(WebGl2RenderingContext::RENDERBUFFER, WebGl2RenderingContext::DEPTH_COMPONENT24, width, height);
+		self.context.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, None);
+
+		let framebuffer = self.context.create_framebuffer().ok_or("failed to create transparency framebuffer")?;
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&framebuffer));
+		self.context.framebuffer_texture_2d(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::COLOR_ATTACHMENT0, WebGl2RenderingContext::TEXTURE_2D, Some(&accumulation), 0);
+		self.context.framebuffer_texture_2d(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::COLOR_ATTACHMENT1, WebGl2RenderingContext::TEXTURE_2D, Some(&weight), 0);
+		self.context.framebuffer_renderbuffer(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::DEPTH_ATTACHMENT, WebGl2RenderingContext::RENDERBUFFER, Some(&depth));
+		let draw_buffers: Array = [WebGl2RenderingContext::COLOR_ATTACHMENT0, WebGl2RenderingContext::COLOR_ATTACHMENT1].iter().map(|buffer| JsValue::from(*buffer)).collect();
+		self.context.draw_buffers(&draw_buffers);
+		let status = self.context.check_framebuffer_status(WebGl2RenderingContext::FRAMEBUFFER);
+		self.context.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+		self.transparency.targets = Some(WeightedTargets { framebuffer, accumulation, weight, depth, width, height });
+		if status != WebGl2RenderingContext::FRAMEBUFFER_COMPLETE
+		{
+			self.delete_weighted_targets();
+			return Err(format!("transparency framebuffer is incomplete ({:#x})", status));
+		}
+
+		return Ok(());
+	}
+
+	fn create_target_texture(&self, internal_format: u32, width: i32, height: i32) -> Result<WebGlTexture, String>
+	{
+		let target = WebGl2RenderingContext::TEXTURE_2D;
+		let texture = self.context.create_texture().ok_or("failed to create transparency target")?;
+		self.context.bind_texture(target, Some(&texture));
+		self.context.tex_storage_2d(target, 1, internal_format, width, height);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::NEAREST as i32);
+		self.context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::NEAREST as i32);
+		self.context.bind_texture(target, None);
+
+		return Ok(texture);
+	}
+
+	fn delete_weighted_targets(&mut self)
+	{
+		if let Some(targets) = self.transparency.targets.take()
+		{
+			self.context.delete_framebuffer(Some(&targets.framebuffer));
+			self.context.delete_texture(Some(&targets.accumulation));
+			self.context.delete_texture(Some(&targets.weight));
+			self.context.delete_renderbuffer(Some(&targets.depth));
+		}
+	}
+}
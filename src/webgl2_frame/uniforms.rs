@@ -0,0 +1,287 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlTexture;
+use web_sys::WebGlUniformLocation;
+use js_sys::Array;
+use js_sys::Float32Array;
+use std::convert::TryInto;
+
+use crate::logger::*;
+use crate::webgl2_wavefront_object::material::MaterialMap;
+use crate::webgl2_wavefront_object::sampler::TextureSampler;
+
+use super::WebGl2Frame;
+
+// Uniforms which are fed by the engine every frame and so can't be overridden from JS
+const ENGINE_UNIFORMS: [&str; 32] =
+[
+	"u_camera_matrix", "u_projection_matrix", "u_model_matrix", "u_normal_matrix", "u_mouse_position", "u_time", "u_resolution",
+	"u_light_count", "u_light_type", "u_light_color", "u_light_position", "u_light_direction", "u_light_range", "u_light_cone",
+	"u_ambient_color", "u_camera_position",
+	"u_base_color", "u_metallic", "u_roughness", "u_emissive", "u_normal_scale", "u_bump_scale", "u_occlusion_strength", "u_specular_color", "u_shininess",
+	"u_alpha_cutoff",
+	"u_prefiltered_levels", "u_environment_intensity",
+	"u_shadow_matrix", "u_shadow_light", "u_shadow_bias", "u_shadow_normal_offset"
+];
+
+// Samplers the engine binds its own textures to, besides the material maps
+const ENGINE_SAMPLERS: [&str; 6] = ["u_irradiance_map", "u_prefiltered_map", "u_brdf_lut", "u_background_map", "u_skybox_map", "u_shadow_map"];
+
+// The engine's textures take units 0 to 14, so textures bound to sampler uniforms are given units from here up
+const FIRST_USER_TEXTURE_UNIT: u32 = 15;
+
+const SAMPLER_TYPES: [u32; 5] =
+[
+	WebGl2RenderingContext::SAMPLER_2D,
+	WebGl2RenderingContext::SAMPLER_3D,
+	WebGl2RenderingContext::SAMPLER_CUBE,
+	WebGl2RenderingContext::SAMPLER_2D_SHADOW,
+	WebGl2RenderingContext::SAMPLER_2D_ARRAY
+];
+
+/*
+*
+*	A value for a user defined shader uniform. The variant is chosen from the type the linked program
+*	declares for the uniform, not from the JS value, so `1` can feed either a float or an int. A sampler
+*	holds a supplied texture along with the unit, past the engine's own, it is bound to
+*
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum UniformValue
+{
+	Float(f32),
+	Vec2([f32; 2]),
+	Vec3([f32; 3]),
+	Vec4([f32; 4]),
+	Int(i32),
+	Mat3([f32; 9]),
+	Mat4([f32; 16]),
+	Sampler { unit: u32, texture: WebGlTexture }
+}
+
+impl UniformValue
+{
+	pub fn from_js(value: &JsValue, gl_type: u32) -> Result<Self, String>
+	{
+		match gl_type
+		{
+			WebGl2RenderingContext::FLOAT => Ok(UniformValue::Float(js_value_to_f32(value)?)),
+			WebGl2RenderingContext::FLOAT_VEC2 => Ok(UniformValue::Vec2(js_value_to_f32_array::<2>(value)?)),
+			WebGl2RenderingContext::FLOAT_VEC3 => Ok(UniformValue::Vec3(js_value_to_f32_array::<3>(value)?)),
+			WebGl2RenderingContext::FLOAT_VEC4 => Ok(UniformValue::Vec4(js_value_to_f32_array::<4>(value)?)),
+			WebGl2RenderingContext::FLOAT_MAT3 => Ok(UniformValue::Mat3(js_value_to_f32_array::<9>(value)?)),
+			WebGl2RenderingContext::FLOAT_MAT4 => Ok(UniformValue::Mat4(js_value_to_f32_array::<16>(value)?)),
+			WebGl2RenderingContext::INT | WebGl2RenderingContext::BOOL => Ok(UniformValue::Int(js_value_to_i32(value)?)),
+			_ if SAMPLER_TYPES.contains(&gl_type) => Err(String::from("sampler uniforms take a texture name and can only be set with set_uniform")),
+			_ => Err(format!("Uniform type 0x{:x} is not supported", gl_type))
+		}
+	}
+
+	pub fn matches_gl_type(&self, gl_type: u32) -> bool
+	{
+		match self
+		{
+			UniformValue::Float(_) => gl_type == WebGl2RenderingContext::FLOAT,
+			UniformValue::Vec2(_) => gl_type == WebGl2RenderingContext::FLOAT_VEC2,
+			UniformValue::Vec3(_) => gl_type == WebGl2RenderingContext::FLOAT_VEC3,
+			UniformValue::Vec4(_) => gl_type == WebGl2RenderingContext::FLOAT_VEC4,
+			UniformValue::Int(_) => gl_type == WebGl2RenderingContext::INT || gl_type == WebGl2RenderingContext::BOOL,
+			UniformValue::Mat3(_) => gl_type == WebGl2RenderingContext::FLOAT_MAT3,
+			UniformValue::Mat4(_) => gl_type == WebGl2RenderingContext::FLOAT_MAT4,
+			UniformValue::Sampler { .. } => gl_type == WebGl2RenderingContext::SAMPLER_2D
+		}
+	}
+
+	pub fn apply(&self, context: &WebGl2RenderingContext, location: Option<&WebGlUniformLocation>)
+	{
+		match self
+		{
+			UniformValue::Float(value) => context.uniform1f(location, *value),
+			UniformValue::Vec2(value) => context.uniform2fv_with_f32_array(location, value),
+			UniformValue::Vec3(value) => context.uniform3fv_with_f32_array(location, value),
+			UniformValue::Vec4(value) => context.uniform4fv_with_f32_array(location, value),
+			UniformValue::Int(value) => context.uniform1i(location, *value),
+			UniformValue::Mat3(value) => context.uniform_matrix3fv_with_f32_array(location, false, value),
+			UniformValue::Mat4(value) => context.uniform_matrix4fv_with_f32_array(location, false, value),
+			UniformValue::Sampler { unit, texture } =>
+			{
+				context.active_texture(WebGl2RenderingContext::TEXTURE0 + unit);
+				context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+				context.active_texture(WebGl2RenderingContext::TEXTURE0);
+				context.uniform1i(location, *unit as i32);
+			}
+		}
+	}
+}
+
+impl WebGl2Frame
+{
+	/*
+	*
+	*	Validate a uniform against the linked program and store it so it is fed on every frame. A sampler2D
+	*	takes the name of a supplied texture, which is bound to a unit of its own past the engine's
+	*
+	*/
+	pub fn set_uniform(&mut self, name: &str, value: &JsValue) -> Result<(), String>
+	{
+		let engine_sampler = ENGINE_SAMPLERS.contains(&name) || MaterialMap::ALL.iter().any(|map| map.sampler() == name);
+		if ENGINE_UNIFORMS.contains(&name) || engine_sampler
+		{
+			return Err(format!("Uniform {} is managed by the engine and can't be set", name));
+		}
+
+		let gl_type = match self.get_active_uniform_type(name)
+		{
+			Some(gl_type) => gl_type,
+			None => return Err(format!("Uniform {} is not an active uniform of the linked program", name))
+		};
+
+		let uniform = match SAMPLER_TYPES.contains(&gl_type)
+		{
+			true => self.create_sampler_uniform(name, value, gl_type),
+			false => UniformValue::from_js(value, gl_type)
+		}
+		.map_err(|e| format!("Uniform {}: {}", name, e))?;
+		rust_verbose(&format!("Setting custom uniform {} to {:?}", name, uniform));
+		if let Some(replaced) = self.custom_uniforms.insert(name.to_owned(), uniform)
+		{
+			self.release_uniform(replaced);
+		}
+
+		return Ok(());
+	}
+
+	pub fn remove_uniform(&mut self, name: &str) -> bool
+	{
+		return match self.custom_uniforms.remove(name)
+		{
+			Some(removed) =>
+			{
+				self.release_uniform(removed);
+				true
+			},
+			None => false
+		};
+	}
+
+	/*
+	*
+	*	Take the named texture from the cache for a sampler uniform, on the first unit no other sampler
+	*	uniform holds. Only as many textures as the device has units past the engine's can be bound
+	*
+	*/
+	fn create_sampler_uniform(&mut self, name: &str, value: &JsValue, gl_type: u32) -> Result<UniformValue, String>
+	{
+		if gl_type != WebGl2RenderingContext::SAMPLER_2D
+		{
+			return Err(String::from("only sampler2D uniforms can be bound to a texture"));
+		}
+		let texture_name = value.as_string().ok_or("expected the name of a supplied texture")?;
+		let source_name = self.texture_cache.find_source_name(&texture_name).ok_or_else(|| format!("texture {} was not supplied", texture_name))?;
+
+		let max_units = self.context
+			.get_parameter(WebGl2RenderingContext::MAX_TEXTURE_IMAGE_UNITS)
+			.ok()
+			.and_then(|units| units.as_f64())
+			.unwrap_or(16.0) as u32;
+		let unit = (FIRST_USER_TEXTURE_UNIT..max_units)
+			.find(|unit| !self.custom_uniforms.iter().any(|(other, value)| match value
+			{
+				UniformValue::Sampler { unit: taken, .. } => other != name && taken == unit,
+				_ => false
+			}))
+			.ok_or_else(|| format!("this device only has {} texture units for sampler uniforms", max_units.saturating_sub(FIRST_USER_TEXTURE_UNIT)))?;
+
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0 + unit);
+		let acquired = self.texture_cache.acquire(&self.context, &source_name, TextureSampler::default());
+		self.context.active_texture(WebGl2RenderingContext::TEXTURE0);
+		let (texture, _) = acquired?;
+
+		return Ok(UniformValue::Sampler { unit, texture });
+	}
+
+	// Give back the texture a sampler uniform held
+	fn release_uniform(&mut self, uniform: UniformValue)
+	{
+		if let UniformValue::Sampler { texture, .. } = uniform
+		{
+			self.texture_cache.release(&self.context, &texture);
+		}
+	}
+
+	/*
+	*
+	*	Drop any stored uniforms the newly linked program no longer declares, or now declares with a different type
+	*
+	*/
+	pub(in super) fn revalidate_custom_uniforms(&mut self)
+	{
+		let names: Vec<String> = self.custom_uniforms.keys().cloned().collect();
+		for name in names
+		{
+			let still_valid = match self.get_active_uniform_type(&name)
+			{
+				Some(gl_type) => self.custom_uniforms[&name].matches_gl_type(gl_type),
+				None => false
+			};
+
+			if !still_valid
+			{
+				rust_warn(&format!("Custom uniform {} no longer matches the linked program and has been dropped", name));
+				if let Some(removed) = self.custom_uniforms.remove(&name)
+				{
+					self.release_uniform(removed);
+				}
+			}
+		}
+	}
+
+	pub(in super) fn apply_custom_uniforms(&self)
+	{
+		let program = match self.current_program.as_ref()
+		{
+			Some(program) => program,
+			None => return
+		};
+
+		for (name, value) in &self.custom_uniforms
+		{
+			rust_super_super_verbose(&format!("Passing custom uniform {} to shader", name));
+			let location = self.context.get_uniform_location(program, name);
+			value.apply(&self.context, location.as_ref());
+		}
+	}
+}
+
+fn js_value_to_f32(value: &JsValue) -> Result<f32, String>
+{
+	return value.as_f64().map(|v| v as f32).ok_or_else(|| String::from("expected a number"));
+}
+
+fn js_value_to_i32(value: &JsValue) -> Result<i32, String>
+{
+	if let Some(flag) = value.as_bool()
+	{
+		return Ok(flag as i32);
+	}
+
+	// A plain `as` cast would quietly truncate 1.5 or saturate 1e10 rather than reject them
+	return match value.as_f64()
+	{
+		Some(v) if v.is_finite() && v.fract() == 0.0 && v >= i32::MIN as f64 && v <= i32::MAX as f64 => Ok(v as i32),
+		Some(v) => Err(format!("expected an integer but got {}", v)),
+		None => Err(String::from("expected an integer"))
+	};
+}
+
+fn js_value_to_f32_array<const N: usize>(value: &JsValue) -> Result<[f32; N], String>
+{
+	if !Array::is_array(value) && !value.is_instance_of::<Float32Array>()
+	{
+		return Err(format!("expected an array of {} numbers", N));
+	}
+
+	let values = Float32Array::new(value).to_vec();
+	return values.try_into().map_err(|values: Vec<f32>| format!("expected {} numbers but got {}", N, values.len()));
+}
@@ -0,0 +1,222 @@
+use js_sys::Array;
+use js_sys::Function;
+use js_sys::Reflect;
+use js_sys::Uint8Array;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::ktx2::*;
+use crate::logger::*;
+
+// From the compressed texture extensions, which web-sys has no constants for
+const COMPRESSED_RGBA_ASTC_4X4_KHR: u32 = 0x93B0;
+const COMPRESSED_RGBA_BPTC_UNORM_EXT: u32 = 0x8E8C;
+const COMPRESSED_RGBA8_ETC2_EAC: u32 = 0x9278;
+const COMPRESSED_RGBA_S3TC_DXT5_EXT: u32 = 0x83F3;
+
+/*
+*
+*	The GPU formats KTX2 textures are uploaded in, from the most to the least preferred. RGBA8 needs no
+*	extension and is the fallback when the device offers none of the others
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompressedFormat
+{
+	Astc4x4,
+	Bc7,
+	Etc2,
+	Bc3,
+	Rgba8
+}
+
+impl CompressedFormat
+{
+	pub const ALL: [CompressedFormat; 5] =
+	[
+		CompressedFormat::Astc4x4,
+		CompressedFormat::Bc7,
+		CompressedFormat::Etc2,
+		CompressedFormat::Bc3,
+		CompressedFormat::Rgba8
+	];
+
+	// The target format name passed to the transcoder
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			CompressedFormat::Astc4x4 => "astc_4x4",
+			CompressedFormat::Bc7 => "bc7",
+			CompressedFormat::Etc2 => "etc2",
+			CompressedFormat::Bc3 => "bc3",
+			CompressedFormat::Rgba8 => "rgba8"
+		}
+	}
+
+	fn extension(&self) -> Option<&'static str>
+	{
+		match self
+		{
+			CompressedFormat::Astc4x4 => Some("WEBGL_compressed_texture_astc"),
+			CompressedFormat::Bc7 => Some("EXT_texture_compression_bptc"),
+			CompressedFormat::Etc2 => Some("WEBGL_compressed_texture_etc"),
+			CompressedFormat::Bc3 => Some("WEBGL_compressed_texture_s3tc"),
+			CompressedFormat::Rgba8 => None
+		}
+	}
+
+	fn gl(&self) -> u32
+	{
+		match self
+		{
+			CompressedFormat::Astc4x4 => COMPRESSED_RGBA_ASTC_4X4_KHR,
+			CompressedFormat::Bc7 => COMPRESSED_RGBA_BPTC_UNORM_EXT,
+			CompressedFormat::Etc2 => COMPRESSED_RGBA8_ETC2_EAC,
+			CompressedFormat::Bc3 => COMPRESSED_RGBA_S3TC_DXT5_EXT,
+			CompressedFormat::Rgba8 => WebGl2RenderingContext::RGBA8
+		}
+	}
+
+	// sRGB variants are uploaded as their linear counterparts, the same as decoded images are
+	fn from_vk_format(vk_format: u32) -> Option<Self>
+	{
+		match vk_format
+		{
+			VK_FORMAT_ASTC_4X4_UNORM_BLOCK | VK_FORMAT_ASTC_4X4_SRGB_BLOCK => Some(CompressedFormat::Astc4x4),
+			VK_FORMAT_BC7_UNORM_BLOCK | VK_FORMAT_BC7_SRGB_BLOCK => Some(CompressedFormat::Bc7),
+			VK_FORMAT_ETC2_R8G8B8A8_UNORM_BLOCK | VK_FORMAT_ETC2_R8G8B8A8_SRGB_BLOCK => Some(CompressedFormat::Etc2),
+			VK_FORMAT_BC3_UNORM_BLOCK | VK_FORMAT_BC3_SRGB_BLOCK => Some(CompressedFormat::Bc3),
+			VK_FORMAT_R8G8B8A8_UNORM | VK_FORMAT_R8G8B8A8_SRGB => Some(CompressedFormat::Rgba8),
+			_ => None
+		}
+	}
+}
+
+/*
+*
+*	The formats the context can take, most preferred first and always ending with RGBA8
+*
+*/
+pub fn get_supported_formats(context: &WebGl2RenderingContext) -> Vec<CompressedFormat>
+{
+	return CompressedFormat::ALL
+		.iter()
+		.filter(|format| match format.extension()
+		{
+			Some(extension) => matches!(context.get_extension(extension), Ok(Some(_))),
+			None => true
+		})
+		.copied()
+		.collect();
+}
+
+/*
+*
+*	Fill the bound texture from a KTX2 file, returning how many mip levels it brought, None when the chain
+*	is left to be generated the same as for decoded images, and whether it has alpha. Files already in a
+*	format the device takes are uploaded as they are once any Zstandard or zlib supercompression is undone.
+*	There is no Basis Universal transcoder built in, so ETC1S and UASTC files only load when the resources
+*	supply a texture_transcoder: a JS function taking the file's bytes and a target format name
+*	("astc_4x4", "bc7", "etc2", "bc3" or "rgba8") and returning {levels: [Uint8Array], alpha: bool},
+*	usually a thin wrapper around the Basis Universal transcoder's KTX2File. Files asking for their mip
+*	chain to be generated are transcoded to RGBA8, as compressed formats can't have one generated
+*
+*/
+pub fn upload_ktx2(context: &WebGl2RenderingContext, bytes: &[u8], texture_name: &str, transcoder: Option<&Function>, formats: &[CompressedFormat]) -> Result<(Option<usize>, bool), String>
+{
+	let ktx2 = Ktx2::parse(bytes).map_err(|e| "Texture ".to_owned() + texture_name + ": " + &e)?;
+
+	if !ktx2.needs_transcoding()
+	{
+		let format = CompressedFormat::from_vk_format(ktx2.vk_format)
+			.filter(|format| formats.contains(format))
+			.ok_or_else(|| format!("Texture {} is in Vulkan format {}, which this device can't sample", texture_name, ktx2.vk_format))?;
+		let levels = ktx2.decompress_levels().map_err(|e| "Texture ".to_owned() + texture_name + ": " + &e)?;
+		let level_slices: Vec<&[u8]> = levels.iter().map(|level| level.as_ref()).collect();
+		let generated = upload_levels(context, &ktx2, format, &level_slices, texture_name)?;
+		rust_info(&("Uploaded KTX2 texture ".to_owned() + texture_name + " as " + format.name()));
+
+		// Only uncompressed pixels are cheap to check for transparency
+		let alpha = format == CompressedFormat::Rgba8 && levels[0].chunks_exact(4).any(|pixel| pixel[3] < 255);
+		return Ok((if generated { None } else { Some(levels.len()) }, alpha));
+	}
+
+	let transcoder = transcoder.ok_or_else(|| "Texture ".to_owned() + texture_name + " is Basis Universal compressed, which isn't transcoded built in. Supply a texture_transcoder function with the resources to load it")?;
+	let format = match ktx2.generate_mipmaps
+	{
+		true => CompressedFormat::Rgba8,
+		false => formats.first().copied().unwrap_or(CompressedFormat::Rgba8)
+	};
+
+	let result = transcoder
+		.call2(&JsValue::NULL, &Uint8Array::from(bytes), &JsValue::from_str(format.name()))
+		.map_err(|e| "Failed to transcode texture ".to_owned() + texture_name + ": " + &e.as_string().unwrap_or_default())?;
+	let levels: Vec<Vec<u8>> = Reflect::get(&result, &JsValue::from_str("levels"))
+		.ok()
+		.filter(|levels| levels.is_instance_of::<Array>())
+		.map(|levels| Array::from(&levels).iter().filter_map(|level| level.dyn_into::<Uint8Array>().ok()).map(|level| level.to_vec()).collect())
+		.unwrap_or_default();
+	if levels.is_empty()
+	{
+		return Err("The transcoder returned no levels for texture ".to_owned() + texture_name);
+	}
+	let alpha = Reflect::get(&result, &JsValue::from_str("alpha")).ok().and_then(|alpha| alpha.as_bool()).unwrap_or(false);
+
+	let level_slices: Vec<&[u8]> = levels.iter().map(|level| level.as_slice()).collect();
+	let generated = upload_levels(context, &ktx2, format, &level_slices, texture_name)?;
+	rust_info(&("Transcoded KTX2 texture ".to_owned() + texture_name + " to " + format.name()));
+
+	return Ok((if generated { None } else { Some(levels.len()) }, alpha));
+}
+
+/*
+*
+*	Upload the given levels, returning whether the mip chain is still to be generated. Only the first
+*	level is used when the file asks for generation, which only RGBA8 can have
+*
+*/
+fn upload_levels(context: &WebGl2RenderingContext, ktx2: &Ktx2, format: CompressedFormat, levels: &[&[u8]], texture_name: &str) -> Result<bool, String>
+{
+	let generated = ktx2.generate_mipmaps && format == CompressedFormat::Rgba8;
+	if ktx2.generate_mipmaps && !generated
+	{
+		rust_warn(&("Texture ".to_owned() + texture_name + " asks for generated mipmaps, which " + format.name() + " can't have, so is sampled without them"));
+	}
+	let levels = if ktx2.generate_mipmaps { &levels[..1] } else { levels };
+
+	let target = WebGl2RenderingContext::TEXTURE_2D;
+	for (level, data) in levels.iter().enumerate()
+	{
+		let (width, height) = ktx2.get_level_size(level);
+		if format == CompressedFormat::Rgba8
+		{
+			context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array
+			(
+				target,
+				level as i32,
+				format.gl() as i32,
+				width as i32,
+				height as i32,
+				0,
+				WebGl2RenderingContext::RGBA,
+				WebGl2RenderingContext::UNSIGNED_BYTE,
+				Some(data)
+			)
+			.map_err(|e| "failed to send image data to texture buffer: ".to_owned() + &e.as_string().unwrap_or_default())?;
+		}
+		else
+		{
+			context.compressed_tex_image_2d_with_u8_array(target, level as i32, format.gl(), width as i32, height as i32, 0, data);
+		}
+	}
+
+	// Levels past those supplied aren't there to sample, unless they are generated from the first
+	if !generated
+	{
+		context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAX_LEVEL, levels.len() as i32 - 1);
+	}
+
+	return Ok(generated);
+}
@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+
+use crate::logger::*;
+
+use super::sampler::*;
+
+// Materials by name, as referenced by an OBJ's usemtl statements
+pub type MaterialLibrary = HashMap<String, Material>;
+
+/*
+*
+*	The texture maps a material can carry, each bound to its own texture unit
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MaterialMap
+{
+	BaseColor,
+	Metallic,
+	Roughness,
+	Normal,
+	Occlusion,
+	Emissive,
+	Specular,
+	Opacity,
+	Bump
+}
+
+impl MaterialMap
+{
+	pub const ALL: [MaterialMap; 9] =
+	[
+		MaterialMap::BaseColor,
+		MaterialMap::Metallic,
+		MaterialMap::Roughness,
+		MaterialMap::Normal,
+		MaterialMap::Occlusion,
+		MaterialMap::Emissive,
+		MaterialMap::Specular,
+		MaterialMap::Opacity,
+		MaterialMap::Bump
+	];
+
+	pub fn from_str(map: &str) -> Result<Self, String>
+	{
+		match map
+		{
+			"base_color" => Ok(MaterialMap::BaseColor),
+			"metallic" => Ok(MaterialMap::Metallic),
+			"roughness" => Ok(MaterialMap::Roughness),
+			"normal" => Ok(MaterialMap::Normal),
+			"occlusion" => Ok(MaterialMap::Occlusion),
+			"emissive" => Ok(MaterialMap::Emissive),
+			"specular" => Ok(MaterialMap::Specular),
+			"opacity" => Ok(MaterialMap::Opacity),
+			"bump" => Ok(MaterialMap::Bump),
+			_ => Err(format!("Unknown material map {}, expected base_color, metallic, roughness, normal, occlusion, emissive, specular, opacity or bump", map))
+		}
+	}
+
+	// Base color stays on unit 0 so shaders written for the single texture flow keep working. Units 6 to 11
+	// belong to the environment, skybox and shadow maps
+	pub fn unit(&self) -> u32
+	{
+		match self
+		{
+			MaterialMap::BaseColor => 0,
+			MaterialMap::Metallic => 1,
+			MaterialMap::Roughness => 2,
+			MaterialMap::Normal => 3,
+			MaterialMap::Occlusion => 4,
+			MaterialMap::Emissive => 5,
+			MaterialMap::Specular => 12,
+			MaterialMap::Opacity => 13,
+			MaterialMap::Bump => 14
+		}
+	}
+
+	pub fn sampler(&self) -> &'static str
+	{
+		match self
+		{
+			MaterialMap::BaseColor => "u_base_color_map",
+			MaterialMap::Metallic => "u_metallic_map",
+			MaterialMap::Roughness => "u_roughness_map",
+			MaterialMap::Normal => "u_normal_map",
+			MaterialMap::Occlusion => "u_occlusion_map",
+			MaterialMap::Emissive => "u_emissive_map",
+			MaterialMap::Specular => "u_specular_map",
+			MaterialMap::Opacity => "u_opacity_map",
+			MaterialMap::Bump => "u_bump_map"
+		}
+	}
+
+	pub fn define(&self) -> &'static str
+	{
+		match self
+		{
+			MaterialMap::BaseColor => "HAS_BASE_COLOR_MAP",
+			MaterialMap::Metallic => "HAS_METALLIC_MAP",
+			MaterialMap::Roughness => "HAS_ROUGHNESS_MAP",
+			MaterialMap::Normal => "HAS_NORMAL_MAP",
+			MaterialMap::Occlusion => "HAS_OCCLUSION_MAP",
+			MaterialMap::Emissive => "HAS_EMISSIVE_MAP",
+			MaterialMap::Specular => "HAS_SPECULAR_MAP",
+			MaterialMap::Opacity => "HAS_OPACITY_MAP",
+			MaterialMap::Bump => "HAS_BUMP_MAP"
+		}
+	}
+
+	pub fn bit(&self) -> u16
+	{
+		return 1 << self.unit();
+	}
+}
+
+/*
+*
+*	How the base color's alpha is used, as in glTF. Dissolved materials and base color textures with
+*	transparent pixels are blended unless told otherwise, Mask discards fragments under the alpha cutoff
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode
+{
+	Opaque,
+	Blend,
+	Mask
+}
+
+impl AlphaMode
+{
+	pub fn from_str(mode: &str) -> Result<Self, String>
+	{
+		match mode
+		{
+			"opaque" => Ok(AlphaMode::Opaque),
+			"blend" => Ok(AlphaMode::Blend),
+			"mask" => Ok(AlphaMode::Mask),
+			_ => Err(format!("Unknown alpha mode {}, expected opaque, blend or mask", mode))
+		}
+	}
+}
+
+/*
+*
+*	A metallic-roughness material with glTF semantics. Classic MTL materials are mapped on to it, and keep
+*	their Blinn-Phong specular terms for shaders that want them. `is_pbr` is set once any of the PBR
+*	extension statements is seen, telling the lit shader which model to shade with
+*
+*/
+#[derive(Clone, Debug)]
+pub struct Material
+{
+	pub name: String,
+	pub base_color: [f32; 4],
+	pub metallic: f32,
+	pub roughness: f32,
+	pub emissive: [f32; 3],
+	pub normal_scale: f32,
+	pub bump_scale: f32,
+	pub occlusion_strength: f32,
+	pub specular_color: [f32; 3],
+	pub shininess: f32,
+	pub is_pbr: bool,
+	pub alpha_mode: AlphaMode,
+	pub alpha_cutoff: f32,
+	pub maps: HashMap<MaterialMap, String>,
+	pub samplers: HashMap<MaterialMap, TextureSampler>
+}
+
+impl Material
+{
+	pub fn new(name: &str) -> Self
+	{
+		Self
+		{
+			name: name.to_owned(),
+			base_color: [1.0, 1.0, 1.0, 1.0],
+			metallic: 0.0,
+			roughness: 0.5,
+			emissive: [0.0, 0.0, 0.0],
+			normal_scale: 1.0,
+			bump_scale: 1.0,
+			occlusion_strength: 1.0,
+			specular_color: [0.25, 0.25, 0.25],
+			shininess: 32.0,
+			is_pbr: false,
+			alpha_mode: AlphaMode::Opaque,
+			alpha_cutoff: 0.5,
+			maps: HashMap::new(),
+			samplers: HashMap::new()
+		}
+	}
+
+	/*
+	*
+	*	Apply a single MTL statement. Returns false for statements that aren't understood
+	*
+	*/
+	fn apply_statement(&mut self, keyword: &str, arguments: &[&str], roughness_set: &mut bool) -> Result<bool, String>
+	{
+		match keyword
+		{
+			"Kd" =>
+			{
+				let color = parse_color(arguments)?;
+				self.base_color = [color[0], color[1], color[2], self.base_color[3]];
+			},
+			"Ks" => self.specular_color = parse_color(arguments)?,
+			"Ke" => self.emissive = parse_color(arguments)?,
+			"Ns" =>
+			{
+				self.shininess = parse_float(arguments)?;
+				// Classic materials get an equivalent roughness unless one is given explicitly
+				if !*roughness_set
+				{
+					self.roughness = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+				}
+			},
+			"d" => self.set_opacity(parse_float(arguments)?),
+			"Tr" => self.set_opacity(1.0 - parse_float(arguments)?),
+			"Pr" =>
+			{
+				self.roughness = parse_float(arguments)?;
+				*roughness_set = true;
+				self.is_pbr = true;
+			},
+			"Pm" =>
+			{
+				self.metallic = parse_float(arguments)?;
+				self.is_pbr = true;
+			},
+			// Sheen, clearcoat and anisotropy are read so they mark the material PBR, but aren't shaded
+			"Ps" | "Pc" | "Pcr" | "aniso" | "anisor" => self.is_pbr = true,
+			"map_Kd" => self.set_map(MaterialMap::BaseColor, arguments)?,
+			"map_Pm" =>
+			{
+				self.set_map(MaterialMap::Metallic, arguments)?;
+				self.is_pbr = true;
+			},
+			"map_Pr" =>
+			{
+				self.set_map(MaterialMap::Roughness, arguments)?;
+				self.is_pbr = true;
+			},
+			"norm" | "map_Pn" =>
+			{
+				self.set_map(MaterialMap::Normal, arguments)?;
+				if let Some(scale) = parse_option(arguments, "-bm")
+				{
+					self.normal_scale = scale;
+				}
+				self.is_pbr = true;
+			},
+			"map_ao" => self.set_map(MaterialMap::Occlusion, arguments)?,
+			"map_Ke" => self.set_map(MaterialMap::Emissive, arguments)?,
+			"map_Ks" => self.set_map(MaterialMap::Specular, arguments)?,
+			"map_d" => self.set_map(MaterialMap::Opacity, arguments)?,
+			"map_Bump" | "map_bump" | "bump" =>
+			{
+				self.set_map(MaterialMap::Bump, arguments)?;
+				if let Some(scale) = parse_option(arguments, "-bm")
+				{
+					self.bump_scale = scale;
+				}
+			},
+			_ => return Ok(false)
+		};
+
+		return Ok(true);
+	}
+
+	fn set_opacity(&mut self, opacity: f32)
+	{
+		self.base_color[3] = opacity.clamp(0.0, 1.0);
+		self.alpha_mode = if self.base_color[3] < 1.0 { AlphaMode::Blend } else { AlphaMode::Opaque };
+	}
+
+	// Map statements may carry options before the file name, which always comes last
+	fn set_map(&mut self, map: MaterialMap, arguments: &[&str]) -> Result<(), String>
+	{
+		let file_name = arguments.last().ok_or_else(|| String::from("expected a texture file name"))?;
+		self.maps.insert(map, (*file_name).to_owned());
+
+		// -clamp on stops the texture repeating outside 0 to 1
+		let mut sampler = TextureSampler::default();
+		if let Some(position) = arguments.iter().position(|argument| *argument == "-clamp")
+		{
+			if arguments.get(position + 1) == Some(&"on")
+			{
+				sampler.wrap_s = TextureWrap::Clamp;
+				sampler.wrap_t = TextureWrap::Clamp;
+			}
+		}
+		self.samplers.insert(map, sampler);
+
+		return Ok(());
+	}
+
+	pub fn get_sampler(&self, map: MaterialMap) -> TextureSampler
+	{
+		return self.samplers.get(&map).copied().unwrap_or_default();
+	}
+}
+
+impl Default for Material
+{
+	fn default() -> Self
+	{
+		return Material::new("default");
+	}
+}
+
+/*
+*
+*	Parse MTL text into a material library. Unlike the strict parser in wavefront_obj statements can come in
+*	any order and the PBR extensions (Pr, Pm, Ps, Pc, Ke, map_Pr, map_Pm, norm...) are understood.
+*	Statements that aren't recognised are skipped
+*
+*/
+pub fn parse_mtl(text: &str) -> Result<MaterialLibrary, String>
+{
+	let mut library: MaterialLibrary = HashMap::new();
+	let mut current: Option<Material> = None;
+	let mut roughness_set = false;
+
+	for (line_number, line) in text.lines().enumerate()
+	{
+		let line = line.split('#').next().unwrap_or("").trim();
+		let mut tokens = line.split_whitespace();
+		let keyword = match tokens.next()
+		{
+			Some(keyword) => keyword,
+			None => continue
+		};
+		let arguments: Vec<&str> = tokens.collect();
+
+		if keyword == "newmtl"
+		{
+			if let Some(material) = current.take()
+			{
+				library.insert(material.name.clone(), material);
+			}
+			let name = arguments.join(" ");
+			if name.is_empty()
+			{
+				return Err(format!("Line {}: newmtl without a material name", line_number + 1));
+			}
+			current = Some(Material::new(&name));
+			roughness_set = false;
+			continue;
+		}
+
+		let material = match current.as_mut()
+		{
+			Some(material) => material,
+			None =>
+			{
+				rust_warn(&format!("Line {}: skipping {} before any newmtl", line_number + 1, keyword));
+				continue;
+			}
+		};
+
+		match material.apply_statement(keyword, &arguments, &mut roughness_set)
+		{
+			Ok(true) => (),
+			Ok(false) => rust_super_verbose(&format!("Skipping unsupported MTL statement {} in material {}", keyword, material.name)),
+			Err(e) => return Err(format!("Line {}: {} {}", line_number + 1, keyword, e))
+		}
+	}
+
+	if let Some(material) = current.take()
+	{
+		library.insert(material.name.clone(), material);
+	}
+
+	return Ok(library);
+}
+
+/*
+*
+*	Whether the metallic and roughness maps are both bound, and to the same texture. A map whose texture
+*	failed to load isn't bound, so a file named for both only counts as packed once it is uploaded
+*
+*/
+pub fn shares_metallic_roughness_texture<T: PartialEq>(map_textures: &[(MaterialMap, T)]) -> bool
+{
+	let get_texture = |map: MaterialMap| map_textures.iter().find(|(texture_map, _)| *texture_map == map).map(|(_, texture)| texture);
+
+	return match (get_texture(MaterialMap::Metallic), get_texture(MaterialMap::Roughness))
+	{
+		(Some(metallic), Some(roughness)) => metallic == roughness,
+		_ => false
+	};
+}
+
+fn parse_float(arguments: &[&str]) -> Result<f32, String>
+{
+	return arguments
+		.first()
+		.and_then(|argument| argument.parse::<f32>().ok())
+		.ok_or_else(|| String::from("expected a number"));
+}
+
+// A single value is a grey, as some exporters write for Ke
+fn parse_color(arguments: &[&str]) -> Result<[f32; 3], String>
+{
+	let values: Vec<f32> = arguments.iter().map_while(|argument| argument.parse::<f32>().ok()).collect();
+
+	match values.len()
+	{
+		0 => Err(String::from("expected a color")),
+		1 | 2 => Ok([values[0], values[0], values[0]]),
+		_ => Ok([values[0], values[1], values[2]])
+	}
+}
+
+fn parse_option(arguments: &[&str], option: &str) -> Option<f32>
+{
+	let position = arguments.iter().position(|argument| *argument == option)?;
+	return arguments.get(position + 1).and_then(|value| value.parse::<f32>().ok());
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn parse_material(text: &str) -> Material
+	{
+		let mut library = parse_mtl(&("newmtl test\n".to_owned() + text)).unwrap();
+		return library.remove("test").unwrap();
+	}
+
+	#[test]
+	fn materials_are_named_and_comments_ignored()
+	{
+		let library = parse_mtl("# exported\nnewmtl painted metal\nKd 1 0 0 # red\n\nnewmtl glass\nd 0.5").unwrap();
+
+		assert_eq!(library.len(), 2);
+		assert_eq!(library["painted metal"].base_color, [1.0, 0.0, 0.0, 1.0]);
+		assert_eq!(library["glass"].base_color[3], 0.5);
+	}
+
+	#[test]
+	fn newmtl_needs_a_name()
+	{
+		assert!(parse_mtl("newmtl\nKd 1 1 1").unwrap_err().starts_with("Line 1"));
+	}
+
+	#[test]
+	fn bad_values_report_their_line()
+	{
+		let error = parse_mtl("newmtl test\nKd 1 1 1\nNs shiny").unwrap_err();
+		assert!(error.starts_with("Line 3: Ns"), "{}", error);
+	}
+
+	#[test]
+	fn single_value_colors_are_grey()
+	{
+		assert_eq!(parse_material("Ke 0.5").emissive, [0.5, 0.5, 0.5]);
+	}
+
+	#[test]
+	fn classic_materials_take_roughness_from_shininess()
+	{
+		let material = parse_material("Ns 98\nKs 1 1 1");
+
+		assert!(!material.is_pbr);
+		assert!((material.roughness - 0.1414).abs() < 1e-3);
+	}
+
+	#[test]
+	fn explicit_roughness_wins_over_shininess_in_either_order()
+	{
+		assert_eq!(parse_material("Pr 0.8\nNs 98").roughness, 0.8);
+		assert_eq!(parse_material("Ns 98\nPr 0.8").roughness, 0.8);
+	}
+
+	#[test]
+	fn pbr_statements_mark_the_material_pbr()
+	{
+		for statement in ["Pm 1", "Pr 0.5", "Pc 1", "map_Pm metal.png", "map_Pr rough.png", "norm normal.png"].iter()
+		{
+			assert!(parse_material(statement).is_pbr, "{}", statement);
+		}
+		assert!(!parse_material("map_Kd color.png\nmap_Bump bump.png").is_pbr);
+	}
+
+	#[test]
+	fn transparency_blends()
+	{
+		let material = parse_material("Tr 0.25");
+
+		assert_eq!(material.base_color[3], 0.75);
+		assert_eq!(material.alpha_mode, AlphaMode::Blend);
+		assert_eq!(parse_material("d 1").alpha_mode, AlphaMode::Opaque);
+	}
+
+	#[test]
+	fn map_statements_set_their_maps()
+	{
+		let material = parse_material("map_Kd color.png\nmap_Pm metal.png\nmap_Pr rough.png\nmap_Pn normal.png\nmap_ao ao.png\nmap_Ke glow.png\nmap_Ks spec.png\nmap_d alpha.png\nbump height.png");
+
+		let expected =
+		[
+			(MaterialMap::BaseColor, "color.png"),
+			(MaterialMap::Metallic, "metal.png"),
+			(MaterialMap::Roughness, "rough.png"),
+			(MaterialMap::Normal, "normal.png"),
+			(MaterialMap::Occlusion, "ao.png"),
+			(MaterialMap::Emissive, "glow.png"),
+			(MaterialMap::Specular, "spec.png"),
+			(MaterialMap::Opacity, "alpha.png"),
+			(MaterialMap::Bump, "height.png")
+		];
+		assert_eq!(material.maps.len(), expected.len());
+		for (map, file_name) in expected.iter()
+		{
+			assert_eq!(material.maps[map], *file_name, "{:?}", map);
+		}
+	}
+
+	#[test]
+	fn map_options_come_before_the_file_name()
+	{
+		let material = parse_material("map_Kd -clamp on -o 0.5 0.5 color.png\nnorm -bm 0.4 normal.png\nmap_Bump -bm 2 bump.png\nmap_Ks -clamp off spec.png");
+
+		assert_eq!(material.maps[&MaterialMap::BaseColor], "color.png");
+		assert_eq!(material.get_sampler(MaterialMap::BaseColor).wrap_s, TextureWrap::Clamp);
+		assert_eq!(material.get_sampler(MaterialMap::BaseColor).wrap_t, TextureWrap::Clamp);
+		assert_eq!(material.get_sampler(MaterialMap::Specular), TextureSampler::default());
+		assert_eq!(material.normal_scale, 0.4);
+		assert_eq!(material.bump_scale, 2.0);
+	}
+
+	#[test]
+	fn maps_need_a_file_name()
+	{
+		assert!(parse_mtl("newmtl test\nmap_Kd").is_err());
+	}
+
+	#[test]
+	fn metallic_roughness_is_packed_when_both_share_a_texture()
+	{
+		assert!(shares_metallic_roughness_texture(&[(MaterialMap::Metallic, 1), (MaterialMap::BaseColor, 2), (MaterialMap::Roughness, 1)]));
+		assert!(!shares_metallic_roughness_texture(&[(MaterialMap::Metallic, 1), (MaterialMap::Roughness, 2)]));
+	}
+
+	#[test]
+	fn metallic_roughness_is_not_packed_when_a_map_is_missing()
+	{
+		// Regression: a metallic and roughness file of the same name used to count as packed even when
+		// its texture never loaded, leaving the shader reading an unbound sampler
+		let material = parse_material("map_Pm orm.png\nmap_Pr orm.png");
+		assert_eq!(material.maps[&MaterialMap::Metallic], material.maps[&MaterialMap::Roughness]);
+
+		assert!(!shares_metallic_roughness_texture(&[(MaterialMap::Roughness, 1)]));
+		assert!(!shares_metallic_roughness_texture(&[(MaterialMap::Metallic, 1)]));
+		assert!(!shares_metallic_roughness_texture::<i32>(&[]));
+	}
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::matrix_helper::cross;
+use crate::matrix_helper::dot;
+use crate::matrix_helper::normalize;
+use crate::matrix_helper::sub;
+use crate::raycast::*;
+use crate::raycast::bvh::Bvh;
+
+// Meshes with fewer triangles than this are brute force ray cast rather than building a BVH
+const BVH_MIN_TRIANGLES: usize = 256;
+
+/*
+*
+*	A CPU side copy of the geometry buffered for an object, laid out the same as the GPU buffers
+*
+*/
+pub struct MeshData
+{
+	pub positions: Vec<f32>,
+	pub texcoords: Vec<f32>,
+	pub normals: Vec<f32>,
+	pub indices: Vec<u32>,
+	pub bvh: Option<Bvh>
+}
+
+impl MeshData
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			positions: Vec::new(),
+			texcoords: Vec::new(),
+			normals: Vec::new(),
+			indices: Vec::new(),
+			bvh: None
+		}
+	}
+
+	/*
+	*
+	*	Build the mesh from a vertex list with one optional uv pair and one normal per vertex
+	*
+	*/
+	pub fn from_vertices(positions: Vec<f32>, texcoords: Vec<f32>, normals: Vec<f32>, indices: Vec<u32>) -> Self
+	{
+		let mut mesh = Self
+		{
+			positions,
+			texcoords,
+			normals,
+			indices,
+			bvh: None
+		};
+
+		if mesh.get_triangle_count() >= BVH_MIN_TRIANGLES
+		{
+			mesh.bvh = Some(Bvh::build(&mesh.positions, &mesh.indices));
+		}
+
+		return mesh;
+	}
+
+	pub fn get_triangle_count(&self) -> usize
+	{
+		return self.indices.len() / 3;
+	}
+
+	pub fn intersect(&self, ray: &Ray) -> Option<RayHit>
+	{
+		return intersect_mesh(&self.positions, &self.indices, self.bvh.as_ref(), ray);
+	}
+
+	pub fn get_face_normal(&self, triangle: usize) -> [f32; 3]
+	{
+		let [a, b, c] = get_triangle(&self.positions, &self.indices, triangle);
+		return normalize(&cross(&sub(&b, &a), &sub(&c, &a)));
+	}
+
+	/*
+	*
+	*	Interpolate the vertex normals at a hit, falling back to the face normal
+	*
+	*/
+	pub fn get_normal(&self, hit: &RayHit) -> [f32; 3]
+	{
+		if self.normals.len() != self.positions.len()
+		{
+			return self.get_face_normal(hit.triangle);
+		}
+
+		let normal = |n: usize| {
+			let index = self.indices[hit.triangle * 3 + n] as usize * 3;
+			[self.normals[index], self.normals[index + 1], self.normals[index + 2]]
+		};
+		let (normal_0, normal_1, normal_2) = (normal(0), normal(1), normal(2));
+		let w = 1.0 - hit.u - hit.v;
+
+		return normalize
+		(
+			&[
+				normal_0[0] * w + normal_1[0] * hit.u + normal_2[0] * hit.v,
+				normal_0[1] * w + normal_1[1] * hit.u + normal_2[1] * hit.v,
+				normal_0[2] * w + normal_1[2] * hit.u + normal_2[2] * hit.v
+			]
+		);
+	}
+
+	/*
+	*
+	*	Every triangle edge once, as pairs of indices for drawing as LINES. Textured meshes give each triangle
+	*	its own vertices, so edges are matched on position rather than index to avoid drawing them twice
+	*
+	*/
+	pub fn get_edge_indices(&self) -> Vec<u32>
+	{
+		let mut welded: HashMap<[u32; 3], u32> = HashMap::new();
+		let position_key = |index: u32| {
+			let start = index as usize * 3;
+			[self.positions[start].to_bits(), self.positions[start + 1].to_bits(), self.positions[start + 2].to_bits()]
+		};
+
+		let mut seen: HashSet<(u32, u32)> = HashSet::new();
+		let mut edges: Vec<u32> = Vec::new();
+		for triangle in self.indices.chunks_exact(3)
+		{
+			for (a, b) in [(triangle[0], triangle[1]), (triangle[1], triangle[2]), (triangle[2], triangle[0])].iter()
+			{
+				let welded_a = *welded.entry(position_key(*a)).or_insert(*a);
+				let welded_b = *welded.entry(position_key(*b)).or_insert(*b);
+				if seen.insert((welded_a.min(welded_b), welded_a.max(welded_b)))
+				{
+					edges.push(*a);
+					edges.push(*b);
+				}
+			}
+		}
+
+		return edges;
+	}
+
+	/*
+	*
+	*	Interpolate the uv coordinates at a hit, if the mesh has any
+	*
+	*/
+	pub fn get_uv(&self, hit: &RayHit) -> Option<[f32; 2]>
+	{
+		if self.texcoords.is_empty()
+		{
+			return None;
+		}
+
+		let uv = |n: usize| {
+			let index = self.indices[hit.triangle * 3 + n] as usize * 2;
+			[self.texcoords[index], self.texcoords[index + 1]]
+		};
+		let (uv_0, uv_1, uv_2) = (uv(0), uv(1), uv(2));
+		let w = 1.0 - hit.u - hit.v;
+
+		return Some
+		(
+			[
+				uv_0[0] * w + uv_1[0] * hit.u + uv_2[0] * hit.v,
+				uv_0[1] * w + uv_1[1] * hit.u + uv_2[1] * hit.v
+			]
+		);
+	}
+}
+
+/*
+*
+*	Area weighted vertex normals. Vertices shared between triangles come out smooth, unshared ones flat
+*
+*/
+pub fn compute_vertex_normals(positions: &[f32], indices: &[u32]) -> Vec<f32>
+{
+	let mut normals: Vec<f32> = vec![0.0; positions.len()];
+
+	for triangle in 0..(indices.len() / 3)
+	{
+		let [a, b, c] = get_triangle(positions, indices, triangle);
+		// Left unnormalised so larger triangles weigh more
+		let face_normal = cross(&sub(&b, &a), &sub(&c, &a));
+
+		for corner in 0..3
+		{
+			let index = indices[triangle * 3 + corner] as usize * 3;
+			normals[index] += face_normal[0];
+			normals[index + 1] += face_normal[1];
+			normals[index + 2] += face_normal[2];
+		}
+	}
+
+	for normal in normals.chunks_mut(3)
+	{
+		let normalized = normalize(&[normal[0], normal[1], normal[2]]);
+		normal.copy_from_slice(&normalized);
+	}
+
+	return normals;
+}
+
+/*
+*
+*	Per vertex tangents from the MikkTSpace algorithm, as x, y, z and the handedness of the bitangent in w,
+*	so they match the tangent space most normal map bakers use. Texture v is expected to run down the
+*	image, as it is buffered, and is turned back up for MikkTSpace so the bitangent points up it as normal
+*	maps expect. Vertices MikkTSpace leaves without a tangent, such as those only on triangles with no uv
+*	area, get any direction along the surface
+*
+*/
+pub fn compute_vertex_tangents(positions: &[f32], normals: &[f32], texcoords: &[f32], indices: &[u32]) -> Vec<f32>
+{
+	let mut geometry = TangentGeometry { positions, normals, texcoords, indices, tangents: vec![0.0; positions.len() / 3 * 4] };
+	// On failure every tangent is left zero and falls back below
+	bevy_mikktspace::generate_tangents(&mut geometry);
+
+	let mut tangents = geometry.tangents;
+	for (index, tangent) in tangents.chunks_mut(4).enumerate()
+	{
+		let direction = [tangent[0], tangent[1], tangent[2]];
+		if direction.iter().all(|value| value.is_finite()) && dot(&direction, &direction) > 0.5
+		{
+			continue;
+		}
+
+		let normal = [normals[index * 3], normals[index * 3 + 1], normals[index * 3 + 2]];
+		let axis = if normal[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+		let orthogonal = normalize(&cross(&axis, &normal));
+		tangent.copy_from_slice(&[orthogonal[0], orthogonal[1], orthogonal[2], 1.0]);
+	}
+
+	return tangents;
+}
+
+/*
+*
+*	An indexed triangle list seen through MikkTSpace's interface, collecting the tangents it sets per vertex
+*
+*/
+struct TangentGeometry<'a>
+{
+	positions: &'a [f32],
+	normals: &'a [f32],
+	texcoords: &'a [f32],
+	indices: &'a [u32],
+	tangents: Vec<f32>
+}
+
+impl TangentGeometry<'_>
+{
+	fn vertex(&self, face: usize, vert: usize) -> usize
+	{
+		return self.indices[face * 3 + vert] as usize;
+	}
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_>
+{
+	fn num_faces(&self) -> usize
+	{
+		return self.indices.len() / 3;
+	}
+
+	fn num_vertices_of_face(&self, _face: usize) -> usize
+	{
+		return 3;
+	}
+
+	fn position(&self, face: usize, vert: usize) -> [f32; 3]
+	{
+		let index = self.vertex(face, vert) * 3;
+		return [self.positions[index], self.positions[index + 1], self.positions[index + 2]];
+	}
+
+	fn normal(&self, face: usize, vert: usize) -> [f32; 3]
+	{
+		let index = self.vertex(face, vert) * 3;
+		return [self.normals[index], self.normals[index + 1], self.normals[index + 2]];
+	}
+
+	fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2]
+	{
+		let index = self.vertex(face, vert) * 2;
+		return [self.texcoords[index], 1.0 - self.texcoords[index + 1]];
+	}
+
+	fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize)
+	{
+		let index = self.vertex(face, vert) * 4;
+		self.tangents[index..index + 4].copy_from_slice(&tangent);
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn assert_close(a: f32, b: f32, tolerance: f32)
+	{
+		assert!((a - b).abs() <= tolerance, "{} is not within {} of {}", a, tolerance, b);
+	}
+
+	fn get_tangent(tangents: &[f32], index: usize) -> ([f32; 3], f32)
+	{
+		return ([tangents[index * 4], tangents[index * 4 + 1], tangents[index * 4 + 2]], tangents[index * 4 + 3]);
+	}
+
+	// A unit quad facing +z with one vertex per triangle corner, as textured objects are buffered
+	fn quad(texcoords: [[f32; 2]; 4]) -> (Vec<f32>, Vec<f32>, Vec<f32>, Vec<u32>)
+	{
+		let corners = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+		let mut positions: Vec<f32> = Vec::new();
+		let mut uvs: Vec<f32> = Vec::new();
+		for corner in [0, 1, 2, 0, 2, 3]
+		{
+			positions.extend_from_slice(&corners[corner]);
+			// Buffered with v running down the image
+			uvs.extend_from_slice(&[texcoords[corner][0], 1.0 - texcoords[corner][1]]);
+		}
+		let normals = [0.0, 0.0, 1.0].repeat(6);
+
+		return (positions, normals, uvs, (0..6).collect());
+	}
+
+	#[test]
+	fn tangents_are_orthogonal_to_curved_normals()
+	{
+		// A bent strip whose smooth normals lean away from the face normals
+		let mut positions: Vec<f32> = Vec::new();
+		let mut normals: Vec<f32> = Vec::new();
+		let mut texcoords: Vec<f32> = Vec::new();
+		let mut indices: Vec<u32> = Vec::new();
+		for column in 0..5
+		{
+			let angle = column as f32 * 0.3;
+			for row in 0..2
+			{
+				positions.extend_from_slice(&[angle.sin(), row as f32, angle.cos()]);
+				normals.extend_from_slice(&[angle.sin(), 0.0, angle.cos()]);
+				texcoords.extend_from_slice(&[column as f32 / 4.0, 1.0 - row as f32]);
+			}
+			if column > 0
+			{
+				let base = (column as u32 - 1) * 2;
+				indices.extend_from_slice(&[base, base + 2, base + 3, base, base + 3, base + 1]);
+			}
+		}
+
+		let tangents = compute_vertex_tangents(&positions, &normals, &texcoords, &indices);
+		for index in 0..positions.len() / 3
+		{
+			let (tangent, handedness) = get_tangent(&tangents, index);
+			let normal = [normals[index * 3], normals[index * 3 + 1], normals[index * 3 + 2]];
+			assert_close(dot(&tangent, &tangent), 1.0, 1e-4);
+			assert_close(dot(&tangent, &normal), 0.0, 1e-4);
+			assert_close(handedness.abs(), 1.0, 0.0);
+		}
+	}
+
+	#[test]
+	fn mirrored_uvs_flip_the_handedness()
+	{
+		let (positions, normals, texcoords, indices) = quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+		let tangents = compute_vertex_tangents(&positions, &normals, &texcoords, &indices);
+		for index in 0..6
+		{
+			let (tangent, handedness) = get_tangent(&tangents, index);
+			assert_close(tangent[0], 1.0, 1e-4);
+			assert_eq!(handedness, 1.0);
+		}
+
+		// Mirrored along u, so the tangent turns around while the bitangent still points up
+		let (positions, normals, texcoords, indices) = quad([[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+		let tangents = compute_vertex_tangents(&positions, &normals, &texcoords, &indices);
+		for index in 0..6
+		{
+			let (tangent, handedness) = get_tangent(&tangents, index);
+			assert_close(tangent[0], -1.0, 1e-4);
+			assert_eq!(handedness, -1.0);
+		}
+	}
+
+	#[test]
+	fn degenerate_triangles_still_get_usable_tangents()
+	{
+		// Every corner on the same uv, so there is no uv area to derive a tangent from
+		let (positions, normals, texcoords, indices) = quad([[0.5, 0.5]; 4]);
+		// A second mesh with a collapsed, zero area triangle
+		let collapsed_positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0];
+		let collapsed_normals = [0.0, 0.0, 1.0].repeat(3);
+		let collapsed_texcoords = [0.0, 1.0, 0.5, 1.0, 1.0, 1.0];
+
+		for tangents in [
+			compute_vertex_tangents(&positions, &normals, &texcoords, &indices),
+			compute_vertex_tangents(&collapsed_positions, &collapsed_normals, &collapsed_texcoords, &[0, 1, 2])
+		]
+		{
+			for index in 0..tangents.len() / 4
+			{
+				let (tangent, handedness) = get_tangent(&tangents, index);
+				assert!(tangent.iter().all(|value| value.is_finite()));
+				assert_close(dot(&tangent, &tangent), 1.0, 1e-4);
+				assert_close(tangent[2], 0.0, 1e-4);
+				assert_close(handedness.abs(), 1.0, 0.0);
+			}
+		}
+	}
+}
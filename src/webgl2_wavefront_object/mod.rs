@@ -1,55 +1,88 @@
 use web_sys::WebGl2RenderingContext;
 use web_sys::WebGlProgram;
 use web_sys::WebGlBuffer;
+use web_sys::WebGlTexture;
 //use wasm_bindgen::prelude::*;
 //use wasm_bindgen::JsCast;
 use rand::Rng;
-use std::collections::HashMap;
-
 
 use crate::logger::*;
+use crate::utils::get_attribute_location;
+use crate::scene_graph::ROOT_NODE;
+use crate::webgl2_frame::render_modes::RenderMode;
+use mesh::MeshData;
+use mesh::compute_vertex_tangents;
+use material::*;
+use sampler::TextureSampler;
+use texture_cache::TextureCache;
 
 pub struct WebGl2WavefrontObject
 {
 	pub marked_for_deletion: bool,
+	pub id: u32,
+	pub node: usize,
+	pub visible: bool,
+	// Overrides the frame's render mode when set
+	pub render_mode: Option<RenderMode>,
 	obj: wavefront_obj::obj::Object,
-	mtls: Option<wavefront_obj::mtl::MtlSet>,
+	pub material: Material,
 	pub vertex_buffer: Option<WebGlBuffer>,
 	pub vertex_and_texture_buffer: Option<WebGlBuffer>,
 	pub vertex_index_buffer: Option<WebGlBuffer>,
     pub indices_size: usize,
+	// Built the first time the object is drawn as lines
+	pub edge_index_buffer: Option<WebGlBuffer>,
+	pub edge_indices_size: usize,
 	pub color_buffer: Option<WebGlBuffer>,
-    textures: Option<HashMap<String, String>>,
-	pub texture_height: i32,
-	pub texture_width: i32,
+	pub normal_buffer: Option<WebGlBuffer>,
+	// Only built for textured objects with a normal or bump map
+	pub tangent_buffer: Option<WebGlBuffer>,
+	pub map_textures: Vec<(MaterialMap, WebGlTexture)>,
 	pub largest: [f32; 3],
-	pub smallest: [f32; 3]
+	pub smallest: [f32; 3],
+	pub mesh: MeshData
 } 
 
 impl WebGl2WavefrontObject
 {
-    pub fn new(obj: wavefront_obj::obj::Object, mtls: Option<wavefront_obj::mtl::MtlSet>, textures: Option<HashMap<String, String>>) -> Result<Self, String>
+    pub fn new(obj: wavefront_obj::obj::Object, materials: &MaterialLibrary) -> Result<Self, String>
     {
-        let object = Self {
+        let mut object = Self {
 			marked_for_deletion: false,
+			id: 0,
+			node: ROOT_NODE,
+			visible: true,
+			render_mode: None,
 			obj: obj,
-			mtls: mtls,
+			material: Material::default(),
 			vertex_buffer: None,
 			vertex_and_texture_buffer: None,
 			vertex_index_buffer: None,
 			indices_size: 0,
+			edge_index_buffer: None,
+			edge_indices_size: 0,
 			color_buffer: None,
-            textures: textures,
-			texture_height: 0,
-			texture_width: 0,
+			normal_buffer: None,
+			tangent_buffer: None,
+			map_textures: Vec::new(),
 			largest: [0.0, 0.0, 0.0],
-			smallest: [0.0, 0.0, 0.0]
+			smallest: [0.0, 0.0, 0.0],
+			mesh: MeshData::new()
         };
 
+		if let Some(material_name) = object.get_material_name()
+		{
+			match materials.get(&material_name)
+			{
+				Some(material) => object.material = material.clone(),
+				None => rust_warn(&("Material ".to_owned() + &material_name + " used by object " + object.obj.name.as_str() + " was not supplied"))
+			}
+		}
+
         return Ok(object);
     }
 
-    pub fn buffer(&mut self, context: &WebGl2RenderingContext, program: &Option<WebGlProgram>) -> Result<(), String>
+    pub fn buffer(&mut self, context: &WebGl2RenderingContext, program: &Option<WebGlProgram>, textures: &mut TextureCache) -> Result<(), String>
 	{
 		/*
 		*
@@ -66,7 +99,20 @@ impl WebGl2WavefrontObject
 		let texture_indices: Vec<u16> = self.get_texture_indices();
 		rust_verbose(&("Texutre Indices is size: ".to_owned() + texture_indices.len().to_string().as_str()));
 
-		if self.textures != None
+		// Only objects whose material resolves a supplied texture, and with texture coordinates on every
+		// corner to sample it by, take the textured path
+		let texture_names: Vec<(MaterialMap, String)> = MaterialMap::ALL
+			.iter()
+			.filter_map(|map| self.get_texture_name(textures, *map).map(|texture_name| (*map, texture_name)))
+			.collect();
+		let has_texcoords = texture_indices.len() == vertex_indices.len()
+			&& texture_indices.iter().all(|index| (*index as usize * 2 + 1) < texture_vertices.len());
+		if !texture_names.is_empty() && !has_texcoords
+		{
+			rust_warn(&("Object: ".to_owned() + self.obj.name.as_str() + " has textures but not a texture coordinate on every corner, drawing it untextured"));
+		}
+
+		if !texture_names.is_empty() && has_texcoords
 		{
 			rust_verbose(&("Object: ".to_owned() + self.obj.name.as_str() + " identified as textured model. Processing accordingly"));
 			
@@ -99,61 +145,46 @@ impl WebGl2WavefrontObject
 				rust_verbose(&("...buffering complete."));
 
 				//Tell GPU how to extract vertex data from the buffer
-				let position_attribute_location = context.get_attrib_location(program.as_ref().unwrap(), "a_position") as u32;
-				context.vertex_attrib_pointer_with_i32
-				(
-					position_attribute_location, //index
-					3, //size
-					WebGl2RenderingContext::FLOAT, //data type
-					false, //normalized
-					20, //stride
-					0 //offset
-				);
+				if let Some(position_attribute_location) = get_attribute_location(context, program.as_ref().unwrap(), "a_position")
+				{
+					context.vertex_attrib_pointer_with_i32
+					(
+						position_attribute_location, //index
+						3, //size
+						WebGl2RenderingContext::FLOAT, //data type
+						false, //normalized
+						20, //stride
+						0 //offset
+					);
+					context.enable_vertex_attrib_array(position_attribute_location);
+				}
 
 				//Tell GPU how to extract texture data from the buffer
-				let texture_attribute_location = context.get_attrib_location(program.as_ref().unwrap(), "a_texcoord") as u32;
-				context.vertex_attrib_pointer_with_i32
-				(
-					texture_attribute_location, //index
-					2, //size
-					WebGl2RenderingContext::FLOAT, //data type
-					false, //normalized 
-					20, //stride
-					12 //offset
-				);
-
-				context.enable_vertex_attrib_array(position_attribute_location);
-				context.enable_vertex_attrib_array(texture_attribute_location);
-				
-				//Buffer the texture image
-				rust_verbose(&("Starting to buffer texture image... "));
-				let texture = context.create_texture().ok_or("failed to create texture")?;
-				context.active_texture(WebGl2RenderingContext::TEXTURE0);
-			    context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
-				let image = self.create_image_as_uint8_array()?;
-				context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
-				let _ = 
-				match 
-					context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_js_u8_array
+				if let Some(texture_attribute_location) = get_attribute_location(context, program.as_ref().unwrap(), "a_texcoord")
+				{
+					context.vertex_attrib_pointer_with_i32
 					(
-						WebGl2RenderingContext::TEXTURE_2D,
-						0,
-						WebGl2RenderingContext::RGBA8 as i32,
-						self.texture_height,
-						self.texture_width,
-						//320,
-						//320,
-						0,
-						WebGl2RenderingContext::RGBA, // format
-						WebGl2RenderingContext::UNSIGNED_BYTE, // type
-						Some(&image)
-					)
+						texture_attribute_location, //index
+						2, //size
+						WebGl2RenderingContext::FLOAT, //data type
+						false, //normalized 
+						20, //stride
+						12 //offset
+					);
+					context.enable_vertex_attrib_array(texture_attribute_location);
+				}
+				
+				//Buffer the material's texture images, each to its own unit
+				for (map, texture_name) in texture_names
 				{
-					Ok(result) => result,
-					Err(_err) => panic!("failed to send image data to texture buffer.")
-				};
-				context.generate_mipmap(WebGl2RenderingContext::TEXTURE_2D);
-				rust_verbose(&("...texture buffering complete."));
+					// Only a missing base color stops the object loading, other maps are left off
+					match self.buffer_texture(context, textures, map, &texture_name)
+					{
+						Ok(texture) => self.map_textures.push((map, texture)),
+						Err(e) if map == MaterialMap::BaseColor => return Err(e),
+						Err(e) => rust_warn(&("Skipping texture ".to_owned() + &texture_name + ": " + &e))
+					}
+				}
 
 			/*
 			Manage Indices for model
@@ -173,6 +204,19 @@ impl WebGl2WavefrontObject
 				);
 			}
 			rust_verbose(&("...buffering complete."));
+
+			// Keep a CPU side copy of the buffered geometry for ray casting
+			let positions: Vec<f32> = merged_array.chunks(5).flat_map(|vertex| vertex[0..3].to_vec()).collect();
+			let texcoords: Vec<f32> = merged_array.chunks(5).flat_map(|vertex| vertex[3..5].to_vec()).collect();
+			let indices: Vec<u32> = (0..(merged_array.len() / 5) as u32).collect();
+			let normals = self.get_vertex_normals(&positions, &indices, &vertex_indices, true);
+			self.buffer_normals(context, &normals);
+			if self.get_map_texture(MaterialMap::Normal).is_some() || self.get_map_texture(MaterialMap::Bump).is_some()
+			{
+				let tangents = compute_vertex_tangents(&positions, &normals, &texcoords, &indices);
+				self.buffer_tangents(context, &tangents);
+			}
+			self.mesh = MeshData::from_vertices(positions, texcoords, normals, indices);
 		} else {
 			/*
 				Manage Vertices for model
@@ -192,8 +236,11 @@ impl WebGl2WavefrontObject
 				self.vertex_buffer = context.create_buffer();
 				context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.vertex_buffer.as_ref());
 
-				let position_attribute_location = context.get_attrib_location(program.as_ref().unwrap(), "a_position") as u32;
-				context.vertex_attrib_pointer_with_i32(position_attribute_location, 3, WebGl2RenderingContext::FLOAT, false, 0, 0);	
+				let position_attribute_location = get_attribute_location(context, program.as_ref().unwrap(), "a_position");
+				if let Some(position_attribute_location) = position_attribute_location
+				{
+					context.vertex_attrib_pointer_with_i32(position_attribute_location, 3, WebGl2RenderingContext::FLOAT, false, 0, 0);	
+				}
 						
 				let vert_array = js_sys::Float32Array::view(&vertex_positions);
 				context.buffer_data_with_array_buffer_view
@@ -202,7 +249,10 @@ impl WebGl2WavefrontObject
 					&vert_array,
 					WebGl2RenderingContext::STATIC_DRAW,
 				);
-				context.enable_vertex_attrib_array(position_attribute_location);
+				if let Some(position_attribute_location) = position_attribute_location
+				{
+					context.enable_vertex_attrib_array(position_attribute_location);
+				}
 				rust_verbose(&("..Vertex data fully buffered."));
 			}
 
@@ -213,9 +263,11 @@ impl WebGl2WavefrontObject
 				rust_verbose(&("Starting to buffer color data... "));
 				self.color_buffer = context.create_buffer();
 				context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.color_buffer.as_ref());
-				let color_attribute_location = context.get_attrib_location(program.as_ref().unwrap(), "a_color") as u32;
-				context.vertex_attrib_pointer_with_i32(color_attribute_location, 4, WebGl2RenderingContext::FLOAT, false, 0, 0);
-				context.enable_vertex_attrib_array(color_attribute_location);
+				if let Some(color_attribute_location) = get_attribute_location(context, program.as_ref().unwrap(), "a_color")
+				{
+					context.vertex_attrib_pointer_with_i32(color_attribute_location, 4, WebGl2RenderingContext::FLOAT, false, 0, 0);
+					context.enable_vertex_attrib_array(color_attribute_location);
+				}
 		
 				//Currently junk colors. Only care about matching vertex count in sample cube
 				let mut rng = rand::rng();
@@ -255,20 +307,187 @@ impl WebGl2WavefrontObject
 			}
 			rust_verbose(&("..indice buffering complete."));
 
+			// Keep a CPU side copy of the buffered geometry for ray casting
+			let indices: Vec<u32> = vertex_indices.iter().map(|index| *index as u32).collect();
+			let normals = self.get_vertex_normals(&vertex_positions, &indices, &vertex_indices, false);
+			self.buffer_normals(context, &normals);
+			self.mesh = MeshData::from_vertices(vertex_positions, Vec::new(), normals, indices);
+
+		}
+		return Ok(());
+	}
+
+	/*
+	*
+	*	Take the supplied texture for the given material map from the cache, uploading it if no other
+	*	object has yet
+	*
+	*/
+	fn buffer_texture(&mut self, context: &WebGl2RenderingContext, textures: &mut TextureCache, map: MaterialMap, texture_name: &str) -> Result<WebGlTexture, String>
+	{
+		rust_verbose(&("Starting to buffer texture image ".to_owned() + texture_name + "... "));
+		context.active_texture(WebGl2RenderingContext::TEXTURE0 + map.unit());
+		let (texture, translucent) = textures.acquire(context, texture_name, self.material.get_sampler(map))?;
+		context.active_texture(WebGl2RenderingContext::TEXTURE0);
+		rust_verbose("...texture buffering complete.");
+
+		// An opacity map, or transparent pixels in the base color, make an otherwise opaque material blend
+		let blends = map == MaterialMap::Opacity || (map == MaterialMap::BaseColor && translucent);
+		if blends && self.material.alpha_mode == AlphaMode::Opaque
+		{
+			rust_verbose(&("Texture ".to_owned() + texture_name + " has transparent pixels, blending object " + self.obj.name.as_str()));
+			self.material.alpha_mode = AlphaMode::Blend;
+		}
+
+		return Ok(texture);
+	}
+
+	/*
+	*
+	*	Buffer one tangent and bitangent sign per vertex for the a_tangent attribute
+	*
+	*/
+	fn buffer_tangents(&mut self, context: &WebGl2RenderingContext, tangents: &[f32])
+	{
+		rust_verbose("Starting to buffer tangent data... ");
+		self.tangent_buffer = context.create_buffer();
+		context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.tangent_buffer.as_ref());
+		unsafe {
+			let tangent_array = js_sys::Float32Array::view(tangents);
+			context.buffer_data_with_array_buffer_view
+			(
+				WebGl2RenderingContext::ARRAY_BUFFER,
+				&tangent_array,
+				WebGl2RenderingContext::STATIC_DRAW,
+			);
+		}
+		rust_verbose("...tangent data buffering complete.");
+	}
+
+	/*
+	*
+	*	Buffer one normal per vertex for the a_normal attribute
+	*
+	*/
+	fn buffer_normals(&mut self, context: &WebGl2RenderingContext, normals: &[f32])
+	{
+		rust_verbose("Starting to buffer normal data... ");
+		self.normal_buffer = context.create_buffer();
+		context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.normal_buffer.as_ref());
+		unsafe {
+			let normal_array = js_sys::Float32Array::view(normals);
+			context.buffer_data_with_array_buffer_view
+			(
+				WebGl2RenderingContext::ARRAY_BUFFER,
+				&normal_array,
+				WebGl2RenderingContext::STATIC_DRAW,
+			);
+		}
+		rust_verbose("...normal data buffering complete.");
+	}
+
+	/*
+	*
+	*	Buffer the mesh's edges as line indices, for the wireframe render modes
+	*
+	*/
+	pub fn buffer_edges(&mut self, context: &WebGl2RenderingContext) -> Result<(), String>
+	{
+		rust_verbose("Starting to buffer edge indices... ");
+		let edges: Vec<u16> = self.mesh.get_edge_indices().iter().map(|index| *index as u16).collect();
+		let buffer = context.create_buffer().ok_or("failed to create edge index buffer")?;
+		context.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&buffer));
+		unsafe {
+			let edge_array = js_sys::Uint16Array::view(&edges);
+			context.buffer_data_with_array_buffer_view
+			(
+				WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+				&edge_array,
+				WebGl2RenderingContext::STATIC_DRAW,
+			);
+		}
+		self.edge_indices_size = edges.len();
+		self.edge_index_buffer = Some(buffer);
+		rust_verbose("...edge index buffering complete.");
+
+		return Ok(());
+	}
 
+	/*
+	*
+	*	Release every GPU resource held by the object. Attribute array state is left to the frame's draw
+	*	call, which sets it up for each object it draws
+	*
+	*/
+	pub fn cleanup(&mut self, context: &WebGl2RenderingContext, textures: &mut TextureCache)
+	{
+		context.delete_buffer(self.vertex_buffer.take().as_ref());
+		context.delete_buffer(self.vertex_and_texture_buffer.take().as_ref());
+		context.delete_buffer(self.vertex_index_buffer.take().as_ref());
+		context.delete_buffer(self.edge_index_buffer.take().as_ref());
+		context.delete_buffer(self.color_buffer.take().as_ref());
+		context.delete_buffer(self.normal_buffer.take().as_ref());
+		context.delete_buffer(self.tangent_buffer.take().as_ref());
+		for (_, texture) in self.map_textures.drain(..)
+		{
+			textures.release(context, &texture);
 		}
+		self.indices_size = 0;
+		self.edge_indices_size = 0;
+		self.mesh = MeshData::new();
+	}
+
+	pub fn get_name(&self) -> &str
+	{
+		return &self.obj.name;
+	}
+
+	pub fn get_map_texture(&self, map: MaterialMap) -> Option<&WebGlTexture>
+	{
+		return self.map_textures.iter().find(|(texture_map, _)| *texture_map == map).map(|(_, texture)| texture);
+	}
+
+	/*
+	*
+	*	Metallic and roughness maps bound to the same uploaded texture are treated as a glTF packed texture,
+	*	with roughness in the green channel and metallic in the blue
+	*
+	*/
+	pub fn is_metallic_roughness_packed(&self) -> bool
+	{
+		return shares_metallic_roughness_texture(&self.map_textures);
+	}
+
+	/*
+	*
+	*	Change how a material map is sampled, swapping in a texture sampled that way if one is loaded
+	*
+	*/
+	pub fn set_texture_sampler(&mut self, context: &WebGl2RenderingContext, textures: &mut TextureCache, map: MaterialMap, sampler: TextureSampler) -> Result<(), String>
+	{
+		self.material.samplers.insert(map, sampler);
+
+		if let Some(position) = self.map_textures.iter().position(|(texture_map, _)| *texture_map == map)
+		{
+			context.active_texture(WebGl2RenderingContext::TEXTURE0 + map.unit());
+			let texture = textures.resample(context, &self.map_textures[position].1, sampler);
+			context.active_texture(WebGl2RenderingContext::TEXTURE0);
+			self.map_textures[position].1 = texture?;
+		}
+
 		return Ok(());
 	}
 
-	pub fn cleanup(&mut self, context: &WebGl2RenderingContext)
+	pub fn get_vertex_count(&self) -> usize
 	{
-		context.disable_vertex_attrib_array(0); // a_position
-    	context.disable_vertex_attrib_array(1);
-		context.delete_buffer(self.vertex_buffer.as_ref());
-		context.delete_buffer(self.vertex_and_texture_buffer.as_ref());
-		context.delete_buffer(self.vertex_index_buffer.as_ref());
-		context.delete_buffer(self.color_buffer.as_ref());
+		return self.obj.vertices.len();
 	}
 }
 
-mod object_loader;
\ No newline at end of file
+mod object_loader;
+pub mod mesh;
+pub mod material;
+pub mod sampler;
+pub mod texture_source;
+pub mod texture_cache;
+pub mod compressed_texture;
\ No newline at end of file
@@ -1,33 +1,50 @@
 use crate::logger;
 use wavefront_obj::obj::*;
-use js_sys::*;
-use std::io::Cursor;
-use image::ImageReader;
+
+use crate::matrix_helper::normalize;
 
 use super::WebGl2WavefrontObject;
+use super::mesh::compute_vertex_normals;
+use super::material::MaterialMap;
+use super::texture_cache::TextureCache;
 
 impl WebGl2WavefrontObject
 {
-	pub(in super) fn get_material_name(&mut self) -> String
+	/*
+	*
+	*	The material the object's faces use. Objects are split so each holds a single usemtl block
+	*
+	*/
+	pub(in super) fn get_material_name(&self) -> Option<String>
 	{
-		return self.obj.geometry[0].material_name.clone().unwrap();
+		return self.obj.geometry.first().and_then(|geometry| geometry.material_name.clone());
 	}
 
-	pub(in super) fn get_texture_name(&mut self) -> Option<String>
+	/*
+	*
+	*	Find the supplied texture a material map refers to. Maps are matched by file name, ignoring any
+	*	directories in the MTL path. A material without any maps, as with models that come without an MTL,
+	*	takes a lone supplied texture as its base color
+	*
+	*/
+	pub(in super) fn get_texture_name(&self, textures: &TextureCache, map: MaterialMap) -> Option<String>
 	{
-		let mtl_name = self.get_material_name();
-
-		// loop through materials until you find the one
-		for material in self.mtls.clone().unwrap().materials
+		if let Some(map_file) = self.material.maps.get(&map)
 		{
-			if material.name == mtl_name
+			if let Some(name) = textures.find_source_name(map_file)
 			{
-				return material.diffuse_map;
-			} 
+				return Some(name);
+			}
+			logger::rust_warn(&("Texture ".to_owned() + map_file + " used by material " + &self.material.name + " was not supplied"));
+		}
+
+		if map == MaterialMap::BaseColor && self.material.maps.is_empty()
+		{
+			return textures.get_only_source_name();
 		}
 
 		return None;
-	}	
+	}
 
 	/*
 	*
@@ -178,69 +195,120 @@ impl WebGl2WavefrontObject
 
 	/*
 	*
-	*	Merge the vertex and texture position data so that it can be stored in a single OpenGL buffer
+	*	Fetch the vertex normal data as stored in the model. 
 	*
 	*/
-	pub(in super) fn merge_vertex_and_texture_positions(&mut self, vertex_positions: &Vec<f32>, vertex_indices: &Vec<u16>, texture_positions: &Vec<f32>, texture_indices: &Vec<u16>) -> Vec<f32>
+	pub(in super) fn get_normal_directions(&mut self) -> Vec<f32> 
 	{
-		let mut merged_vertex_and_texture_positions: Vec<f32> = Vec::new();
+		let mut normals_out: Vec<f32> = Vec::new();
 
-		for n in 0..vertex_indices.len()
+		for n in 0..self.obj.normals.len()
 		{
-			merged_vertex_and_texture_positions.push(vertex_positions[(vertex_indices[n] as usize * 3) + 0]);
-			merged_vertex_and_texture_positions.push(vertex_positions[(vertex_indices[n] as usize * 3) + 1]);
-			merged_vertex_and_texture_positions.push(vertex_positions[(vertex_indices[n] as usize * 3) + 2]);
-			merged_vertex_and_texture_positions.push(texture_positions[(texture_indices[n] as usize * 2) + 0]);
-			merged_vertex_and_texture_positions.push(1.0 - (texture_positions[(texture_indices[n] as usize * 2) + 1]));
+			normals_out.push(self.obj.normals[n].x as f32);
+			normals_out.push(self.obj.normals[n].y as f32);
+			normals_out.push(self.obj.normals[n].z as f32);
 		}
+		return normals_out;
+	}
 
-		self.log_merged_vertex_and_texture_positions(&merged_vertex_and_texture_positions);
+	/*
+	*
+	*	Fetch the normal index data as stored in the model. Empty unless every triangle corner has a normal
+	*	Note 1 - fetched in the order y, z, x to line up with the vertex indices
+	*
+	*/
+	pub(in super) fn get_normal_indices(&mut self) -> Vec<u16>
+	{
+		let mut shapes_out: Vec<u16> = Vec::new();
 
-		return merged_vertex_and_texture_positions;
+		for n in 0..self.obj.geometry[0].shapes.len()
+		{
+			let Primitive::Triangle(x, y, z) = self.obj.geometry[0].shapes[n].primitive else { continue };
+			match (y.2, z.2, x.2)
+			{
+				(Some(y), Some(z), Some(x)) =>
+				{
+					shapes_out.push(y as u16);
+					shapes_out.push(z as u16);
+					shapes_out.push(x as u16);
+				},
+				_ => return Vec::new()
+			};
+		}
+
+		return shapes_out;
 	}
 
 	/*
 	*
-	*	Convert the passed base64 image data into a raw u8 array of rbga values. 
+	*	Produce one normal per buffered vertex. When `per_corner` is set the buffer holds a vertex for every
+	*	triangle corner, otherwise vertices are shared and normals from the model are averaged onto them.
+	*	Models without normals for every corner have them computed from the geometry instead
 	*
 	*/
-	pub(in super) fn create_image_as_uint8_array(&mut self) -> Result<Uint8Array, String> 
+	pub(in super) fn get_vertex_normals(&mut self, positions: &[f32], indices: &[u32], vertex_indices: &[u16], per_corner: bool) -> Vec<f32>
 	{
-		//For now assuming that there is only ever 1 texture per object
-		let texture_b64 = self.textures.clone().unwrap().into_values().next().expect("Expected at least one texture");
-
-		// Convert base64 to a binary array
-		let bytes = base64::decode(texture_b64.clone()).map_err(|_| "Failed to decode base64")?;
+		let normal_directions = self.get_normal_directions();
+		let normal_indices = self.get_normal_indices();
 
-		let img1 = match ImageReader::new(Cursor::new(bytes)).with_guessed_format()
+		let valid = !normal_indices.is_empty()
+			&& normal_indices.len() == vertex_indices.len()
+			&& normal_indices.iter().all(|index| (*index as usize * 3 + 2) < normal_directions.len());
+		if !valid
 		{
-			Ok(img1) => img1,
-			Err(e) => return Err(e.to_string())
+			logger::rust_verbose(&("Object: ".to_owned() + self.obj.name.as_str() + " has no usable normals, computing them from the geometry"));
+			return compute_vertex_normals(positions, indices);
+		}
+
+		let normal = |n: usize| {
+			let index = normal_indices[n] as usize * 3;
+			[normal_directions[index], normal_directions[index + 1], normal_directions[index + 2]]
 		};
 
-		let img2 = match img1.decode()
+		if per_corner
 		{
-			Ok(img2) => img2,
-			Err(e) => return Err(e.to_string())
-		};
+			return (0..normal_indices.len()).flat_map(normal).collect();
+		}
 
-		let rgba_img = img2.to_rgba8();
+		let mut normals_out: Vec<f32> = vec![0.0; positions.len()];
+		for (n, vertex_index) in vertex_indices.iter().enumerate()
+		{
+			let index = *vertex_index as usize * 3;
+			let direction = normal(n);
+			normals_out[index] += direction[0];
+			normals_out[index + 1] += direction[1];
+			normals_out[index + 2] += direction[2];
+		}
+		for normal_out in normals_out.chunks_mut(3)
+		{
+			let normalized = normalize(&[normal_out[0], normal_out[1], normal_out[2]]);
+			normal_out.copy_from_slice(&normalized);
+		}
 
-		// Get image dimensions
-		let (width, height) = rgba_img.dimensions();
-		self.texture_height = height as i32;
-		self.texture_width = width as i32;
-		logger::rust_info(&("Image Size: ".to_owned() + width.to_string().as_str() + " x " + height.to_string().as_str()));
+		return normals_out;
+	}
 
-		// Access raw pixel data
-		let pixels = rgba_img.as_raw();
+	/*
+	*
+	*	Merge the vertex and texture position data so that it can be stored in a single OpenGL buffer
+	*
+	*/
+	pub(in super) fn merge_vertex_and_texture_positions(&mut self, vertex_positions: &Vec<f32>, vertex_indices: &Vec<u16>, texture_positions: &Vec<f32>, texture_indices: &Vec<u16>) -> Vec<f32>
+	{
+		let mut merged_vertex_and_texture_positions: Vec<f32> = Vec::new();
 
-		// Create a Blob from binary data
-		let array = js_sys::Uint8Array::from(pixels.as_slice());
+		for n in 0..vertex_indices.len()
+		{
+			merged_vertex_and_texture_positions.push(vertex_positions[(vertex_indices[n] as usize * 3) + 0]);
+			merged_vertex_and_texture_positions.push(vertex_positions[(vertex_indices[n] as usize * 3) + 1]);
+			merged_vertex_and_texture_positions.push(vertex_positions[(vertex_indices[n] as usize * 3) + 2]);
+			merged_vertex_and_texture_positions.push(texture_positions[(texture_indices[n] as usize * 2) + 0]);
+			merged_vertex_and_texture_positions.push(1.0 - (texture_positions[(texture_indices[n] as usize * 2) + 1]));
+		}
 
-		self.log_js_uint8_array(&array);
+		self.log_merged_vertex_and_texture_positions(&merged_vertex_and_texture_positions);
 
-		return Ok(array);	
+		return merged_vertex_and_texture_positions;
 	}
 
 	/*
@@ -299,15 +367,4 @@ impl WebGl2WavefrontObject
 			}
 		}
 	}
-
-	/*
-	*
-	*	Log the RGBA elements in image array
-	*
-	*/
-	pub(in super) fn log_js_uint8_array(&mut self, array: &js_sys::Uint8Array)
-	{
-		logger::rust_super_super_verbose(&("Loaded texure coordinates are: "));
-		logger::rust_super_super_verbose(&(array.to_string().as_string().unwrap()));
-	}
-}
\ No newline at end of file
+}
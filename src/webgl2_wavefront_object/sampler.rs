@@ -0,0 +1,327 @@
+use js_sys::Reflect;
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+// From EXT_texture_filter_anisotropic, which web-sys has no constants for
+const TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FF;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureWrap
+{
+	Repeat,
+	Clamp,
+	Mirror
+}
+
+impl TextureWrap
+{
+	pub fn from_str(wrap: &str) -> Result<Self, String>
+	{
+		match wrap
+		{
+			"repeat" => Ok(TextureWrap::Repeat),
+			"clamp" => Ok(TextureWrap::Clamp),
+			"mirror" => Ok(TextureWrap::Mirror),
+			_ => Err(format!("Unknown texture wrap {}, expected repeat, clamp or mirror", wrap))
+		}
+	}
+
+	fn gl(&self) -> i32
+	{
+		return match self
+		{
+			TextureWrap::Repeat => WebGl2RenderingContext::REPEAT,
+			TextureWrap::Clamp => WebGl2RenderingContext::CLAMP_TO_EDGE,
+			TextureWrap::Mirror => WebGl2RenderingContext::MIRRORED_REPEAT
+		} as i32;
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter
+{
+	Nearest,
+	Linear
+}
+
+impl TextureFilter
+{
+	pub fn from_str(filter: &str) -> Result<Self, String>
+	{
+		match filter
+		{
+			"nearest" => Ok(TextureFilter::Nearest),
+			"linear" => Ok(TextureFilter::Linear),
+			_ => Err(format!("Unknown texture filter {}, expected nearest or linear", filter))
+		}
+	}
+}
+
+/*
+*
+*	How a material map's texture is sampled. The minifying filter also picks between the two nearest mip
+*	levels the same way when mipmaps are on. Anisotropy above 1 needs EXT_texture_filter_anisotropic and is
+*	clamped to what the device supports
+*
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureSampler
+{
+	pub wrap_s: TextureWrap,
+	pub wrap_t: TextureWrap,
+	pub min_filter: TextureFilter,
+	pub mag_filter: TextureFilter,
+	pub mipmaps: bool,
+	pub anisotropy: f32
+}
+
+impl Default for TextureSampler
+{
+	fn default() -> Self
+	{
+		return Self
+		{
+			wrap_s: TextureWrap::Repeat,
+			wrap_t: TextureWrap::Repeat,
+			min_filter: TextureFilter::Linear,
+			mag_filter: TextureFilter::Linear,
+			mipmaps: true,
+			anisotropy: 1.0
+		};
+	}
+}
+
+/*
+*
+*	Sampler settings as given from JS, each None when left out
+*
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SamplerOptions
+{
+	pub wrap: Option<String>,
+	pub wrap_s: Option<String>,
+	pub wrap_t: Option<String>,
+	pub filter: Option<String>,
+	pub min_filter: Option<String>,
+	pub mag_filter: Option<String>,
+	pub mipmaps: Option<bool>,
+	pub anisotropy: Option<f64>
+}
+
+impl SamplerOptions
+{
+	pub fn from_js(options: &JsValue) -> Self
+	{
+		let get = |name: &str| Reflect::get(options, &JsValue::from_str(name)).unwrap_or(JsValue::UNDEFINED);
+
+		return Self
+		{
+			wrap: get("wrap").as_string(),
+			wrap_s: get("wrap_s").as_string(),
+			wrap_t: get("wrap_t").as_string(),
+			filter: get("filter").as_string(),
+			min_filter: get("min_filter").as_string(),
+			mag_filter: get("mag_filter").as_string(),
+			mipmaps: get("mipmaps").as_bool(),
+			anisotropy: get("anisotropy").as_f64()
+		};
+	}
+}
+
+impl TextureSampler
+{
+	/*
+	*
+	*	Read {wrap, wrap_s, wrap_t, min_filter, mag_filter, filter, mipmaps, anisotropy} over the current
+	*	settings, keeping any that aren't given
+	*
+	*/
+	pub fn from_js(options: &JsValue, current: TextureSampler) -> Result<Self, String>
+	{
+		return current.merge(&SamplerOptions::from_js(options));
+	}
+
+	/*
+	*
+	*	Apply the given options over these settings. The per axis wrap and min/mag filters win over the
+	*	shared wrap and filter
+	*
+	*/
+	pub fn merge(&self, options: &SamplerOptions) -> Result<Self, String>
+	{
+		let mut sampler = *self;
+
+		if let Some(wrap) = &options.wrap
+		{
+			sampler.wrap_s = TextureWrap::from_str(wrap)?;
+			sampler.wrap_t = sampler.wrap_s;
+		}
+		if let Some(wrap) = &options.wrap_s
+		{
+			sampler.wrap_s = TextureWrap::from_str(wrap)?;
+		}
+		if let Some(wrap) = &options.wrap_t
+		{
+			sampler.wrap_t = TextureWrap::from_str(wrap)?;
+		}
+		if let Some(filter) = &options.filter
+		{
+			sampler.min_filter = TextureFilter::from_str(filter)?;
+			sampler.mag_filter = sampler.min_filter;
+		}
+		if let Some(filter) = &options.min_filter
+		{
+			sampler.min_filter = TextureFilter::from_str(filter)?;
+		}
+		if let Some(filter) = &options.mag_filter
+		{
+			sampler.mag_filter = TextureFilter::from_str(filter)?;
+		}
+		if let Some(mipmaps) = options.mipmaps
+		{
+			sampler.mipmaps = mipmaps;
+		}
+		if let Some(anisotropy) = options.anisotropy
+		{
+			sampler.anisotropy = (anisotropy as f32).max(1.0);
+		}
+
+		return Ok(sampler);
+	}
+
+	/*
+	*
+	*	Set the parameters of the texture bound to TEXTURE_2D. Mip levels have to exist already when mipmaps are on
+	*
+	*/
+	pub fn apply(&self, context: &WebGl2RenderingContext)
+	{
+		let target = WebGl2RenderingContext::TEXTURE_2D;
+		let min_filter = match (self.min_filter, self.mipmaps)
+		{
+			(TextureFilter::Nearest, false) => WebGl2RenderingContext::NEAREST,
+			(TextureFilter::Linear, false) => WebGl2RenderingContext::LINEAR,
+			(TextureFilter::Nearest, true) => WebGl2RenderingContext::NEAREST_MIPMAP_NEAREST,
+			(TextureFilter::Linear, true) => WebGl2RenderingContext::LINEAR_MIPMAP_LINEAR
+		};
+		let mag_filter = match self.mag_filter
+		{
+			TextureFilter::Nearest => WebGl2RenderingContext::NEAREST,
+			TextureFilter::Linear => WebGl2RenderingContext::LINEAR
+		};
+
+		context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_S, self.wrap_s.gl());
+		context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_T, self.wrap_t.gl());
+		context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, min_filter as i32);
+		context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, mag_filter as i32);
+
+		// Setting 1 as well turns off anisotropy a texture had before
+		if matches!(context.get_extension("EXT_texture_filter_anisotropic"), Ok(Some(_)))
+		{
+			let max_anisotropy = context.get_parameter(MAX_TEXTURE_MAX_ANISOTROPY_EXT)
+				.ok()
+				.and_then(|value| value.as_f64())
+				.unwrap_or(1.0) as f32;
+			context.tex_parameterf(target, TEXTURE_MAX_ANISOTROPY_EXT, self.anisotropy.min(max_anisotropy));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn some(value: &str) -> Option<String>
+	{
+		return Some(value.to_string());
+	}
+
+	#[test]
+	fn wrap_and_filter_names_parse()
+	{
+		assert_eq!(TextureWrap::from_str("repeat"), Ok(TextureWrap::Repeat));
+		assert_eq!(TextureWrap::from_str("clamp"), Ok(TextureWrap::Clamp));
+		assert_eq!(TextureWrap::from_str("mirror"), Ok(TextureWrap::Mirror));
+		assert_eq!(TextureFilter::from_str("nearest"), Ok(TextureFilter::Nearest));
+		assert_eq!(TextureFilter::from_str("linear"), Ok(TextureFilter::Linear));
+	}
+
+	#[test]
+	fn unknown_wrap_and_filter_names_are_rejected()
+	{
+		assert!(TextureWrap::from_str("clamp_to_edge").unwrap_err().contains("clamp_to_edge"));
+		assert!(TextureWrap::from_str("Repeat").is_err());
+		assert!(TextureFilter::from_str("trilinear").unwrap_err().contains("trilinear"));
+		assert!(TextureFilter::from_str("").is_err());
+	}
+
+	#[test]
+	fn default_repeats_with_linear_mipmaps()
+	{
+		let sampler = TextureSampler::default();
+		assert_eq!(sampler.wrap_s, TextureWrap::Repeat);
+		assert_eq!(sampler.wrap_t, TextureWrap::Repeat);
+		assert_eq!(sampler.min_filter, TextureFilter::Linear);
+		assert_eq!(sampler.mag_filter, TextureFilter::Linear);
+		assert!(sampler.mipmaps);
+		assert_eq!(sampler.anisotropy, 1.0);
+	}
+
+	#[test]
+	fn empty_options_keep_the_current_settings()
+	{
+		let current = TextureSampler { wrap_s: TextureWrap::Mirror, min_filter: TextureFilter::Nearest, anisotropy: 4.0, ..TextureSampler::default() };
+		assert_eq!(current.merge(&SamplerOptions::default()), Ok(current));
+	}
+
+	#[test]
+	fn options_merge_onto_the_current_settings()
+	{
+		let current = TextureSampler { wrap_t: TextureWrap::Mirror, mipmaps: false, ..TextureSampler::default() };
+		let options = SamplerOptions { wrap_s: some("clamp"), mag_filter: some("nearest"), anisotropy: Some(8.0), ..SamplerOptions::default() };
+		let sampler = current.merge(&options).unwrap();
+
+		assert_eq!(sampler.wrap_s, TextureWrap::Clamp);
+		assert_eq!(sampler.wrap_t, TextureWrap::Mirror);
+		assert_eq!(sampler.min_filter, TextureFilter::Linear);
+		assert_eq!(sampler.mag_filter, TextureFilter::Nearest);
+		assert!(!sampler.mipmaps);
+		assert_eq!(sampler.anisotropy, 8.0);
+	}
+
+	#[test]
+	fn per_axis_options_win_over_shared_ones()
+	{
+		let options = SamplerOptions
+		{
+			wrap: some("clamp"),
+			wrap_t: some("mirror"),
+			filter: some("nearest"),
+			mag_filter: some("linear"),
+			..SamplerOptions::default()
+		};
+		let sampler = TextureSampler::default().merge(&options).unwrap();
+
+		assert_eq!(sampler.wrap_s, TextureWrap::Clamp);
+		assert_eq!(sampler.wrap_t, TextureWrap::Mirror);
+		assert_eq!(sampler.min_filter, TextureFilter::Nearest);
+		assert_eq!(sampler.mag_filter, TextureFilter::Linear);
+	}
+
+	#[test]
+	fn anisotropy_is_at_least_one()
+	{
+		let options = SamplerOptions { anisotropy: Some(0.25), ..SamplerOptions::default() };
+		assert_eq!(TextureSampler::default().merge(&options).unwrap().anisotropy, 1.0);
+	}
+
+	#[test]
+	fn a_bad_option_fails_the_whole_merge()
+	{
+		let options = SamplerOptions { wrap_s: some("clamp"), min_filter: some("cubic"), ..SamplerOptions::default() };
+		assert!(TextureSampler::default().merge(&options).is_err());
+	}
+}
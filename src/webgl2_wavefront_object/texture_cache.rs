@@ -0,0 +1,278 @@
+use js_sys::Function;
+use js_sys::Uint8Array;
+use std::collections::HashMap;
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGlTexture;
+
+use crate::image_decoder::decode_rgba8;
+use crate::ktx2::Ktx2;
+use crate::logger::*;
+
+use super::compressed_texture::*;
+use super::sampler::TextureSampler;
+use super::texture_source::TextureSource;
+
+struct CachedTexture
+{
+	name: String,
+	sampler: TextureSampler,
+	texture: WebGlTexture,
+	translucent: bool,
+	// The mip levels a KTX2 file came with, None for images and files that have them generated
+	fixed_levels: Option<usize>,
+	users: usize,
+	// Left over from sources that have since been replaced, so only kept until their users let go
+	stale: bool
+}
+
+/*
+*
+*	The supplied texture sources and the GPU textures made from them. Each file is uploaded once for every
+*	different way it is sampled and shared between the objects and material maps that use it, then deleted
+*	when the last of them releases it
+*
+*/
+pub struct TextureCache
+{
+	sources: HashMap<String, TextureSource>,
+	textures: Vec<CachedTexture>,
+	transcoder: Option<Function>,
+	// Queried the first time a KTX2 texture is uploaded
+	compressed_formats: Option<Vec<CompressedFormat>>
+}
+
+impl TextureCache
+{
+	pub fn new() -> Self
+	{
+		return Self
+		{
+			sources: HashMap::new(),
+			textures: Vec::new(),
+			transcoder: None,
+			compressed_formats: None
+		};
+	}
+
+	/*
+	*
+	*	Replace the supplied sources. Textures already uploaded stay alive for the objects using them, but
+	*	are never handed out again
+	*
+	*/
+	pub fn set_sources(&mut self, sources: Option<HashMap<String, TextureSource>>)
+	{
+		self.sources = sources.unwrap_or_default();
+		for cached in &mut self.textures
+		{
+			cached.stale = true;
+		}
+	}
+
+	/*
+	*
+	*	The JS function Basis Universal KTX2 textures are transcoded with, see upload_ktx2
+	*
+	*/
+	pub fn set_transcoder(&mut self, transcoder: Option<Function>)
+	{
+		self.transcoder = transcoder;
+	}
+
+	/*
+	*
+	*	The name of the supplied texture a material refers to, matched by file name ignoring directories
+	*
+	*/
+	pub fn find_source_name(&self, path: &str) -> Option<String>
+	{
+		let file_name = get_file_name(path);
+		return self.sources.keys().find(|name| get_file_name(name) == file_name).cloned();
+	}
+
+	pub fn get_only_source_name(&self) -> Option<String>
+	{
+		if self.sources.len() != 1
+		{
+			return None;
+		}
+
+		return self.sources.keys().next().cloned();
+	}
+
+	/*
+	*
+	*	Take a reference to the texture for a source sampled a given way, uploading it the first time. Also
+	*	returns whether any pixel is less than fully opaque
+	*
+	*/
+	pub fn acquire(&mut self, context: &WebGl2RenderingContext, name: &str, sampler: TextureSampler) -> Result<(WebGlTexture, bool), String>
+	{
+		if let Some(cached) = self.textures.iter_mut().find(|cached| !cached.stale && cached.name == name && cached.sampler == sampler)
+		{
+			cached.users += 1;
+			return Ok((cached.texture.clone(), cached.translucent));
+		}
+
+		let source = self.sources.get(name).ok_or_else(|| "Texture ".to_owned() + name + " was not supplied")?;
+		let texture = context.create_texture().ok_or("failed to create texture")?;
+		context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+		let uploaded = match source
+		{
+			TextureSource::Encoded(bytes) if Ktx2::is_ktx2(bytes) =>
+			{
+				let formats = self.compressed_formats.get_or_insert_with(|| get_supported_formats(context));
+				upload_ktx2(context, bytes, name, self.transcoder.as_ref(), formats).map(|(levels, alpha)| (alpha, levels))
+			},
+			_ => upload_texture_source(context, source, name).map(|translucent| (translucent, None))
+		};
+		let (translucent, fixed_levels) = match uploaded
+		{
+			Ok(uploaded) => uploaded,
+			Err(e) =>
+			{
+				context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+				context.delete_texture(Some(&texture));
+				return Err(e);
+			}
+		};
+		configure_texture(context, sampler, fixed_levels, false);
+		rust_verbose(&("Uploaded texture ".to_owned() + name + ", " + &(self.textures.len() + 1).to_string() + " textures cached"));
+
+		self.textures.push(CachedTexture { name: name.to_owned(), sampler, texture: texture.clone(), translucent, fixed_levels, users: 1, stale: false });
+
+		return Ok((texture, translucent));
+	}
+
+	/*
+	*
+	*	Swap a held texture for the same image sampled differently. A texture nothing else uses is changed
+	*	in place rather than uploaded again
+	*
+	*/
+	pub fn resample(&mut self, context: &WebGl2RenderingContext, texture: &WebGlTexture, sampler: TextureSampler) -> Result<WebGlTexture, String>
+	{
+		let position = self.textures.iter().position(|cached| cached.texture == *texture).ok_or("texture is not cached")?;
+
+		let cached = &mut self.textures[position];
+		if cached.users == 1
+		{
+			context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&cached.texture));
+			configure_texture(context, sampler, cached.fixed_levels, cached.sampler.mipmaps);
+			cached.sampler = sampler;
+			return Ok(cached.texture.clone());
+		}
+
+		let name = cached.name.clone();
+		let (texture, _) = self.acquire(context, &name, sampler)?;
+		self.textures[position].users -= 1;
+
+		return Ok(texture);
+	}
+
+	pub fn release(&mut self, context: &WebGl2RenderingContext, texture: &WebGlTexture)
+	{
+		if let Some(position) = self.textures.iter().position(|cached| cached.texture == *texture)
+		{
+			self.textures[position].users -= 1;
+			if self.textures[position].users == 0
+			{
+				let cached = self.textures.remove(position);
+				context.delete_texture(Some(&cached.texture));
+			}
+		}
+	}
+}
+
+/*
+*
+*	Set the bound texture's sampling, generating mip levels it doesn't have yet. Files that bring their own
+*	levels can't have more generated, so a single level one is sampled without mipmaps
+*
+*/
+fn configure_texture(context: &WebGl2RenderingContext, sampler: TextureSampler, fixed_levels: Option<usize>, has_mipmaps: bool)
+{
+	let mut applied = sampler;
+	match fixed_levels
+	{
+		None if sampler.mipmaps && !has_mipmaps => context.generate_mipmap(WebGl2RenderingContext::TEXTURE_2D),
+		None => (),
+		Some(levels) => applied.mipmaps = sampler.mipmaps && levels > 1
+	}
+	applied.apply(context);
+}
+
+/*
+*
+*	Fill the bound texture from its source, returning whether any pixel is less than fully opaque. Images
+*	the browser decoded are uploaded directly and can't be inspected, so they count as opaque
+*
+*/
+fn upload_texture_source(context: &WebGl2RenderingContext, source: &TextureSource, texture_name: &str) -> Result<bool, String>
+{
+	let internal_format = WebGl2RenderingContext::RGBA8 as i32;
+	let result = match source
+	{
+		TextureSource::Encoded(bytes) =>
+		{
+			let (width, height, image, translucent) = create_image_as_uint8_array(texture_name, bytes)?;
+			context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_js_u8_array
+			(
+				WebGl2RenderingContext::TEXTURE_2D,
+				0,
+				internal_format,
+				width,
+				height,
+				0,
+				WebGl2RenderingContext::RGBA, // format
+				WebGl2RenderingContext::UNSIGNED_BYTE, // type
+				Some(&image)
+			)
+			.map(|_| translucent)
+		},
+		TextureSource::Bitmap(bitmap) => context
+			.tex_image_2d_with_u32_and_u32_and_image_bitmap(WebGl2RenderingContext::TEXTURE_2D, 0, internal_format, WebGl2RenderingContext::RGBA, WebGl2RenderingContext::UNSIGNED_BYTE, bitmap)
+			.map(|_| false),
+		TextureSource::Image(image) => context
+			.tex_image_2d_with_u32_and_u32_and_html_image_element(WebGl2RenderingContext::TEXTURE_2D, 0, internal_format, WebGl2RenderingContext::RGBA, WebGl2RenderingContext::UNSIGNED_BYTE, image)
+			.map(|_| false)
+	};
+
+	if let Some((width, height)) = source.get_browser_size()
+	{
+		rust_info(&("Image Size: ".to_owned() + width.to_string().as_str() + " x " + height.to_string().as_str()));
+	}
+
+	return result.map_err(|e| "failed to send image data to texture buffer: ".to_owned() + &e.as_string().unwrap_or_default());
+}
+
+/*
+*
+*	Decode the passed image file into its size, a raw u8 array of rbga values, and whether any pixel is
+*	less than fully opaque
+*
+*/
+fn create_image_as_uint8_array(texture_name: &str, bytes: &[u8]) -> Result<(i32, i32, Uint8Array, bool), String>
+{
+	let rgba_img = decode_rgba8(bytes, Some(texture_name))
+		.map_err(|e| "Texture ".to_owned() + texture_name + ": " + &e.to_string())?;
+
+	// Get image dimensions
+	let (width, height) = rgba_img.dimensions();
+	rust_info(&("Image Size: ".to_owned() + width.to_string().as_str() + " x " + height.to_string().as_str()));
+
+	// Access raw pixel data
+	let pixels = rgba_img.as_raw();
+
+	let translucent = pixels.chunks_exact(4).any(|pixel| pixel[3] < 255);
+
+	let array = Uint8Array::from(pixels.as_slice());
+	rust_super_super_verbose(&("Loaded texture pixels are: ".to_owned() + &array.to_string().as_string().unwrap_or_default()));
+
+	return Ok((width as i32, height as i32, array, translucent));
+}
+
+fn get_file_name(path: &str) -> &str
+{
+	return path.rsplit(['/', '\\']).next().unwrap_or(path);
+}
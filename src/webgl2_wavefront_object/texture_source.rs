@@ -0,0 +1,129 @@
+use base64::Engine;
+use js_sys::Array;
+use js_sys::ArrayBuffer;
+use js_sys::Map;
+use js_sys::Uint8Array;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::JsValue;
+use web_sys::Blob;
+use web_sys::HtmlImageElement;
+use web_sys::ImageBitmap;
+
+use crate::logger::*;
+
+/*
+*
+*	A texture as supplied in the "textures" resource map. Encoded files are decoded by the image crate, so
+*	also work headless, or read as KTX2 containers. Images the browser has already decoded are uploaded to
+*	the GPU as they are
+*
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureSource
+{
+	Encoded(Rc<[u8]>),
+	Bitmap(ImageBitmap),
+	Image(HtmlImageElement)
+}
+
+impl TextureSource
+{
+	/*
+	*
+	*	Accepts a base64 string, Uint8Array or ArrayBuffer of an encoded image, an ImageBitmap or a loaded
+	*	HTMLImageElement. Reading a Blob is asynchronous, so one has to be turned into an ArrayBuffer with
+	*	blob.arrayBuffer(), or an ImageBitmap with createImageBitmap(blob), before it is passed in
+	*
+	*/
+	pub fn from_js(value: &JsValue) -> Result<Self, String>
+	{
+		if let Some(base64) = value.as_string()
+		{
+			let bytes = base64::engine::general_purpose::STANDARD
+				.decode(base64.trim())
+				.map_err(|e| "failed to decode base64: ".to_owned() + &e.to_string())?;
+			return Ok(TextureSource::Encoded(bytes.into()));
+		}
+		if let Some(array) = value.dyn_ref::<Uint8Array>()
+		{
+			return Ok(TextureSource::Encoded(array.to_vec().into()));
+		}
+		if let Some(buffer) = value.dyn_ref::<ArrayBuffer>()
+		{
+			return Ok(TextureSource::Encoded(Uint8Array::new(buffer).to_vec().into()));
+		}
+		if let Some(bitmap) = value.dyn_ref::<ImageBitmap>()
+		{
+			return Ok(TextureSource::Bitmap(bitmap.clone()));
+		}
+		if let Some(image) = value.dyn_ref::<HtmlImageElement>()
+		{
+			if !image.complete() || image.natural_width() == 0
+			{
+				return Err(String::from("image element has not finished loading"));
+			}
+			return Ok(TextureSource::Image(image.clone()));
+		}
+		if value.is_instance_of::<Blob>()
+		{
+			return Err(String::from("Blobs are read asynchronously, pass await blob.arrayBuffer() or await createImageBitmap(blob) instead"));
+		}
+
+		return Err(String::from("expected a base64 string, Uint8Array, ArrayBuffer, ImageBitmap or HTMLImageElement"));
+	}
+
+	/*
+	*
+	*	The size of a source the browser has decoded, None for encoded bytes
+	*
+	*/
+	pub fn get_browser_size(&self) -> Option<(u32, u32)>
+	{
+		return match self
+		{
+			TextureSource::Encoded(_) => None,
+			TextureSource::Bitmap(bitmap) => Some((bitmap.width(), bitmap.height())),
+			TextureSource::Image(image) => Some((image.natural_width(), image.natural_height()))
+		};
+	}
+}
+
+/*
+*
+*	Read the "textures" map of the resources, skipping with a warning any entry that can't be used
+*
+*/
+pub fn get_texture_sources(resources: &Map) -> Option<HashMap<String, TextureSource>>
+{
+	let textures = resources.get(&JsValue::from_str("textures"));
+	if !textures.is_instance_of::<Map>()
+	{
+		return None;
+	}
+
+	let mut sources: HashMap<String, TextureSource> = HashMap::new();
+	for entry in Array::from(&Map::from(textures).entries()).iter()
+	{
+		let pair = Array::from(&entry);
+		let name = match pair.get(0).as_string()
+		{
+			Some(name) => name,
+			None => continue
+		};
+
+		match TextureSource::from_js(&pair.get(1))
+		{
+			Ok(source) => { sources.insert(name, source); },
+			Err(e) => rust_warn(&("Skipping texture ".to_owned() + &name + ": " + &e))
+		}
+	}
+
+	if sources.is_empty()
+	{
+		return None;
+	}
+
+	return Some(sources);
+}